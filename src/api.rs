@@ -1,14 +1,34 @@
+use std::future::Future;
+use std::pin::Pin;
+/// Something that can answer "did this user really join the server with this server id?".
+pub trait SessionBackend: Send + Sync {
+    fn check_session<'a>(
+        &'a self,
+        name: &'a str,
+        server_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<bool>> + Send + 'a>>;
+}
+/// Session backend speaking the legacy `checkserver.jsp` protocol.
 pub struct APIHelper {
-
+    session_server: String,
 }
 impl APIHelper {
-    pub async fn authenticate_user(name: &str, server_id: &str) -> anyhow::Result<bool> {
-        let mut builder = reqwest::Client::builder();
-        let url = format!("http://session.minecraft.net/game/checkserver.jsp?user={user}&serverId={id}", user = name, id = server_id);
-        log::info!("Url: {:?}", url);
-        let resp = builder.build()?.get(&url).header("Host", "session.minecraft.net").header("Connection", "close").send().await?;
-        log::info!("got");
-        match resp.text().await?.as_str() {
+    pub fn new(session_server: &str) -> Self {
+        Self {
+            session_server: session_server.to_string(),
+        }
+    }
+    pub async fn authenticate_user(&self, name: &str, server_id: &str) -> anyhow::Result<bool> {
+        let builder = reqwest::Client::builder();
+        log::debug!("Checking session for {} against {}", name, self.session_server);
+        let resp = builder
+            .build()?
+            .get(&self.session_server)
+            .query(&[("user", name), ("serverId", server_id)])
+            .header("Connection", "close")
+            .send()
+            .await?;
+        match resp.text().await?.trim() {
             "YES" => {
                 return Ok(true);
             }
@@ -17,4 +37,41 @@ impl APIHelper {
             }
         }
     }
-}
\ No newline at end of file
+}
+impl SessionBackend for APIHelper {
+    fn check_session<'a>(
+        &'a self,
+        name: &'a str,
+        server_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<bool>> + Send + 'a>> {
+        Box::pin(self.authenticate_user(name, server_id))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    async fn stand_in_server(answer: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("http://{}/game/checkserver.jsp", addr)
+    }
+    #[tokio::test]
+    async fn session_check() {
+        let helper = APIHelper::new(&stand_in_server("YES").await);
+        assert!(helper.check_session("Notch", "abcdef").await.unwrap());
+        let helper = APIHelper::new(&stand_in_server("NO").await);
+        assert!(!helper.check_session("Notch", "abcdef").await.unwrap());
+    }
+}
@@ -1,4 +1,5 @@
 use once_cell::sync::Lazy;
+use std::sync::Mutex;
 #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
 struct SerializeOPS {
     ops: Vec<String>,
@@ -10,16 +11,65 @@ pub struct ServerConfig {
     pub server_name: String,
     pub server_motd: String,
     pub max_players: u32,
+    #[serde(default = "default_server_full_message")]
+    pub server_full_message: String,
+    #[serde(default)]
+    pub online_mode: bool,
+    #[serde(default = "default_session_server")]
+    pub session_server: String,
     pub level_name: String,
     pub chunk_distance: i32,
+    #[serde(default = "default_chunk_unload_grace_period")]
+    pub chunk_unload_grace_period: u128,
+    #[serde(default = "default_max_loaded_chunks")]
+    pub max_loaded_chunks: usize,
+    #[serde(default = "default_chunk_workers")]
+    pub chunk_workers: usize,
+    #[serde(default = "default_chunk_sends_per_tick")]
+    pub chunk_sends_per_tick: usize,
     pub chunk_generator: String,
+    #[serde(default = "default_flat_preset")]
+    pub flat_preset: String,
     pub tps: i32,
     pub world_seed: Option<u64>,
+    #[serde(default)]
+    pub worlds: Vec<WorldConfig>,
     pub autosave_interval: i64,
     pub logging: LoggingConfig,
     pub experimental: ExperimentalConfig,
     // generic configuration, max players etc
 }
+fn default_server_full_message() -> String {
+    "The server is full!".to_string()
+}
+fn default_flat_preset() -> String {
+    crate::world::chunks::FlatChunkGenerator::DEFAULT_PRESET.to_string()
+}
+fn default_chunk_unload_grace_period() -> u128 {
+    600
+}
+fn default_max_loaded_chunks() -> usize {
+    4096
+}
+fn default_chunk_workers() -> usize {
+    2
+}
+fn default_chunk_sends_per_tick() -> usize {
+    4
+}
+fn default_session_server() -> String {
+    "http://session.minecraft.net/game/checkserver.jsp".to_string()
+}
+/// A world loaded next to `level_name`.
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct WorldConfig {
+    pub name: String,
+    /// Defaults to the main world's generator.
+    pub chunk_generator: Option<String>,
+    pub seed: Option<u64>,
+    #[serde(default = "default_flat_preset")]
+    pub flat_preset: String,
+}
 #[derive(serde_derive::Deserialize, Debug)]
 pub struct ExperimentalConfig {
     pub async_chat: bool,
@@ -43,20 +93,44 @@ listen_port = 25565
 # Server name (unused)
 server_name = "Hello!"
 
-# Server MOTD (unused)
+# Server MOTD, shown in the multiplayer server list
 server_motd = "there!"
 
-# Max players.
+# Max players, also shown in the multiplayer server list
 max_players = 32
 
+# Disconnect reason given to players joining a full server. Operators bypass the player limit.
+server_full_message = "The server is full!"
+
+# Online mode, verifies players against the session server
+online_mode = false
+
+# Session server used to verify players in online mode
+session_server = "http://session.minecraft.net/game/checkserver.jsp"
+
 # World file location. Minecraft region file format.
 level_name = "world"
 
 # Player chunk load distance
 chunk_distance = 8
 
-# Chunk generator, can be: (flat, noise, mountain). Noise is quite slow (working on it). CURRENTLY HAS NO EFFECT.
-chunk_generator = "noise"
+# Ticks a chunk must be out of every player's view before it is saved and unloaded
+chunk_unload_grace_period = 600
+
+# Max chunks kept in memory. Past this, the longest idle chunks are unloaded early. Spawn chunks are always kept.
+max_loaded_chunks = 4096
+
+# Threads per world that read and generate chunks, so players moving into new terrain don't stall the server
+chunk_workers = 2
+
+# Chunks sent to each player per tick, nearest first, so moving into new terrain doesn't flood their connection
+chunk_sends_per_tick = 4
+
+# Chunk generator for new worlds, can be: (flat, noise, mountain, vanilla). Noise is quite slow (working on it). Vanilla is a port of the Beta 1.7.3 generator, but it has not been checked against worlds from a real server. Existing worlds keep the generator they were made with.
+chunk_generator = "mountain"
+
+# Layers for the flat generator, block ids from the bottom up. Prefix a layer with <count>x to repeat it.
+flat_preset = "7,31x3,2"
 
 # Server TPS (Ticks Per Second), probably shouldn't change it. But who's stopping you?
 tps = 20
@@ -67,6 +141,13 @@ tps = 20
 # Autosave interval (in ticks)
 autosave_interval = 1200
 
+# Extra worlds loaded next to the main one, each in its own directory. Players move between them with /world <name>.
+# Generator and seed work like chunk_generator and world_seed above.
+# [[worlds]]
+# name = "build"
+# chunk_generator = "flat"
+# seed = 420
+
 # Logging options
 
 [logging]
@@ -173,3 +254,216 @@ pub fn remove_op(username: &str) {
     config.ops.retain(|name| name != username);
     std::fs::write("ops.toml", toml::to_string(&config).unwrap()).unwrap();
 }
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone)]
+pub struct SerializeWhitelist {
+    pub enabled: bool,
+    pub players: Vec<String>,
+}
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone)]
+struct SerializeBannedPlayers {
+    players: Vec<String>,
+}
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone)]
+struct SerializeBannedIPs {
+    ips: Vec<String>,
+}
+const WHITELIST_FILE: &str = "whitelist.toml";
+const BANNED_PLAYERS_FILE: &str = "banned-players.toml";
+const BANNED_IPS_FILE: &str = "banned-ips.toml";
+// The lists are checked on every login, so they are read from disk once and
+// then kept here. Commands that change a list write the file and the cache together.
+static WHITELIST: Lazy<Mutex<Option<SerializeWhitelist>>> = Lazy::new(|| Mutex::new(None));
+static BANNED_PLAYERS: Lazy<Mutex<Option<SerializeBannedPlayers>>> = Lazy::new(|| Mutex::new(None));
+static BANNED_IPS: Lazy<Mutex<Option<SerializeBannedIPs>>> = Lazy::new(|| Mutex::new(None));
+fn read_list_file<T: serde::de::DeserializeOwned>(path: &str, default: &str, name: &str) -> anyhow::Result<T> {
+    let file = if let Ok(f) = std::fs::read_to_string(path) {
+        f
+    } else {
+        log::info!("Generating {} file.", name);
+        std::fs::write(path, default)
+            .map_err(|e| anyhow::anyhow!("could not create {}: {}", path, e))?;
+        default.to_string()
+    };
+    toml::from_str(&file).map_err(|e| anyhow::anyhow!("invalid {} file {}: {}", name, path, e))
+}
+fn write_list_file<T: serde::Serialize>(path: &str, list: &T) -> anyhow::Result<()> {
+    let contents = toml::to_string(list)?;
+    std::fs::write(path, contents).map_err(|e| anyhow::anyhow!("could not write {}: {}", path, e))?;
+    Ok(())
+}
+/// Returns the cached list, reading it from disk if it has not been loaded yet.
+/// A file that fails to load is not cached, so fixing it takes effect on the next read.
+fn cached_list<T: serde::de::DeserializeOwned + Clone>(
+    cache: &Mutex<Option<T>>,
+    path: &str,
+    default: &str,
+    name: &str,
+) -> anyhow::Result<T> {
+    let mut cache = cache.lock().unwrap();
+    if let Some(list) = cache.as_ref() {
+        return Ok(list.clone());
+    }
+    let list: T = read_list_file(path, default, name)?;
+    *cache = Some(list.clone());
+    Ok(list)
+}
+fn store_list<T: serde::Serialize>(cache: &Mutex<Option<T>>, path: &str, list: T) -> anyhow::Result<()> {
+    write_list_file(path, &list)?;
+    *cache.lock().unwrap() = Some(list);
+    Ok(())
+}
+pub fn get_whitelist() -> anyhow::Result<SerializeWhitelist> {
+    cached_list(&WHITELIST, WHITELIST_FILE, "enabled = false\nplayers = []", "whitelist")
+}
+pub fn set_whitelist_enabled(enabled: bool) -> anyhow::Result<()> {
+    let mut config = get_whitelist()?;
+    config.enabled = enabled;
+    store_list(&WHITELIST, WHITELIST_FILE, config)
+}
+pub fn add_to_whitelist(username: &str) -> anyhow::Result<()> {
+    let mut config = get_whitelist()?;
+    let username = username.to_lowercase();
+    if !config.players.contains(&username) {
+        config.players.push(username);
+        store_list(&WHITELIST, WHITELIST_FILE, config)?;
+    }
+    Ok(())
+}
+pub fn remove_from_whitelist(username: &str) -> anyhow::Result<()> {
+    let mut config = get_whitelist()?;
+    let username = username.to_lowercase();
+    config.players.retain(|name| name != &username);
+    store_list(&WHITELIST, WHITELIST_FILE, config)
+}
+pub fn get_banned_players() -> anyhow::Result<Vec<String>> {
+    let config = cached_list(&BANNED_PLAYERS, BANNED_PLAYERS_FILE, "players = []", "banned players")?;
+    Ok(config.players)
+}
+pub fn add_banned_player(username: &str) -> anyhow::Result<()> {
+    let mut players = get_banned_players()?;
+    let username = username.to_lowercase();
+    if !players.contains(&username) {
+        players.push(username);
+        store_list(&BANNED_PLAYERS, BANNED_PLAYERS_FILE, SerializeBannedPlayers { players })?;
+    }
+    Ok(())
+}
+/// Returns true if the player was banned.
+pub fn remove_banned_player(username: &str) -> anyhow::Result<bool> {
+    let mut players = get_banned_players()?;
+    let username = username.to_lowercase();
+    let len = players.len();
+    players.retain(|name| name != &username);
+    let removed = players.len() != len;
+    store_list(&BANNED_PLAYERS, BANNED_PLAYERS_FILE, SerializeBannedPlayers { players })?;
+    Ok(removed)
+}
+pub fn get_banned_ips() -> anyhow::Result<Vec<String>> {
+    let config = cached_list(&BANNED_IPS, BANNED_IPS_FILE, "ips = []", "banned IPs")?;
+    Ok(config.ips)
+}
+pub fn add_banned_ip(ip: &str) -> anyhow::Result<()> {
+    let mut ips = get_banned_ips()?;
+    if !ips.iter().any(|banned| banned == ip) {
+        ips.push(ip.to_string());
+        store_list(&BANNED_IPS, BANNED_IPS_FILE, SerializeBannedIPs { ips })?;
+    }
+    Ok(())
+}
+/// Returns true if the IP was banned.
+pub fn remove_banned_ip(ip: &str) -> anyhow::Result<bool> {
+    let mut ips = get_banned_ips()?;
+    let len = ips.len();
+    ips.retain(|banned| banned != ip);
+    let removed = ips.len() != len;
+    store_list(&BANNED_IPS, BANNED_IPS_FILE, SerializeBannedIPs { ips })?;
+    Ok(removed)
+}
+/// Checks the ban lists and whitelist, returning the disconnect reason if the player may not join.
+pub fn check_access(username: &str, addr: &std::net::SocketAddr) -> anyhow::Result<Option<String>> {
+    Ok(refusal(
+        username,
+        &addr.ip().to_string(),
+        &get_whitelist()?,
+        &get_banned_players()?,
+        &get_banned_ips()?,
+    ))
+}
+fn refusal(
+    username: &str,
+    ip: &str,
+    whitelist: &SerializeWhitelist,
+    banned_players: &[String],
+    banned_ips: &[String],
+) -> Option<String> {
+    if banned_ips.iter().any(|banned| banned == ip) {
+        return Some("Your IP address is banned from this server!".to_string());
+    }
+    let username = username.to_lowercase();
+    if banned_players.contains(&username) {
+        return Some("You are banned from this server!".to_string());
+    }
+    if whitelist.enabled && !whitelist.players.contains(&username) {
+        return Some("You are not white-listed on this server!".to_string());
+    }
+    None
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("exo_{}_{}.toml", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+    #[test]
+    fn cached_list_round_trip() {
+        let path = temp_file("banned_players");
+        let cache = Mutex::new(None);
+        let list: SerializeBannedPlayers = cached_list(&cache, &path, "players = []", "banned players").unwrap();
+        assert!(list.players.is_empty());
+        let players = vec!["notch".to_string()];
+        store_list(&cache, &path, SerializeBannedPlayers { players: players.clone() }).unwrap();
+        // Written through to disk, so a fresh cache reads the same list back.
+        let fresh = Mutex::new(None);
+        let read: SerializeBannedPlayers = cached_list(&fresh, &path, "players = []", "banned players").unwrap();
+        assert_eq!(read.players, players);
+        // Later reads come from the cache, not the file.
+        std::fs::write(&path, "players = [\"jeb_\"]").unwrap();
+        assert_eq!(cached_list(&cache, &path, "players = []", "banned players").unwrap().players, players);
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn invalid_list_file_is_an_error_and_not_cached() {
+        let path = temp_file("banned_ips");
+        std::fs::write(&path, "ips = [").unwrap();
+        let cache: Mutex<Option<SerializeBannedIPs>> = Mutex::new(None);
+        assert!(cached_list(&cache, &path, "ips = []", "banned IPs").is_err());
+        assert!(cache.lock().unwrap().is_none());
+        std::fs::write(&path, "ips = [\"10.0.0.1\"]").unwrap();
+        assert_eq!(cached_list(&cache, &path, "ips = []", "banned IPs").unwrap().ips, vec!["10.0.0.1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn refuses_banned_and_unlisted_players() {
+        let mut whitelist = SerializeWhitelist { enabled: false, players: vec!["notch".to_string()] };
+        let banned_players = vec!["griefer".to_string()];
+        let banned_ips = vec!["10.0.0.1".to_string()];
+        let check = |name: &str, ip: &str, whitelist: &SerializeWhitelist| {
+            refusal(name, ip, whitelist, &banned_players, &banned_ips)
+        };
+        assert_eq!(check("Notch", "10.0.0.2", &whitelist), None);
+        assert_eq!(check("Steve", "10.0.0.2", &whitelist), None);
+        assert_eq!(
+            check("Notch", "10.0.0.1", &whitelist),
+            Some("Your IP address is banned from this server!".to_string())
+        );
+        assert_eq!(check("Griefer", "10.0.0.2", &whitelist), Some("You are banned from this server!".to_string()));
+        whitelist.enabled = true;
+        assert_eq!(check("Notch", "10.0.0.2", &whitelist), None);
+        assert_eq!(
+            check("Steve", "10.0.0.2", &whitelist),
+            Some("You are not white-listed on this server!".to_string())
+        );
+    }
+}
@@ -8,12 +8,17 @@ use crate::objects::Objects;
 use crate::server::Server;
 use crate::systems::Systems;
 use crate::world::mcregion::MCRegionLoader;
+use crate::world::chunks::SaveSummary;
+use crate::world::saver::SaveResult;
 //pub mod aa_bounding_box;
 use items::*;
 pub mod entities;
 pub mod events;
+pub mod fluids;
 pub mod gamerule;
 pub mod items;
+pub mod portals;
+pub mod pregen;
 use entities::*;
 use events::*;
 use flume::{Receiver, Sender};
@@ -21,7 +26,7 @@ use once_cell::sync::Lazy;
 use std::any::Any;
 use std::cell::RefCell;
 use std::cell::{Ref, RefMut};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -432,9 +437,37 @@ impl ChunkCoords {
     /// Chunk coordinates from a position.
     pub fn from_pos(position: &Position) -> Self {
         Self {
-            x: (position.x / 16.0) as i32,
-            z: (position.z / 16.0) as i32,
+            x: (position.x.floor() as i32) >> 4,
+            z: (position.z.floor() as i32) >> 4,
+        }
+    }
+    /// Every chunk within `radius` of this one, walking outwards ring by ring.
+    pub fn spiral(&self, radius: i32) -> Vec<ChunkCoords> {
+        let mut chunks = vec![*self];
+        for ring in 1..=radius {
+            // Start at the ring's corner and walk its four sides.
+            let (mut x, mut z) = (-ring, -ring);
+            for (dx, dz) in [(1, 0), (0, 1), (-1, 0), (0, -1)].iter() {
+                for _ in 0..ring * 2 {
+                    chunks.push(ChunkCoords { x: self.x + x, z: self.z + z });
+                    x += dx;
+                    z += dz;
+                }
+            }
         }
+        chunks
+    }
+    /// Whether this chunk is within `distance` chunks of `center` on both axes.
+    pub fn in_view(&self, center: &ChunkCoords, distance: i32) -> bool {
+        (self.x - center.x).abs() <= distance && (self.z - center.z).abs() <= distance
+    }
+    /// The chunks in view of `center` that aren't in `loaded`, nearest first.
+    pub fn send_order(center: &ChunkCoords, distance: i32, loaded: &[ChunkCoords]) -> VecDeque<ChunkCoords> {
+        center
+            .spiral(distance)
+            .into_iter()
+            .filter(|coords| !loaded.contains(coords))
+            .collect()
     }
 }
 #[derive(Clone, Debug)]
@@ -474,6 +507,8 @@ impl PlayerRef {
     pub fn teleport(&self, game: &mut Game, position: &Position) {
         self.set_position(*position);
         self.check_chunks(game);
+        // The chunks right around the player should arrive before the player does.
+        self.send_queued_chunks(game);
         let pos = position;
         let packet = ServerPacket::PlayerPositionAndLook {
             x: pos.x,
@@ -580,6 +615,15 @@ impl PlayerRef {
     pub fn get_world(&self) -> i8 {
         self.player.borrow().world.clone()
     }
+    /// How many chunks around the player are sent, after the server's cap.
+    pub fn get_view_distance(&self) -> i32 {
+        self.player.borrow().view_distance()
+    }
+    /// Change the player's view distance, unloading chunks that fall outside it straight away.
+    pub fn set_view_distance(&self, game: &mut Game, distance: i32) {
+        self.player.borrow_mut().view_distance = distance;
+        self.check_chunks(game);
+    }
     /// Get the player's username.
     pub fn get_username(&self) -> String {
         self.player.borrow().username.clone()
@@ -605,6 +649,10 @@ impl PlayerRef {
     pub fn get_id(&self) -> EntityID {
         self.player.borrow().id.clone()
     }
+    /// Get the player's socket address.
+    pub fn get_addr(&self) -> SocketAddr {
+        self.player.borrow().socket_addr.clone()
+    }
     /// Disconnect the player,
     pub fn disconnect(&self, reason: String) {
         self.player.borrow_mut().disconnect(reason);
@@ -761,17 +809,17 @@ impl PlayerRef {
         player.last_inventory = player.inventory.clone();
         player.inv_changed = false;
     }
-    /// Check chunks to load/unload
+    /// Unload the chunks that left the view and queue the ones that came into it, nearest first.
+    /// Queued chunks that leave the view again before they're sent are dropped.
     pub fn check_chunks(&self, game: &mut Game) {
         let mut cl = self.player.borrow_mut();
         // Chunk check
         cl.pos_changed = false;
-        let pos = cl.position.clone();
+        let center = ChunkCoords::from_pos(&cl.position);
+        let distance = cl.view_distance();
         let mut packets = vec![];
         cl.loaded_chunks.retain(|chunk| {
-            if chunk.distance(&ChunkCoords::from_pos(&pos))
-                > CONFIGURATION.chunk_distance as f64 * 1.5
-            {
+            if !chunk.in_view(&center, distance) {
                 if CONFIGURATION.logging.chunk_unload {
                     log::info!("Unloading chunk at ({}, {})", chunk.x, chunk.z);
                 }
@@ -788,62 +836,26 @@ impl PlayerRef {
         for packet in packets {
             cl.write(packet);
         }
-        for x in -CONFIGURATION.chunk_distance..CONFIGURATION.chunk_distance {
-            for z in -CONFIGURATION.chunk_distance..CONFIGURATION.chunk_distance {
-                //let coords = ChunkCoords { x: x, z: z };
-                let mut coords = ChunkCoords::from_pos(&pos);
-                coords.x += x;
-                coords.z += z;
-                if game.world.get_world().check_chunk_exists(&coords)
-                /*  && !(x == 0 && z == 0) */
-                {
-                    if !cl.loaded_chunks.contains(&coords) {
-                        cl.loaded_chunks.push(coords);
-                        game.loaded_chunks.push(coords);
-                        if CONFIGURATION.logging.chunk_load {
-                            log::info!(
-                                "{} is loading chunk at ({}, {})",
-                                cl.username,
-                                coords.x,
-                                coords.z
-                            );
-                        }
-                        let packets = game
-                            .world
-                            .get_world()
-                            .chunk_to_packets(coords, cl.packet_send_sender.clone());
-                        if packets.is_err() {
-                            continue;
-                        }
-                    }
-                } else {
-                    /*                     if let Some(c) = game.world.mcr_helper.get_chunk(ChunkCoords { x: coords.x, z: coords.z }) {
-                        game.world.chunks.insert(coords, c);
-                    } else {
-                        game.world.chunks.insert(
-                            coords,
-                            game.world.generator.gen_chunk(ChunkCoords {
-                                x: coords.x,
-                                z: coords.z,
-                            }),
-                        );
-                    } */
-                    game.world.get_world().init_chunk(&ChunkCoords {
-                        x: coords.x,
-                        z: coords.z,
-                    });
-                }
+        let queue = ChunkCoords::send_order(&center, distance, &cl.loaded_chunks);
+        let mut world = game.world.get_world();
+        for coords in queue.iter() {
+            // Sent from the queue once a worker has loaded it.
+            if !world.check_chunk_exists(coords) {
+                world.request_chunk(coords);
             }
         }
+        drop(world);
+        cl.chunk_queue = queue;
         let mut packets = vec![];
         let loaded = cl.loaded_chunks.clone();
+        let world = cl.world;
         cl.rendered_players.retain(|id, _| {
             if let Some(plr) = game.players.0.lock().unwrap().clone().get(&id.0) {
                 let otherpos = plr.get_position_clone();
                 let c_x = otherpos.x as i32 / 16;
                 let c_z = otherpos.z as i32 / 16;
                 let c_coords = ChunkCoords { x: c_x, z: c_z };
-                if !loaded.contains(&c_coords) {
+                if plr.get_world() != world || !loaded.contains(&c_coords) {
                     packets.push(ServerPacket::DestroyEntity { eid: id.0 .0 });
                     return false;
                 }
@@ -857,6 +869,43 @@ impl PlayerRef {
             cl.write(packet);
         }
     }
+    /// Send the next few queued chunks, up to `chunk_sends_per_tick`. Chunks still being loaded
+    /// keep their place in the queue, and ones whose load failed are asked for again.
+    pub fn send_queued_chunks(&self, game: &mut Game) {
+        let mut cl = self.player.borrow_mut();
+        let mut budget = CONFIGURATION.chunk_sends_per_tick;
+        let mut waiting = VecDeque::new();
+        while budget > 0 {
+            let coords = match cl.chunk_queue.pop_front() {
+                Some(coords) => coords,
+                None => break,
+            };
+            let mut world = game.world.get_world();
+            if !world.check_chunk_exists(&coords) {
+                if !world.is_chunk_pending(&coords) {
+                    world.request_chunk(&coords);
+                }
+                waiting.push_back(coords);
+                continue;
+            }
+            drop(world);
+            let sent = game
+                .world
+                .get_world()
+                .chunk_to_packets(coords, cl.packet_send_sender.clone());
+            if sent.is_err() {
+                continue;
+            }
+            if CONFIGURATION.logging.chunk_load {
+                log::info!("{} is loading chunk at ({}, {})", cl.username, coords.x, coords.z);
+            }
+            cl.loaded_chunks.push(coords);
+            game.loaded_chunks.push(coords);
+            budget -= 1;
+        }
+        waiting.append(&mut cl.chunk_queue);
+        cl.chunk_queue = waiting;
+    }
     /// Get this player's metadata
     pub fn build_metadata(&self) -> Metadata {
         self.unwrap().unwrap().build_metadata()
@@ -900,6 +949,14 @@ impl PlayerRef {
     pub fn set_air(&self, air: u16) {
         self.player.borrow_mut().air = air;
     }
+    /// Get how many ticks the player has been standing in a portal.
+    pub fn get_portal_ticks(&self) -> u32 {
+        self.player.borrow().portal_ticks
+    }
+    /// Set how many ticks the player has been standing in a portal.
+    pub fn set_portal_ticks(&self, ticks: u32) {
+        self.player.borrow_mut().portal_ticks = ticks;
+    }
     /// Get the last drown tick.
     pub fn get_last_drown_tick(&self) -> u128 {
         self.player.borrow().last_drown_tick
@@ -922,7 +979,7 @@ impl PlayerRef {
         if self.player.borrow().metadata_changed {
             for player in game.players.iter() {
                 let player = player.1;
-                if player
+                if player.get_world() == game.dimension && player
                     .get_loaded_chunks()
                     .contains(&self.get_position_clone().to_chunk_coords())
                 {
@@ -940,6 +997,7 @@ impl PlayerRef {
         if self.player.borrow().pos_changed.clone() {
             self.check_chunks(game);
         }
+        self.send_queued_chunks(game);
         if self.player.borrow().inv_changed.clone() {
             self.sync_inventory();
         }
@@ -1096,9 +1154,10 @@ impl PlayerRef {
         for player in plrlist.iter() {
             if player.0 != &cl.id {
                 let other_username = player.1.get_username();
-                if !cl
-                    .loaded_chunks
-                    .contains(&player.1.get_position().to_chunk_coords())
+                if player.1.get_world() != cl.world
+                    || !cl
+                        .loaded_chunks
+                        .contains(&player.1.get_position().to_chunk_coords())
                 {
                     //log::info!("{} not in {} loaded chunks", other_username, cl.username);
                     continue;
@@ -1299,6 +1358,10 @@ pub struct Player {
     pub last_transaction_id: i16,
     pub current_cursored_item: Option<ItemStack>,
     pub loaded_chunks: Vec<ChunkCoords>,
+    /// Chunks in view that haven't been sent yet, nearest first.
+    pub chunk_queue: VecDeque<ChunkCoords>,
+    /// The view distance the player asked for with `/view-distance`.
+    pub view_distance: i32,
     pub has_loaded_before: Vec<ChunkCoords>,
     pub since_last_attack: std::time::Instant,
     pub mining_block: MiningBlockData,
@@ -1314,6 +1377,8 @@ pub struct Player {
     pub last_drown_tick: u128,
     pub async_chat: Sender<AsyncChatCommand>,
     pub last_keepalive_time: u128,
+    /// How many ticks the player has been standing in a portal.
+    pub portal_ticks: u32,
     players_list: PlayerList,
 }
 impl Player {
@@ -1327,6 +1392,10 @@ impl Player {
         metadata.insert_byte(meta_val);
         metadata
     }
+    /// The view distance in chunks, capped by `chunk_distance`.
+    pub fn view_distance(&self) -> i32 {
+        self.view_distance.min(CONFIGURATION.chunk_distance)
+    }
     pub fn save_to_mem(&mut self) {
         //log::info!("Saving playerdata");
         let name = self.username.clone();
@@ -1335,6 +1404,7 @@ impl Player {
         data.position = self.position.clone();
         data.health = self.health;
         data.inventory = self.inventory.clone();
+        data.view_distance = self.view_distance;
     }
     pub fn sync_position(&mut self) {
         let pos = self.position;
@@ -1544,6 +1614,9 @@ pub struct PersistentPlayerData {
     pub position: Position,
     pub health: i16,
     pub inventory: Inventory,
+    /// Name of the world the player is in.
+    pub world: String,
+    pub view_distance: i32,
 }
 use tile_entity::*;
 impl Eq for PersistentPlayerData {}
@@ -1585,6 +1658,26 @@ pub struct CachedCommandData {
     root: String,
     desc: String,
 }
+/// A dimension's world and everything living in it, parked while another dimension is active.
+pub struct Dimension {
+    pub world: crate::world::chunks::WorldRef,
+    pub loaded_chunks: LoadedChunks,
+    pub block_updates: Vec<Block>,
+    pub entities: Arc<RefCell<HashMap<EntityID, Arc<RefCell<Box<dyn Entity>>>>>>,
+    pub tile_entities:
+        Arc<RefCell<HashMap<BlockPosition, Arc<RefCell<Box<dyn tile_entity::BlockTileEntity>>>>>>,
+}
+impl Dimension {
+    fn new(world: crate::world::chunks::World) -> Self {
+        Self {
+            world: crate::world::chunks::WorldRef { world: Arc::new(RefCell::new(world)) },
+            loaded_chunks: LoadedChunks(HashMap::new()),
+            block_updates: Vec::new(),
+            entities: Arc::new(RefCell::new(HashMap::new())),
+            tile_entities: Arc::new(RefCell::new(HashMap::new())),
+        }
+    }
+}
 pub struct Game {
     pub objects: Arc<Objects>,
     pub players: PlayerList,
@@ -1608,10 +1701,138 @@ pub struct Game {
     pub is_raining: bool,
     pub is_storming: bool,
     pub world_saving: bool,
+    /// Id of the world `world`, `entities` and the other per-dimension fields currently belong to.
+    /// 0 is `level_name`, -1 its nether, and the `worlds` from the config count up from 1.
+    /// Between systems this is always 0.
+    pub dimension: i8,
+    /// The worlds that aren't active, by id.
+    pub dimensions: HashMap<i8, Dimension>,
+    pub pregen: Option<pregen::Pregen>,
 }
 use nbt::*;
 use rand::Rng;
 impl Game {
+    /// Swap `dimension` into the per-dimension fields, parking the active one.
+    fn switch_dimension(&mut self, dimension: i8) -> bool {
+        if dimension == self.dimension {
+            return true;
+        }
+        let target = match self.dimensions.remove(&dimension) {
+            Some(target) => target,
+            None => return false,
+        };
+        let parked = Dimension {
+            world: std::mem::replace(&mut self.world, target.world),
+            loaded_chunks: std::mem::replace(&mut self.loaded_chunks, target.loaded_chunks),
+            block_updates: std::mem::replace(&mut self.block_updates, target.block_updates),
+            entities: std::mem::replace(&mut self.entities, target.entities),
+            tile_entities: std::mem::replace(&mut self.tile_entities, target.tile_entities),
+        };
+        self.dimensions.insert(self.dimension, parked);
+        self.dimension = dimension;
+        true
+    }
+    /// Run `f` with `dimension` active, switching back afterwards. `None` if there is no such dimension.
+    pub fn in_dimension<T>(&mut self, dimension: i8, f: impl FnOnce(&mut Game) -> T) -> Option<T> {
+        let previous = self.dimension;
+        if !self.switch_dimension(dimension) {
+            return None;
+        }
+        let result = f(self);
+        self.switch_dimension(previous);
+        Some(result)
+    }
+    /// Run `f` once in every dimension, overworld first and the rest by id. Returns the last error,
+    /// if any.
+    pub fn for_each_dimension(
+        &mut self,
+        mut f: impl FnMut(&mut Game) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut dimensions: Vec<i8> = self.dimensions.keys().cloned().collect();
+        dimensions.push(self.dimension);
+        dimensions.sort_unstable_by_key(|id| (*id != 0, *id));
+        let mut result = Ok(());
+        for dimension in dimensions {
+            if let Some(Err(e)) = self.in_dimension(dimension, &mut f) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+    /// Every loaded world's id and name, ordered by id.
+    pub fn world_names(&self) -> Vec<(i8, String)> {
+        let mut names = vec![(self.dimension, self.world.get_world().name.clone())];
+        for (id, dimension) in self.dimensions.iter() {
+            names.push((*id, dimension.world.get_world().name.clone()));
+        }
+        names.sort_by_key(|(id, _)| *id);
+        names
+    }
+    /// Id of the world called `name`.
+    pub fn world_id(&self, name: &str) -> Option<i8> {
+        self.world_names()
+            .into_iter()
+            .find(|(_, world)| world == name)
+            .map(|(id, _)| id)
+    }
+    /// Name of world `id`, or the main world's if there is no such world.
+    pub fn world_name(&self, id: i8) -> String {
+        self.world_names()
+            .into_iter()
+            .find(|(world, _)| *world == id)
+            .map_or_else(|| CONFIGURATION.level_name.clone(), |(_, name)| name)
+    }
+    /// The dimension the client is told world `id` is, 0 for overworlds and -1 for the nether.
+    pub fn client_dimension(&self, id: i8) -> i8 {
+        if id == self.dimension {
+            return self.world.get_world().dimension;
+        }
+        self.dimensions
+            .get(&id)
+            .map_or(0, |dimension| dimension.world.get_world().dimension)
+    }
+    /// Move a player to `position` in another world, as a portal, respawning or `/world` does.
+    pub fn move_to_dimension(&mut self, player: &Arc<PlayerRef>, dimension: i8, position: Position) {
+        if dimension != self.dimension && !self.dimensions.contains_key(&dimension) {
+            return;
+        }
+        let client_dimension = self.client_dimension(dimension);
+        let same_client_dimension = self.client_dimension(player.get_world()) == client_dimension;
+        if let Ok(plr) = player.unwrap() {
+            let _ = self.hide_player(&plr);
+        }
+        if let Ok(mut plr) = player.unwrap() {
+            // The client only throws its world away when the respawn changes dimension, so going
+            // between overworlds the old chunks and entities have to be removed by hand.
+            let chunks = std::mem::take(&mut plr.loaded_chunks);
+            let players: Vec<EntityID> = plr.rendered_players.drain().map(|((id, _), _)| id).collect();
+            let entities: Vec<EntityID> = plr.rendered_entities.drain().map(|(id, _)| id).collect();
+            if same_client_dimension {
+                for chunk in chunks {
+                    plr.write(ServerPacket::PreChunk {
+                        x: chunk.x,
+                        z: chunk.z,
+                        mode: false,
+                    });
+                }
+                for id in players.into_iter().chain(entities) {
+                    plr.write(ServerPacket::DestroyEntity { eid: id.0 });
+                }
+            }
+            plr.world = dimension;
+        }
+        if let Some(data) = self
+            .persistent_player_data
+            .borrow_mut()
+            .get_mut(&player.get_username())
+        {
+            data.world = self.world_name(dimension);
+        }
+        player.write_packet(ServerPacket::Respawn {
+            world: client_dimension,
+        });
+        self.in_dimension(dimension, |game| player.teleport(game, &position));
+    }
     pub fn check_world_save(&mut self) {
         if self.world_saving {
             if GAME_GLOBAL.get_time() % CONFIGURATION.autosave_interval == 0 {
@@ -1619,13 +1840,85 @@ impl Game {
                 if let Err(e) = self.save_playerdata() {
                     log::info!("Error saving playerdata: {:?}", e);
                 }
-                if let Err(e) = self.world.get_world().to_file(&CONFIGURATION.level_name) {
+                if let Err(e) = self.save_world("CONSOLE") {
                     log::info!("Error saving the world: {:?}", e);
                 }
-                self.op_status_message("CONSOLE", "Auto-save complete.");
             }
         }
     }
+    /// Report saves the background saver has finished to whoever asked for them.
+    pub fn poll_world_saves(&mut self) {
+        let _ = self.for_each_dimension(|game| {
+            let results = game.world.get_world().poll_saves();
+            game.report_world_saves(results);
+            Ok(())
+        });
+    }
+    fn report_world_saves(&mut self, results: Vec<SaveResult>) {
+        let location = match self.dimension {
+            0 => String::new(),
+            _ => format!(" in {}", self.world.get_world().name),
+        };
+        for result in results {
+            if let Some(e) = &result.error {
+                log::info!("Error saving the world: {:?}", e);
+            }
+            let requester = match &result.requester {
+                Some(requester) if requester == pregen::SAVE_REQUESTER => {
+                    pregen::save_finished(self, &result);
+                    continue;
+                }
+                Some(requester) => requester,
+                None => continue,
+            };
+            if result.error.is_some() {
+                self.op_status_message(requester, "Save failed. Check console for details.");
+            } else {
+                self.op_status_message(
+                    requester,
+                    &format!(
+                        "Save complete. Wrote {} of {} loaded chunks{}.",
+                        result.summary.chunks_saved, result.summary.chunks_loaded, location
+                    ),
+                );
+            }
+        }
+    }
+    /// Queue the chunks that changed to be written in the background. Completion is reported to
+    /// `requester` through `op_status_message`.
+    pub fn save_world(&mut self, requester: &str) -> anyhow::Result<()> {
+        self.for_each_dimension(|game| {
+            game.prepare_world_save();
+            game.world.get_world().queue_save(Some(requester))
+        })
+    }
+    /// Save every dimension and wait for it to be on disk, for shutdown.
+    pub fn save_world_blocking(&mut self) -> anyhow::Result<SaveSummary> {
+        let mut total = SaveSummary::default();
+        self.for_each_dimension(|game| {
+            let pending = game.world.get_world().wait_for_saves();
+            game.report_world_saves(pending);
+            game.prepare_world_save();
+            let summary = game.world.get_world().to_file(&CONFIGURATION.level_name)?;
+            total.chunks_saved += summary.chunks_saved;
+            total.chunks_loaded += summary.chunks_loaded;
+            Ok(())
+        })?;
+        Ok(total)
+    }
+    /// Copy the entities, time and weather into the world.
+    fn prepare_world_save(&mut self) {
+        self.store_chunk_entities(|_| true, false);
+        let mut world = self.world.get_world();
+        let time = GAME_GLOBAL.get_time();
+        for chunk in world.chunks.values_mut().filter(|chunk| chunk.dirty) {
+            chunk.last_update = time;
+        }
+        world.level_data.time = time;
+        world.level_data.raining = self.is_raining;
+        world.level_data.rain_time = self.rain_ticks.min(i32::MAX as u128) as i32;
+        world.level_data.thundering = self.is_storming;
+    }
     pub fn strike_lightning(&mut self, pos: BlockPosition) {
         let id = EntityID::new();
         self.packet_to_chunk(
@@ -1642,7 +1935,7 @@ impl Game {
     }
     pub fn packet_to_chunk(&mut self, chunk: ChunkCoords, packet: ServerPacket) {
         for player in self.players.iter() {
-            if player.1.get_loaded_chunks().contains(&chunk) {
+            if player.1.get_world() == self.dimension && player.1.get_loaded_chunks().contains(&chunk) {
                 player.1.write_packet(packet.clone());
             }
         }
@@ -1667,6 +1960,43 @@ impl Game {
                     .write_packet(ServerPacket::NewInvalidState { reason: 2 });
             }
         }
+        if self.is_raining {
+            self.snowfall();
+        }
+    }
+    /// In cold biomes the rain falls as snow: now and then a loaded chunk gets a snow layer on
+    /// one of its columns, or still water there freezes over.
+    fn snowfall(&mut self) {
+        use crate::world::vanilla::features::{is_solid, Region};
+        let mut rng = rand::thread_rng();
+        let mut changes = Vec::new();
+        {
+            let mut world = self.world.get_world();
+            for chunk in self.loaded_chunks.0.keys() {
+                if rng.gen_range(0..16) != 0 {
+                    continue;
+                }
+                let x = chunk.x * 16 + rng.gen_range(0..16);
+                let z = chunk.z * 16 + rng.gen_range(0..16);
+                if !world.get_biome(&BlockPosition::new(x, 0, z)).has_snow() {
+                    continue;
+                }
+                let mut region = Region::new(&mut world.chunks);
+                let y = region.top_solid_block(x, z);
+                if !(1..128).contains(&y) {
+                    continue;
+                }
+                let below = region.get(x, y - 1, z);
+                if below == 9 {
+                    changes.push((BlockPosition::new(x, y - 1, z), 79));
+                } else if below != 79 && is_solid(below) && region.is_air(x, y, z) {
+                    changes.push((BlockPosition::new(x, y, z), 78));
+                }
+            }
+        }
+        for (position, block_type) in changes {
+            self.set_block(position, block_type, 0);
+        }
     }
     pub fn stop_server(&mut self) {
         self.save_playerdata().unwrap();
@@ -1674,7 +2004,9 @@ impl Game {
         for player in plrs.iter() {
             player.1.disconnect("Server closed".to_string());
         }
-        self.world.get_world().to_file(&CONFIGURATION.level_name);
+        if let Err(e) = self.save_world_blocking() {
+            log::info!("Error saving the world: {:?}", e);
+        }
         std::process::exit(0);
     }
     pub fn op_status_message(&mut self, username: &str, message: &str) {
@@ -1737,6 +2069,102 @@ impl Game {
             }
         }
     }
+    /// Spawn the entities and tile entities stored in chunks that were just loaded from disk.
+    pub fn spawn_chunk_entities(&mut self) {
+        let (entities, tile_entities) = self.world.get_world().take_pending_entities();
+        for tag in entities {
+            if let Some(entity) = entity_from_tag(&tag, self.ticks) {
+                self.spawn_entity(entity);
+            }
+        }
+        for tag in tile_entities {
+            if let Some(entity) = tile_entity_from_tag(&tag) {
+                self.tile_entities
+                    .borrow_mut()
+                    .insert(entity.get_position(), Arc::new(RefCell::new(entity)));
+            }
+        }
+    }
+    /// Serialize the entities and tile entities in the chunks matching `filter` into those chunks,
+    /// ready to be saved. With `remove` set they are also despawned, for chunks being unloaded.
+    fn store_chunk_entities(&mut self, filter: impl Fn(&ChunkCoords) -> bool, remove: bool) {
+        self.spawn_chunk_entities();
+        let mut world = self.world.get_world();
+        for (coords, chunk) in world.chunks.iter_mut() {
+            if filter(coords) {
+                // Entities move and change without touching blocks, so any chunk holding them is rewritten.
+                if !chunk.entities.is_empty() || !chunk.tile_entities.is_empty() {
+                    chunk.dirty = true;
+                }
+                chunk.entities.clear();
+                chunk.tile_entities.clear();
+            }
+        }
+        let mut removed_entities = Vec::new();
+        for (id, entity) in self.entities.borrow().iter() {
+            let mut entity = entity.borrow_mut();
+            let position = entity.get_position();
+            let coords = ChunkCoords::from_pos(position);
+            if !filter(&coords) {
+                continue;
+            }
+            if let Some(tag) = entity.save() {
+                if let Some(chunk) = world.chunks.get_mut(&coords) {
+                    chunk.entities.push(tag);
+                    chunk.dirty = true;
+                }
+            }
+            if remove {
+                removed_entities.push(*id);
+            }
+        }
+        let mut removed_tile_entities = Vec::new();
+        for (position, entity) in self.tile_entities.borrow().iter() {
+            let coords = ChunkCoords {
+                x: position.x >> 4,
+                z: position.z >> 4,
+            };
+            if !filter(&coords) {
+                continue;
+            }
+            if let Some(tag) = entity.borrow().save() {
+                if let Some(chunk) = world.chunks.get_mut(&coords) {
+                    chunk.tile_entities.push(tag);
+                    chunk.dirty = true;
+                }
+            }
+            if remove {
+                removed_tile_entities.push(*position);
+            }
+        }
+        drop(world);
+        for id in removed_entities {
+            self.entities.borrow_mut().remove(&id);
+            IDS.lock().unwrap().push(id.0);
+        }
+        for position in removed_tile_entities {
+            self.tile_entities.borrow_mut().remove(&position);
+        }
+    }
+    /// Save the given chunks and drop them from memory, along with their entities.
+    pub fn unload_chunks(&mut self, chunks: &HashSet<ChunkCoords>) {
+        self.store_chunk_entities(|coords| chunks.contains(coords), true);
+        let time = GAME_GLOBAL.get_time();
+        let mut world = self.world.get_world();
+        for coords in chunks {
+            if let Some(chunk) = world.chunks.get_mut(coords).filter(|chunk| chunk.dirty) {
+                chunk.last_update = time;
+            }
+            if let Err(e) = world.unload_chunk(coords) {
+                log::error!("Error saving chunk {}, {}: {:?}", coords.x, coords.z, e);
+                continue;
+            }
+            self.loaded_chunks.0.remove(coords);
+            if CONFIGURATION.logging.chunk_unload {
+                log::info!("Saved and unloaded chunk {}, {}", coords.x, coords.z);
+            }
+        }
+    }
     pub fn tile_entity_ticks(&mut self) {
         let tiles = self.tile_entities.borrow().clone();
         for (pos, entity) in tiles.iter() {
@@ -1765,8 +2193,7 @@ impl Game {
                     );
                     let blk = chunk
                         .get_block(pos.0, pos.1, pos.2)
-                        .expect("Should not error!")
-                        .clone();
+                        .expect("Should not error!");
                     pos.0 += chunk.x * 16;
                     pos.2 += chunk.z * 16;
                     blocks.push((blk, pos));
@@ -1791,6 +2218,71 @@ impl Game {
             }
         }
     }
+    /// Set a block and queue the change to be sent to everyone who can see it.
+    pub fn set_block(&mut self, position: BlockPosition, block_type: u8, meta: u8) {
+        let block = self.world.get_block(&position);
+        block.set_type(block_type);
+        block.set_meta(meta);
+        self.queue_block_change(position, block_type, meta);
+    }
+    /// Like `set_block`, but without notifying the neighbours.
+    pub fn set_block_quietly(&mut self, position: BlockPosition, block_type: u8, meta: u8) {
+        self.world.get_block(&position).set_quietly(block_type, meta);
+        self.queue_block_change(position, block_type, meta);
+    }
+    /// Send a block change to the players with its chunk loaded at the end of the tick, batched
+    /// with the chunk's other changes.
+    pub fn queue_block_change(&mut self, position: BlockPosition, block_type: u8, meta: u8) {
+        self.block_updates.push(Block {
+            position,
+            block: crate::world::chunks::Block {
+                b_type: block_type,
+                b_metadata: meta,
+                ..Default::default()
+            },
+        });
+    }
+    /// Give the block at `position` a scheduled tick `delay` ticks from now.
+    pub fn schedule_block_tick(&mut self, position: BlockPosition, delay: u128) {
+        let block_type = self.world.get_block(&position).get_type();
+        let tick = self.ticks + delay;
+        self.world.get_world().schedule_tick(position, block_type, tick);
+    }
+    /// Run the scheduled block ticks that are due.
+    pub fn scheduled_ticks(&mut self) {
+        let due = self.world.get_world().scheduled_ticks.take_due(self.ticks);
+        for (position, block_type) in due {
+            if !self.world.get_world().is_block_loaded(&position) {
+                continue;
+            }
+            // The block was replaced since the tick was scheduled.
+            if self.world.get_block(&position).get_type() != block_type {
+                continue;
+            }
+            if let Some(block) = ItemRegistry::global().get_item(block_type as i16) {
+                if let Some(block) = block.get_item().as_block() {
+                    block.scheduled_tick(self, position);
+                }
+            }
+        }
+    }
+    /// Tell the neighbours of every block changed since the last tick.
+    pub fn neighbour_updates(&mut self) {
+        let changed: Vec<BlockPosition> = self.world.get_world().block_updates.drain(..).collect();
+        for from in changed {
+            for to in from.all_directions() {
+                if !self.world.get_world().is_block_loaded(&to) {
+                    continue;
+                }
+                let block_type = self.world.get_block(&to).get_type();
+                if let Some(block) = ItemRegistry::global().get_item(block_type as i16) {
+                    if let Some(block) = block.get_item().as_block() {
+                        block.nearby_block_update(self, from, to);
+                    }
+                }
+            }
+        }
+    }
     pub fn inv_to_tag(inv: &Inventory) -> CompoundTag {
         let mut inventory_tag = CompoundTag::new();
         let mut items = Vec::new();
@@ -1855,6 +2347,15 @@ impl Game {
                     position: pos,
                     health: root.get_i16("health").ok().unwrap().clone(),
                     inventory: Game::tag_to_inv(&root.get_compound_tag("Inventory").unwrap()),
+                    world: match root.get_str("World") {
+                        Ok(world) => world.to_string(),
+                        // Playerdata from before named worlds only recorded the dimension.
+                        Err(_) => match root.get_i32("Dimension") {
+                            Ok(-1) => format!("{}/DIM-1", CONFIGURATION.level_name),
+                            _ => CONFIGURATION.level_name.clone(),
+                        },
+                    },
+                    view_distance: root.get_i32("ViewDistance").unwrap_or(CONFIGURATION.chunk_distance),
                 },
             );
         }
@@ -1883,6 +2384,8 @@ impl Game {
             root.insert_compound_tag("Position", position_tag);
             root.insert_str("Username", name);
             root.insert_i16("health", player.health);
+            root.insert_str("World", &player.world);
+            root.insert_i32("ViewDistance", player.view_distance);
             root.insert_compound_tag("Inventory", Game::inv_to_tag(&player.inventory));
             let mut file = std::fs::File::create(format!(
                 "{}/playerdata/{}.nbt",
@@ -1897,6 +2400,37 @@ impl Game {
             .borrow_mut()
             .insert(entity.get_id(), Arc::new(RefCell::new(entity)));
     }
+    /// Load the world in `dir`, or generate a new one there.
+    fn open_world(
+        dir: &str,
+        chunk_generator: &str,
+        seed: Option<u64>,
+        flat_preset: &str,
+    ) -> crate::world::chunks::World {
+        use crate::world::chunks::*;
+        use rand::RngCore;
+        if let Ok(world) = World::from_file_mcr(dir) {
+            log::info!("Loading world \"{}\"", dir);
+            return world;
+        }
+        let seed = seed.unwrap_or_else(|| rand::thread_rng().next_u64());
+        let generator = match generator_from_config(chunk_generator, seed, flat_preset) {
+            Ok(generator) => generator,
+            Err(e) => {
+                log::info!("{}, using \"flat\"", e);
+                Box::new(PlainWorldGenerator::new(FlatChunkGenerator::default()))
+            }
+        };
+        log::info!(
+            "Initializing world \"{}\" with chunk generator \"{}\" with seed ({})",
+            dir,
+            chunk_generator,
+            seed
+        );
+        let mut world = World::new(generator, MCRegionLoader::new(dir).unwrap());
+        world.generate_spawn_chunks();
+        world
+    }
     pub fn new(
         systems: Systems,
         recv: Receiver<AsyncGameCommand>,
@@ -1910,63 +2444,69 @@ impl Game {
                 return false;
             }
             let event = event.unwrap();
-            //log::info!("Got event!");
-            let mut packet = event.packet.clone();
-            let mut player = event.player.unwrap().unwrap();
-            //packet.y -= 1;
-            match packet.direction {
-                0 => {
-                    packet.y -= 1;
-                }
-                1 => {
-                    //packet.y += 1;
-                    packet.y = match packet.y.checked_add(1) {
-                        Some(num) => num,
-                        None => {
-                            return false;
+            // Place the block in the dimension the player is in.
+            game.in_dimension(event.player.get_world(), |game| {
+                //log::info!("Got event!");
+                let mut packet = event.packet.clone();
+                let mut player = event.player.unwrap().unwrap();
+                //packet.y -= 1;
+                match packet.direction {
+                    0 => {
+                        packet.y -= 1;
+                    }
+                    1 => {
+                        //packet.y += 1;
+                        packet.y = match packet.y.checked_add(1) {
+                            Some(num) => num,
+                            None => {
+                                return false;
+                            }
                         }
                     }
+                    2 => {
+                        packet.z -= 1;
+                    }
+                    3 => {
+                        packet.z += 1;
+                    }
+                    4 => {
+                        packet.x -= 1;
+                    }
+                    5 => {
+                        packet.x += 1;
+                    }
+                    x => {
+                        log::debug!("Fal {}", x);
+                        return false;
+                    }
                 }
-                2 => {
-                    packet.z -= 1;
-                }
-                3 => {
-                    packet.z += 1;
-                }
-                4 => {
-                    packet.x -= 1;
-                }
-                5 => {
-                    packet.x += 1;
-                }
-                x => {
-                    log::debug!("Fal {}", x);
-                    return false;
-                }
-            }
-            // BLOCKS stuff
-            let block = game.world.get_block(&BlockPosition { x: packet.x, y: packet.y as i32, z: packet.z });
-            if let Some(blk) = ItemRegistry::global().get_item(block.get_type() as i16) {
-                if let Some(blk) = blk.get_item().as_block() {
-                    if !blk.is_solid() {
-                        if event.needs_align {
-                            block.set_meta(1);
+                // BLOCKS stuff
+                let block = game.world.get_block(&BlockPosition { x: packet.x, y: packet.y as i32, z: packet.z });
+                if let Some(blk) = ItemRegistry::global().get_item(block.get_type() as i16) {
+                    if let Some(blk) = blk.get_item().as_block() {
+                        if !blk.is_solid() {
+                            if event.needs_align {
+                                block.set_meta(1);
+                            }
+                            block.set_type(event.packet.block_or_item_id as u8);
+                            let position = BlockPosition { x: packet.x, y: packet.y as i32, z: packet.z };
+                            game.queue_block_change(position, block.get_type(), block.get_meta());
+                            fluids::block_placed(game, position);
+                        } else {
+                            player.write(ServerPacket::BlockChange {
+                                x: packet.x,
+                                y: packet.y + 0,
+                                z: packet.z,
+                                block_type: block.get_type() as i8,
+                                block_metadata: block.get_meta() as i8,
+                            })
                         }
-                        block.set_type(event.packet.block_or_item_id as u8);
-                    } else {
-                        player.write(ServerPacket::BlockChange {
-                            x: packet.x,
-                            y: packet.y + 0,
-                            z: packet.z,
-                            block_type: block.get_type() as i8,
-                            block_metadata: block.get_meta() as i8,
-                        })
                     }
                 }
-            }
-            true
+                true
+            })
+            .unwrap_or(false)
         }));
-        use rand::RngCore;
         items::default::init_items(&mut registry);
         crate::game::entities::tile_entity::init_items(&mut registry);
         ITEM_REGISTRY
@@ -1975,54 +2515,25 @@ impl Game {
             .expect("Can't set item registry!");
         //let generator = crate::temp_chunks::FlatWorldGenerator::new(64, 1,1, 1);
         use crate::world::chunks::*;
-        let mut world: crate::world::chunks::World;
-        if let Ok(w) = crate::world::chunks::World::from_file_mcr(&CONFIGURATION.level_name) {
-            log::info!("LOading world!");
-            world = w;
-        } else {
-            /*match CONFIGURATION.chunk_generator.as_str() {
-                "noise" => {
-                    let mut seed = rand::thread_rng().next_u64();
-                    if let Some(s) = CONFIGURATION.world_seed {
-                        seed = s as u64;
-                    }
-                    log::info!(
-                        "Initializing world with chunk generator \"noise\" with seed ({})",
-                        seed
-                    );
-                    Box::new(FunnyChunkGenerator::new(seed, FunnyChunkPreset::MOUNTAIN))
-                        as Box<dyn ChunkGenerator>
-                }
-                "flat" => {
-                    log::info!("Initializing world with chunk generator \"flat\"");
-                    Box::new(FlatChunkGenerator {}) as Box<dyn ChunkGenerator>
-                }
-                "mountain" => {
-                    let mut seed = rand::thread_rng().next_u64();
-                    if let Some(s) = CONFIGURATION.world_seed {
-                        seed = s as u64;
-                    }
-                    //seed = 14686157966026215583;
-                    log::info!(
-                        "Initializing world with chunk generator \"mountain\" with seed ({})",
-                        seed
-                    );
-                    Box::new(MountainChunkGenerator::new(seed)) as Box<dyn ChunkGenerator>
-                }
-                unknown => {
-                    log::info!("Unknown chunk generator \"{}\", using \"flat\"", unknown);
-                    Box::new(FlatChunkGenerator {}) as Box<dyn ChunkGenerator>
-                }
-            } */
-            let mut seed = rand::thread_rng().next_u64();
-            if let Some(s) = CONFIGURATION.world_seed {
-                seed = s as u64;
-            }
-            world = crate::world::chunks::World::new(
-                Box::new(MountainWorldGenerator::new(seed)),
-                MCRegionLoader::new(&CONFIGURATION.level_name).unwrap(),
-            );
-            world.generate_spawn_chunks();
+        let world = Self::open_world(
+            &CONFIGURATION.level_name,
+            &CONFIGURATION.chunk_generator,
+            CONFIGURATION.world_seed,
+            &CONFIGURATION.flat_preset,
+        );
+        let mut nether = crate::world::chunks::World::new(
+            Box::new(crate::world::vanilla::nether::NetherChunkGenerator::new(
+                world.generator.get_seed(),
+            )),
+            MCRegionLoader::new(&format!("{}/DIM-1", CONFIGURATION.level_name)).unwrap(),
+        );
+        nether.dimension = -1;
+        let mut dimensions = HashMap::new();
+        dimensions.insert(-1, Dimension::new(nether));
+        for (i, config) in CONFIGURATION.worlds.iter().enumerate() {
+            let generator = config.chunk_generator.as_ref().unwrap_or(&CONFIGURATION.chunk_generator);
+            let world = Self::open_world(&config.name, generator, config.seed, &config.flat_preset);
+            dimensions.insert(i as i8 + 1, Dimension::new(world));
         }
         //world = crate::world::mcregion::temp_from_dir("New World").unwrap();
         let mut command_system = CommandSystem::new();
@@ -2197,6 +2708,167 @@ impl Game {
                 Ok(3)
             }),
         ));
+        command_system.register(Command::new(
+            "whitelist",
+            "manage the whitelist (add/remove/on/off/list)",
+            4,
+            vec![CommandArgumentTypes::String, CommandArgumentTypes::StringRest],
+            Box::new(|game, executor, mut args| {
+                let action = args[0].as_any().downcast_mut::<String>().unwrap().clone();
+                let rest = args[1].as_any().downcast_mut::<Vec<String>>().unwrap().clone();
+                let result = match action.as_str() {
+                    "on" => crate::configuration::set_whitelist_enabled(true).map(|_| {
+                        game.op_status_message(&executor.username(), "Turned on white-listing");
+                    }),
+                    "off" => crate::configuration::set_whitelist_enabled(false).map(|_| {
+                        game.op_status_message(&executor.username(), "Turned off white-listing");
+                    }),
+                    "list" => crate::configuration::get_whitelist().map(|whitelist| {
+                        executor.send_message(Message::new(&format!(
+                            "White-listed players: {}",
+                            whitelist.players.join(", ")
+                        )));
+                    }),
+                    "add" | "remove" => {
+                        let player_name = match rest.get(0) {
+                            Some(name) => name.clone(),
+                            None => return Ok(1),
+                        };
+                        if action == "add" {
+                            crate::configuration::add_to_whitelist(&player_name).map(|_| {
+                                game.op_status_message(
+                                    &executor.username(),
+                                    &format!("Added {} to white-list", player_name),
+                                );
+                            })
+                        } else {
+                            crate::configuration::remove_from_whitelist(&player_name).map(|_| {
+                                game.op_status_message(
+                                    &executor.username(),
+                                    &format!("Removed {} from white-list", player_name),
+                                );
+                            })
+                        }
+                    }
+                    _ => {
+                        return Ok(1);
+                    }
+                };
+                if let Err(e) = result {
+                    log::error!("Could not update the whitelist: {}", e);
+                    executor.send_message(Message::new(&format!("§cCould not update the whitelist: {}", e)));
+                    return Ok(3);
+                }
+                Ok(0)
+            }),
+        ));
+        command_system.register(Command::new(
+            "ban",
+            "ban a player",
+            4,
+            vec![CommandArgumentTypes::String],
+            Box::new(|game, executor, mut args| {
+                let player_name = args[0].as_any().downcast_mut::<String>().unwrap().clone();
+                if let Err(e) = crate::configuration::add_banned_player(&player_name) {
+                    log::error!("Could not ban {}: {}", player_name, e);
+                    executor.send_message(Message::new(&format!("§cCould not update the ban list: {}", e)));
+                    return Ok(3);
+                }
+                if let Some(player) = game.players.get_player(&player_name) {
+                    player.disconnect("Banned by an operator.".to_string());
+                }
+                game.op_status_message(&executor.username(), &format!("Banning {}", player_name));
+                Ok(0)
+            }),
+        ));
+        command_system.register(Command::new(
+            "ban-ip",
+            "ban an IP address, or the address of an online player",
+            4,
+            vec![CommandArgumentTypes::String],
+            Box::new(|game, executor, mut args| {
+                let target = args[0].as_any().downcast_mut::<String>().unwrap().clone();
+                let ip = if let Ok(ip) = target.parse::<std::net::IpAddr>() {
+                    ip
+                } else if let Some(player) = game.players.get_player(&target) {
+                    player.get_addr().ip()
+                } else {
+                    executor.send_message(Message::new(&format!(
+                        "\"{}\" is not a valid IP address or online player.",
+                        target
+                    )));
+                    return Ok(3);
+                };
+                if let Err(e) = crate::configuration::add_banned_ip(&ip.to_string()) {
+                    log::error!("Could not ban ip {}: {}", ip, e);
+                    executor.send_message(Message::new(&format!("§cCould not update the ban list: {}", e)));
+                    return Ok(3);
+                }
+                let plrs = game.players.0.lock().unwrap().clone();
+                for player in plrs.values() {
+                    if player.get_addr().ip() == ip {
+                        player.disconnect("Banned by an operator.".to_string());
+                    }
+                }
+                game.op_status_message(&executor.username(), &format!("Banning ip {}", ip));
+                Ok(0)
+            }),
+        ));
+        command_system.register(Command::new(
+            "pardon",
+            "unban a player or IP address",
+            4,
+            vec![CommandArgumentTypes::String],
+            Box::new(|game, executor, mut args| {
+                let target = args[0].as_any().downcast_mut::<String>().unwrap().clone();
+                let removed = if target.parse::<std::net::IpAddr>().is_ok() {
+                    crate::configuration::remove_banned_ip(&target)
+                } else {
+                    crate::configuration::remove_banned_player(&target)
+                };
+                let removed = match removed {
+                    Ok(removed) => removed,
+                    Err(e) => {
+                        log::error!("Could not pardon {}: {}", target, e);
+                        executor.send_message(Message::new(&format!("§cCould not update the ban list: {}", e)));
+                        return Ok(3);
+                    }
+                };
+                if !removed {
+                    executor.send_message(Message::new(&format!(
+                        "§7Nothing changed. \"{}\" is not banned.",
+                        target
+                    )));
+                    return Ok(3);
+                }
+                game.op_status_message(&executor.username(), &format!("Pardoning {}", target));
+                Ok(0)
+            }),
+        ));
+        command_system.register(Command::new(
+            "banlist",
+            "list banned players, or banned IPs with \"banlist ips\"",
+            4,
+            vec![CommandArgumentTypes::StringRest],
+            Box::new(|_, executor, mut args| {
+                let rest = args[0].as_any().downcast_mut::<Vec<String>>().unwrap().clone();
+                let result = if rest.get(0).map(|s| s.as_str()) == Some("ips") {
+                    crate::configuration::get_banned_ips().map(|ips| {
+                        executor.send_message(Message::new(&format!("Banned IP addresses: {}", ips.join(", "))));
+                    })
+                } else {
+                    crate::configuration::get_banned_players().map(|players| {
+                        executor.send_message(Message::new(&format!("Banned players: {}", players.join(", "))));
+                    })
+                };
+                if let Err(e) = result {
+                    log::error!("Could not read the ban list: {}", e);
+                    executor.send_message(Message::new(&format!("§cCould not read the ban list: {}", e)));
+                    return Ok(3);
+                }
+                Ok(0)
+            }),
+        ));
         command_system.register(Command::new(
             "smite",
             "smite a player",
@@ -2343,7 +3015,12 @@ impl Game {
                                 to.get_username()
                             ),
                         );
-                        from.teleport(game, &to.get_position());
+                        let dimension = to.get_world();
+                        if from.get_world() != dimension {
+                            game.move_to_dimension(&from, dimension, to.get_position());
+                        } else {
+                            game.in_dimension(dimension, |game| from.teleport(game, &to.get_position()));
+                        }
                     } else {
                         executor
                             .send_message(Message::new(&format!("Can't find user {}. No tp.", to)));
@@ -2357,6 +3034,113 @@ impl Game {
                 Ok(0)
             }),
         ));
+        command_system.register(Command::new(
+            "world",
+            "world (name)",
+            1,
+            vec![CommandArgumentTypes::String],
+            Box::new(|game, executor, mut args| {
+                let name = args[0].as_any().downcast_mut::<String>().unwrap().clone();
+                let executor =
+                    if let Some(executor) = executor.as_any().downcast_mut::<Arc<PlayerRef>>() {
+                        executor
+                    } else {
+                        return Ok(3);
+                    };
+                let id = if let Some(id) = game.world_id(&name) {
+                    id
+                } else {
+                    let names: Vec<String> =
+                        game.world_names().into_iter().map(|(_, name)| name).collect();
+                    executor.send_message(Message::new(&format!(
+                        "§7No world named {}. Worlds: {}",
+                        name,
+                        names.join(", ")
+                    )));
+                    return Ok(3);
+                };
+                if executor.get_world() == id {
+                    executor.send_message(Message::new(&format!("§7You are already in {}.", name)));
+                    return Ok(0);
+                }
+                let mut position = game
+                    .in_dimension(id, |game| game.world.get_world().spawn_position)
+                    .unwrap();
+                position.stance = position.y + 1.62;
+                log::info!("{} moved to world {}", executor.get_username(), name);
+                game.move_to_dimension(executor, id, position);
+                executor.send_message(Message::new(&format!("§7Moved to {}.", name)));
+                Ok(0)
+            }),
+        ));
+        command_system.register(Command::new(
+            "view-distance",
+            "view-distance (chunks)",
+            1,
+            vec![CommandArgumentTypes::Int],
+            Box::new(|game, executor, mut args| {
+                let distance = *args[0].as_any().downcast_mut::<i32>().unwrap();
+                let executor =
+                    if let Some(executor) = executor.as_any().downcast_mut::<Arc<PlayerRef>>() {
+                        executor
+                    } else {
+                        return Ok(3);
+                    };
+                if distance < 1 {
+                    return Ok(3);
+                }
+                let distance = distance.min(CONFIGURATION.chunk_distance);
+                game.in_dimension(executor.get_world(), |game| {
+                    executor.set_view_distance(game, distance)
+                });
+                executor.send_message(Message::new(&format!(
+                    "§7Your view distance is now {} chunks.",
+                    distance
+                )));
+                Ok(0)
+            }),
+        ));
+        command_system.register(Command::new(
+            "pregen",
+            "pregen (radius in chunks) [circle] or pregen stop",
+            4,
+            vec![CommandArgumentTypes::StringRest],
+            Box::new(|game, executor, mut args| {
+                let args = args[0].as_any().downcast_mut::<Vec<String>>().unwrap().clone();
+                match args.get(0).map(|arg| arg.as_str()) {
+                    Some("stop") => {
+                        if pregen::stop(game) {
+                            game.op_status_message(&executor.username(), "Stopped pregen.");
+                        } else {
+                            executor.send_message(Message::new("§7No pregen is running."));
+                        }
+                        return Ok(0);
+                    }
+                    Some(radius) => {
+                        let radius = match radius.parse::<i32>() {
+                            Ok(radius) if radius >= 0 => radius,
+                            _ => return Ok(1),
+                        };
+                        let circle = match args.get(1).map(|arg| arg.as_str()) {
+                            None | Some("square") => false,
+                            Some("circle") => true,
+                            Some(_) => return Ok(1),
+                        };
+                        // Players pregen the world they are in, the console the main one.
+                        let dimension = executor
+                            .as_any()
+                            .downcast_mut::<Arc<PlayerRef>>()
+                            .map_or(0, |player| player.get_world());
+                        if let Err(e) = pregen::start(game, dimension, radius, circle, &executor.username()) {
+                            executor.send_message(Message::new(&format!("§7{}.", e)));
+                            return Ok(3);
+                        }
+                        Ok(0)
+                    }
+                    None => Ok(1),
+                }
+            }),
+        ));
         command_system.register(Command::new(
             "kill",
             "die",
@@ -2586,14 +3370,12 @@ impl Game {
                     executor
                         .send_message(Message::new(&format!("Error saving playerdata: {:?}", e)));
                 }
-                if let Err(e) = game.world.to_file(&CONFIGURATION.level_name) {
+                if let Err(e) = game.save_world(&executor.username()) {
                     log::info!("Error saving the world: {:?}", e);
                     game.op_status_message(
                         &executor.username(),
                         "Save failed. Check console for details.",
                     );
-                } else {
-                    game.op_status_message(&executor.username(), "Save complete.");
                 }
                 //game.world.to_file(&CONFIGURATION.level_name);
                 Ok(0)
@@ -2672,7 +3454,8 @@ impl Game {
                 args: command.arguments.clone(),
             });
         }
-        let game = Self {
+        let level_data = world.level_data;
+        let mut game = Self {
             objects: objects,
             players: PlayerList(Arc::new(Mutex::new(HashMap::new()))),
             systems: Arc::new(RefCell::new(systems)),
@@ -2690,12 +3473,16 @@ impl Game {
             async_chat_manager: async_chat_manager,
             perm_level_map: perm_level_map,
             cached_command_list: cached_command_list,
-            rain_ticks: 0,
+            rain_ticks: if level_data.raining { level_data.rain_time.max(0) as u128 } else { 0 },
             is_raining: false,
-            is_storming: false,
+            is_storming: level_data.raining && level_data.thundering,
             world_saving: true,
+            dimension: 0,
+            dimensions,
+            pregen: None,
         };
-        let mut game_globals = GameGlobals { time: 0 };
+        pregen::resume(&mut game);
+        let mut game_globals = GameGlobals { time: level_data.time.rem_euclid(24000) };
         GAME_GLOBAL.set(game_globals);
         game
     }
@@ -2738,20 +3525,25 @@ impl Game {
             let orig = player.is_crouching(); // borrow().crouching;
             let orig_hi = player.get_item_in_hand_clone(); // .borrow().get_item_in_hand_ref().clone();
             let orig_pos = player.get_position_clone(); // .borrow().position.clone();
-            if let Err(e) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                if let Err(e) = crate::network::packet::handler::handle_packet(
-                    self,
-                    server,
-                    player.clone(),
-                    packet,
-                ) {
-                    log::error!(
-                        "Error handling packet from user {}: {:?}",
-                        player.clone().get_username(), /*borrow().username*/
-                        e
-                    );
-                }
-            })) {
+            // Packets are handled in the sender's dimension.
+            let dimension = player.get_world();
+            let result = self.in_dimension(dimension, |game| {
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    if let Err(e) = crate::network::packet::handler::handle_packet(
+                        game,
+                        server,
+                        player.clone(),
+                        packet,
+                    ) {
+                        log::error!(
+                            "Error handling packet from user {}: {:?}",
+                            player.clone().get_username(), /*borrow().username*/
+                            e
+                        );
+                    }
+                }))
+            });
+            if let Some(Err(e)) = result {
                 //log::info!("Critical error handling packet from user {}.", player.clone().get_username());
                 player.write_packet(ServerPacket::Disconnect {
                     reason: String::from("A fatal error has occured."),
@@ -2862,21 +3654,45 @@ impl Game {
         let clients = server.clients.borrow_mut();
         let client = clients.get(&id).unwrap().clone();
         let mut client = client.borrow_mut();
+        drop(clients);
+        let is_op = self.perm_level_map.get(&client.username).map_or(false, |level| *level >= 4);
+        let online = self
+            .players
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|player| player.get_username() != client.username)
+            .count();
+        if !is_op && online >= CONFIGURATION.max_players as usize {
+            client.write(ServerPacket::Disconnect {
+                reason: CONFIGURATION.server_full_message.clone(),
+            })?;
+            log::info!("{}/{} was refused: the server is full", client.username, client.addr);
+            drop(client);
+            server.clients.borrow_mut().remove(&id);
+            IDS.lock().unwrap().push(id.0);
+            return Ok(());
+        }
+        // Players whose world is no longer loaded end up in the main one.
+        let world = self
+            .persistent_player_data
+            .borrow()
+            .get(&client.username)
+            .map(|data| data.world.clone());
+        let known_world = world.and_then(|world| self.world_id(&world));
+        let dimension = known_world.unwrap_or(0);
+        let map_seed = self
+            .in_dimension(dimension, |game| game.world.get_world().generator.get_seed())
+            .unwrap_or(0);
         let packet = ServerPacket::ServerLoginRequest {
             entity_id: id.0,
             unknown: "".to_string(),
-            map_seed: self.world.get_world().generator.get_seed() as i64,
-            dimension: 0,
+            map_seed: map_seed as i64,
+            dimension: self.client_dimension(dimension),
         };
         client.write(packet)?;
         let addr = client.addr;
-        drop(clients);
-        if self.players.0.lock().unwrap().len() + 1 > CONFIGURATION.max_players as usize {
-            client.write(ServerPacket::Disconnect {
-                reason: "The server is full!".to_string(),
-            })?;
-            return Err(anyhow::anyhow!("The server is full!"));
-        }
         if self.is_raining {
             client.write(ServerPacket::NewInvalidState { reason: 1 })?;
         }
@@ -2896,11 +3712,16 @@ impl Game {
             }
         }
         let mut persist_data: Option<PersistentPlayerData> = None;
-        let mut pos = self.world.get_world().spawn_position;
+        let spawn_position = self
+            .in_dimension(dimension, |game| game.world.get_world().spawn_position)
+            .unwrap_or(self.world.get_world().spawn_position);
+        let mut pos = spawn_position;
         if let Some(data) = self.persistent_player_data.borrow().get(&client.username) {
-            log::debug!("Position from persist: {:?}", data.position);
-            pos = data.position;
-            pos.y += 2.;
+            if known_world.is_some() {
+                log::debug!("Position from persist: {:?}", data.position);
+                pos = data.position;
+                pos.y += 2.;
+            }
             persist_data = Some(data.clone());
         }
         log::info!(
@@ -2910,46 +3731,18 @@ impl Game {
             id.0,
             pos
         );
-        let mut loaded_chunks = Vec::new();
-        let spawnchunk = pos.to_chunk_coords();
-        for x in -CONFIGURATION.chunk_distance..CONFIGURATION.chunk_distance {
-            for z in -CONFIGURATION.chunk_distance..CONFIGURATION.chunk_distance {
-                let spawnchunk = ChunkCoords {
-                    x: (pos.x as i32) + x,
-                    z: (pos.z as i32) + z,
-                };
-                if self.world.get_world().check_chunk_exists(&spawnchunk) {
-                    loaded_chunks.push(spawnchunk.clone());
-                    self.world
-                        .get_world().chunk_to_packets(spawnchunk, client.packet_send_sender.clone())?;
-                    self.loaded_chunks.push(spawnchunk);
-                } else {
-                    self.world.get_world().init_chunk(&ChunkCoords {
-                        x: spawnchunk.x,
-                        z: spawnchunk.z,
-                    });
-                    /*                     if let Some(c) = self.world.mcr_helper.get_chunk(ChunkCoords {
-                        x: spawnchunk.x,
-                        z: spawnchunk.z,
-                    }) {
-                        self.world.chunks.insert(spawnchunk, c);
-                    } else {
-                        // TODO STRUCTURE GEN
-                        self.world.chunks.insert(
-                            spawnchunk,
-                            self.world.generator.gen_chunk(ChunkCoords {
-                                x: spawnchunk.x,
-                                z: spawnchunk.z,
-                            }),
-                        );
-                        loaded_chunks.push(spawnchunk.clone());
-                    } */
-                    self.world.get_world()
-                        .chunk_to_packets(spawnchunk, client.packet_send_sender.clone())?;
-                    self.loaded_chunks.push(spawnchunk);
-                }
-            }
-        }
+        let packet_send_sender = client.packet_send_sender.clone();
+        // Only the chunk the player spawns in goes out now, the rest are queued on their first tick.
+        let loaded_chunks = self.in_dimension(dimension, |game| -> anyhow::Result<Vec<ChunkCoords>> {
+            let spawnchunk = ChunkCoords::from_pos(&pos);
+            game.world.get_world().init_chunk(&spawnchunk);
+            game.world
+                .get_world()
+                .chunk_to_packets(spawnchunk, packet_send_sender.clone())?;
+            game.loaded_chunks.push(spawnchunk);
+            Ok(vec![spawnchunk])
+        });
+        let loaded_chunks = loaded_chunks.unwrap()?;
         //std::thread::sleep_ms(1000);
         //log::info!("finished sending chunks");
         /*         self.world
@@ -2965,15 +3758,18 @@ impl Game {
             on_ground: pos.on_ground,
         })?;
         client.write(ServerPacket::SpawnPosition {
-            x: self.world.get_world().spawn_position.x as i32,
-            y: self.world.get_world().spawn_position.y as i32,
-            z: self.world.get_world().spawn_position.z as i32,
+            x: spawn_position.x as i32,
+            y: spawn_position.y as i32,
+            z: spawn_position.z as i32,
         })?;
         //client.write(ServerPacket::PlayerTeleport { player_id: -1, position: Position::from_pos(64, 128, 64)})?;
         let list = self.players.clone();
         let mut players = self.players.0.lock().unwrap();
         let mut health = 20;
         let mut inventory = Inventory::new();
+        let view_distance = persist_data
+            .as_ref()
+            .map_or(CONFIGURATION.chunk_distance, |data| data.view_distance);
         if let Some(data) = persist_data {
             health = data.health;
             inventory = data.inventory;
@@ -2999,7 +3795,7 @@ impl Game {
                 last_health: 20,
                 last_position: pos.clone(),
                 dead: false,
-                world: 0,
+                world: dimension,
                 last_void_dmg: std::time::Instant::now(),
                 inventory: inventory,
                 last_inventory: Inventory::new(),
@@ -3008,6 +3804,8 @@ impl Game {
                 last_transaction_id: 0,
                 current_cursored_item: None,
                 loaded_chunks: loaded_chunks,
+                chunk_queue: VecDeque::new(),
+                view_distance,
                 has_loaded_before: Vec::new(),
                 since_last_attack: std::time::Instant::now(),
                 mining_block: MiningBlockData::default(),
@@ -3016,7 +3814,7 @@ impl Game {
                 held_item_changed: false,
                 inv_changed: false,
                 checking_fall: true,
-                pos_changed: false,
+                pos_changed: true,
                 offground_height: 0.0,
                 chatbox: Chatbox::default(),
                 socket_addr: addr,
@@ -3027,6 +3825,7 @@ impl Game {
                 metadata_changed: true,
                 async_chat: self.async_chat_manager.clone(),
                 last_keepalive_time: self.ticks,
+                portal_ticks: 0,
             }))),
         );
         let us = players.get(&id).unwrap().clone();
@@ -3048,9 +3847,135 @@ impl Game {
                     position: us.get_position_clone(),
                     health: us.get_health(),
                     inventory: us.get_inventory().clone(),
+                    world: self.world_name(dimension),
+                    view_distance,
                 },
             );
+        } else if known_world.is_none() {
+            let world = self.world_name(dimension);
+            if let Some(data) = self.persistent_player_data.borrow_mut().get_mut(&us.get_username()) {
+                data.world = world;
+            }
         }
         Ok(())
     }
 }
+#[cfg(test)]
+impl Game {
+    /// A game around a small flat world in a fresh temporary directory, with no players or commands.
+    pub fn for_tests(name: &str) -> (String, Self) {
+        use crate::world::chunks::{generator_from_config, World, WorldRef};
+        ITEM_REGISTRY.get_or_init(|| {
+            let mut registry = ItemRegistry::new();
+            items::default::init_items(&mut registry);
+            crate::game::entities::tile_entity::init_items(&mut registry);
+            registry
+        });
+        let dir = std::env::temp_dir().join(format!("exo_game_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let dir = dir.to_str().unwrap().to_string();
+        let world = World::new(
+            generator_from_config("flat", 0, "7,3x1").unwrap(),
+            MCRegionLoader::new(&dir).unwrap(),
+        );
+        let world = WorldRef { world: Arc::new(RefCell::new(world)) };
+        for x in -1..=1 {
+            for z in -1..=1 {
+                world.get_world().init_chunk(&ChunkCoords { x, z });
+            }
+        }
+        let game = Self {
+            objects: Arc::new(Objects::new()),
+            players: PlayerList(Arc::new(Mutex::new(HashMap::new()))),
+            systems: Arc::new(RefCell::new(Systems::new())),
+            world,
+            block_updates: Vec::new(),
+            command_system: Arc::new(RefCell::new(CommandSystem::new())),
+            ticks: 0,
+            tile_entities: Arc::new(RefCell::new(HashMap::new())),
+            entities: Arc::new(RefCell::new(HashMap::new())),
+            loaded_chunks: LoadedChunks(HashMap::new()),
+            persistent_player_data: Arc::new(RefCell::new(HashMap::new())),
+            gamerules: gamerule::Gamerules::default(),
+            tps: 0.,
+            async_commands: flume::unbounded().1,
+            async_chat_manager: flume::unbounded().0,
+            perm_level_map: HashMap::new(),
+            cached_command_list: Vec::new(),
+            rain_ticks: 0,
+            is_raining: false,
+            is_storming: false,
+            world_saving: true,
+            dimension: 0,
+            dimensions: HashMap::new(),
+            pregen: None,
+        };
+        (dir, game)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn chunks_are_sent_nearest_first() {
+        let center = ChunkCoords { x: 3, z: -7 };
+        let order = ChunkCoords::send_order(&center, 3, &[]);
+        assert_eq!(order.len(), 49);
+        assert_eq!(order[0], center);
+        let rings: Vec<i32> = order
+            .iter()
+            .map(|coords| (coords.x - center.x).abs().max((coords.z - center.z).abs()))
+            .collect();
+        assert!(rings.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(order.iter().all(|coords| coords.in_view(&center, 3)));
+        assert_eq!(order.iter().collect::<HashSet<_>>().len(), 49);
+    }
+    #[test]
+    fn chunks_that_leave_view_are_dropped() {
+        let loaded = vec![ChunkCoords { x: 0, z: 0 }, ChunkCoords { x: 1, z: 0 }];
+        let before = ChunkCoords::send_order(&ChunkCoords { x: 0, z: 0 }, 2, &loaded);
+        assert!(!before.contains(&ChunkCoords { x: 1, z: 0 }));
+        assert!(before.contains(&ChunkCoords { x: -2, z: 1 }));
+        // Walking two chunks east takes the western column out of view.
+        let center = ChunkCoords { x: 2, z: 0 };
+        let after = ChunkCoords::send_order(&center, 2, &loaded);
+        assert!(!after.iter().any(|coords| coords.x < 0));
+        assert!(after.contains(&ChunkCoords { x: 4, z: 2 }));
+        assert!(!ChunkCoords { x: -1, z: 0 }.in_view(&center, 2));
+        assert!(ChunkCoords { x: 0, z: -2 }.in_view(&center, 2));
+    }
+    #[test]
+    fn negative_positions_are_sent_from_their_own_chunk() {
+        let center = ChunkCoords::from_pos(&Position::from_pos(-0.5, 64.0, -17.0));
+        assert_eq!(center, ChunkCoords { x: -1, z: -2 });
+        assert_eq!(
+            ChunkCoords::from_pos(&Position::from_pos(-16.0, 64.0, 15.9)),
+            ChunkCoords { x: -1, z: 0 }
+        );
+        let order = ChunkCoords::send_order(&center, 1, &[]);
+        assert_eq!(order[0], ChunkCoords { x: -1, z: -2 });
+        assert!(order.contains(&ChunkCoords { x: -2, z: -3 }));
+        assert!(!order.contains(&ChunkCoords { x: 1, z: 0 }));
+    }
+    #[test]
+    fn dimensions_run_overworld_first() {
+        use crate::world::chunks::{generator_from_config, World};
+        let (dir, mut game) = Game::for_tests("dimension_order");
+        for id in [2, -1, 1].iter() {
+            let world = World::new(
+                generator_from_config("flat", 0, "7,3x1").unwrap(),
+                MCRegionLoader::new(&format!("{}/DIM{}", dir, id)).unwrap(),
+            );
+            game.dimensions.insert(*id, Dimension::new(world));
+        }
+        let mut order = Vec::new();
+        game.for_each_dimension(|game| {
+            order.push(game.dimension);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(order, vec![0, -1, 1, 2]);
+        assert_eq!(game.dimension, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
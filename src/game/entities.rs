@@ -3,6 +3,7 @@ pub mod tile_entity;
 pub mod gravel_entity;
 pub mod mob_entity;
 use super::*;
+use nbt::{CompoundTag, Tag};
 pub trait Entity {
     fn spawn_entity(&mut self, player: &mut RefMut<'_, Player>);
     fn destruct_entity(&self, player: &mut RefMut<'_, Player>);
@@ -22,4 +23,50 @@ pub trait Entity {
         false
     }
     fn as_any(&mut self) -> &mut (dyn Any + 'static) where Self: 'static;
+    /// Serialize the entity for saving with its chunk. Entities returning `None` aren't saved.
+    fn save(&mut self) -> Option<CompoundTag> {
+        None
+    }
+}
+/// Build the tag fields every vanilla entity has.
+pub fn base_entity_tag(id: &str, position: &Position) -> CompoundTag {
+    let mut tag = CompoundTag::new();
+    tag.insert_str("id", id);
+    tag.insert("Pos", Tag::List(vec![Tag::Double(position.x), Tag::Double(position.y), Tag::Double(position.z)]));
+    tag.insert("Motion", Tag::List(vec![Tag::Double(0.), Tag::Double(0.), Tag::Double(0.)]));
+    tag.insert("Rotation", Tag::List(vec![Tag::Float(position.yaw), Tag::Float(position.pitch)]));
+    tag.insert_f32("FallDistance", 0.);
+    tag.insert_i16("Fire", -1);
+    tag.insert_i16("Air", 300);
+    tag.insert_i8("OnGround", position.on_ground as i8);
+    tag
+}
+/// Read the position out of a vanilla entity tag.
+pub fn position_from_tag(tag: &CompoundTag) -> Option<Position> {
+    let pos = match tag.get::<&Tag>("Pos").ok()? {
+        Tag::List(list) => list
+            .iter()
+            .filter_map(|t| if let Tag::Double(v) = t { Some(*v) } else { None })
+            .collect::<Vec<f64>>(),
+        _ => return None,
+    };
+    if pos.len() != 3 {
+        return None;
+    }
+    let mut position = Position::from_pos(pos[0], pos[1], pos[2]);
+    if let Ok(Tag::List(rotation)) = tag.get::<&Tag>("Rotation") {
+        if let (Some(Tag::Float(yaw)), Some(Tag::Float(pitch))) = (rotation.get(0), rotation.get(1)) {
+            position.yaw = *yaw;
+            position.pitch = *pitch;
+        }
+    }
+    Some(position)
+}
+/// Recreate an entity saved with `Entity::save`.
+pub fn entity_from_tag(tag: &CompoundTag, ticks: u128) -> Option<Box<dyn Entity>> {
+    match tag.get_str("id").ok()? {
+        "Item" => Some(Box::new(item_entity::ItemEntity::from_tag(tag, ticks)?)),
+        "FallingSand" => Some(Box::new(gravel_entity::GravelEntity::from_tag(tag, ticks)?)),
+        _ => None,
+    }
 }
\ No newline at end of file
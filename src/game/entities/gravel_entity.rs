@@ -18,6 +18,12 @@ impl GravelEntity {
             do_movement: false,
         }
     }
+    pub fn from_tag(tag: &CompoundTag, ticks: u128) -> Option<Self> {
+        if tag.get_i8("Tile").ok()? != 13 {
+            return None;
+        }
+        Some(Self::new(position_from_tag(tag)?, ticks))
+    }
     pub fn destruct(self) {
         IDS.lock().unwrap().push(self.entity_id.0);
     }
@@ -70,6 +76,11 @@ impl Entity for GravelEntity {
     fn get_position(&mut self) -> &mut Position {
         &mut self.position
     }
+    fn save(&mut self) -> Option<CompoundTag> {
+        let mut tag = base_entity_tag("FallingSand", &self.position);
+        tag.insert_i8("Tile", 13);
+        Some(tag)
+    }
     fn broadcast_pos_change(&mut self) -> bool {
         false
     }
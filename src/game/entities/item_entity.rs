@@ -9,6 +9,7 @@ pub struct ItemEntity {
     to_remove: bool,
     velocity: Option<[f64; 3]>,
     do_movement: bool,
+    age: u128,
 }
 impl ItemEntity {
     pub fn new(position: Position, ticks_spawned: u128, item: ItemStack, velocity: Option<[f64; 3]>) -> Self {
@@ -20,8 +21,22 @@ impl ItemEntity {
             to_remove: false,
             velocity: velocity,
             do_movement: false,
+            age: 0,
         }
     }
+    pub fn from_tag(tag: &CompoundTag, ticks: u128) -> Option<Self> {
+        let position = position_from_tag(tag)?;
+        let item = tag.get_compound_tag("Item").ok()?;
+        let item = ItemStack::new(
+            item.get_i16("id").ok()?,
+            item.get_i16("Damage").unwrap_or(0),
+            item.get_i8("Count").ok()?,
+        );
+        let age = tag.get_i16("Age").unwrap_or(0).max(0) as u128;
+        let mut entity = Self::new(position, ticks.saturating_sub(age), item, None);
+        entity.age = age;
+        Some(entity)
+    }
     pub fn destruct(self) {
         IDS.lock().unwrap().push(self.entity_id.0);
     }
@@ -67,6 +82,7 @@ impl Entity for ItemEntity {
             return;
         }
         log::debug!("I am being ticked! I'm at {:?}", self.position);
+        self.age = game.ticks - self.ticks_spawned;
         if game.ticks - self.ticks_spawned > 600 {
             game.entities.borrow_mut().remove(&self.entity_id);
         }
@@ -99,7 +115,7 @@ impl Entity for ItemEntity {
             }
             let players = game.players.0.lock().unwrap().clone();
             for player in players {
-                if self.position.distance(&player.1.get_position()) < 1.5 && !player.1.is_dead() {
+                if player.1.get_world() == game.dimension && self.position.distance(&player.1.get_position()) < 1.5 && !player.1.is_dead() {
                     self.to_remove = true;
                     let plr_id = player.1.get_id();
                     let packet = ServerPacket::CollectItem { collected_eid: self.entity_id.0, collector_eid: plr_id.0};
@@ -113,6 +129,20 @@ impl Entity for ItemEntity {
     fn get_position(&mut self) -> &mut Position {
         &mut self.position
     }
+    fn save(&mut self) -> Option<CompoundTag> {
+        if self.to_remove {
+            return None;
+        }
+        let mut tag = base_entity_tag("Item", &self.position);
+        let mut item = CompoundTag::new();
+        item.insert_i16("id", self.item.id);
+        item.insert_i16("Damage", self.item.damage);
+        item.insert_i8("Count", self.item.count);
+        tag.insert_compound_tag("Item", item);
+        tag.insert_i16("Health", 5);
+        tag.insert_i16("Age", self.age.min(i16::MAX as u128) as i16);
+        Some(tag)
+    }
     fn broadcast_pos_change(&mut self) -> bool {
         if self.do_movement {
             self.do_movement = false;
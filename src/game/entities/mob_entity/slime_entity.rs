@@ -86,6 +86,9 @@ impl MobEntity for SlimeEntity {
             let mut closest_position: Option<Position> = None;
             for player in game.players.0.lock().unwrap().clone().iter() {
                 let player = player.1;
+                if player.get_world() != game.dimension {
+                    continue;
+                }
                 if closest_position.is_none() {
                     closest_position = Some(player.get_position());
                     continue;
@@ -121,6 +124,9 @@ impl MobEntity for SlimeEntity {
             }
             if self.health < 0 {
                 for player in game.players.0.lock().unwrap().clone().iter() {
+                    if player.1.get_world() != game.dimension {
+                        continue;
+                    }
                     player.1.write_packet(ServerPacket::EntityStatus {
                         eid: self.entity_id.0,
                         entity_status: 3,
@@ -3,6 +3,7 @@ pub mod trapdoor_tile_entity;
 use crate::game::items::block::*;
 use once_cell::sync::Lazy;
 use std::sync::{Arc, Mutex};
+use nbt::CompoundTag;
 pub trait BlockTileEntity {
     fn new_entity(&self, position: BlockPosition) -> Box<dyn BlockTileEntity>;
     fn get_position(&self) -> BlockPosition;
@@ -34,6 +35,31 @@ pub trait BlockTileEntity {
     fn is_solid(&self) -> bool {
         true
     }
+    /// Serialize the tile entity for saving with its chunk. Tile entities returning `None` aren't saved.
+    fn save(&self) -> Option<CompoundTag> {
+        None
+    }
+}
+/// Build the tag fields every tile entity has.
+pub fn base_tile_entity_tag(id: &str, position: &BlockPosition) -> CompoundTag {
+    let mut tag = CompoundTag::new();
+    tag.insert_str("id", id);
+    tag.insert_i32("x", position.x);
+    tag.insert_i32("y", position.y);
+    tag.insert_i32("z", position.z);
+    tag
+}
+/// Recreate a tile entity saved with `BlockTileEntity::save`.
+pub fn tile_entity_from_tag(tag: &CompoundTag) -> Option<Box<dyn BlockTileEntity>> {
+    let position = BlockPosition::new(
+        tag.get_i32("x").ok()?,
+        tag.get_i32("y").ok()?,
+        tag.get_i32("z").ok()?,
+    );
+    match tag.get_str("id").ok()? {
+        "Trapdoor" => Some(trapdoor_tile_entity::TrapdoorTileEntity::from_tag(tag, position)),
+        _ => None,
+    }
 }
 impl<T> crate::game::items::block::Block for T
 where
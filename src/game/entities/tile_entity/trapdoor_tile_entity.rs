@@ -11,6 +11,12 @@ impl std::default::Default for TrapdoorTileEntity {
         Self { internal: None }
     }
 }
+impl TrapdoorTileEntity {
+    pub fn from_tag(tag: &CompoundTag, position: BlockPosition) -> Box<dyn BlockTileEntity> {
+        let is_open = tag.get_i8("Open").unwrap_or(0) != 0;
+        Box::new(Self { internal: Some(Internal { position, is_open }) })
+    }
+}
 impl BlockTileEntity for TrapdoorTileEntity {
     fn stack_size(&self) -> i16 {
         64
@@ -26,7 +32,7 @@ impl BlockTileEntity for TrapdoorTileEntity {
         log::info!("Is open? {}", internal.is_open);
         for player in game.players.iter() {
             let player = player.1;
-            if player.get_loaded_chunks().contains(&internal.position.to_chunk_coords()) {
+            if player.get_world() == game.dimension && player.get_loaded_chunks().contains(&internal.position.to_chunk_coords()) {
                 player.write_packet(ServerPacket::SoundEffect { effect_id: 1003, x: internal.position.x, y: internal.position.y as i8, z: internal.position.z, sound_data: 1 });
             }
         }
@@ -61,6 +67,12 @@ impl BlockTileEntity for TrapdoorTileEntity {
     fn hardness(&self) -> f32 {
         2.
     }
+    fn save(&self) -> Option<CompoundTag> {
+        let internal = self.internal.as_ref()?;
+        let mut tag = base_tile_entity_tag("Trapdoor", &internal.position);
+        tag.insert_i8("Open", internal.is_open as i8);
+        Some(tag)
+    }
     fn get_position(&self) -> BlockPosition {
         self.internal.as_ref().unwrap().position
     }
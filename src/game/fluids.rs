@@ -0,0 +1,321 @@
+use super::*;
+/// Water and lava, following the b1.7.3 flow rules.
+///
+/// Flow level lives in the block metadata: 0 is a source, 1-7 is how far the fluid has spread
+/// from one, and 8 is set on fluid that is falling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fluid {
+    Water,
+    Lava,
+}
+impl Fluid {
+    pub fn from_id(block_type: u8) -> Option<Self> {
+        match block_type {
+            8 | 9 => Some(Self::Water),
+            10 | 11 => Some(Self::Lava),
+            _ => None,
+        }
+    }
+    pub fn flowing_id(&self) -> u8 {
+        match self {
+            Self::Water => 8,
+            Self::Lava => 10,
+        }
+    }
+    pub fn still_id(&self) -> u8 {
+        match self {
+            Self::Water => 9,
+            Self::Lava => 11,
+        }
+    }
+    /// Ticks between flow updates.
+    pub fn tick_rate(&self) -> u128 {
+        match self {
+            Self::Water => 5,
+            Self::Lava => 30,
+        }
+    }
+    /// How much the flow level grows with each block the fluid spreads sideways.
+    fn decay_step(&self) -> u8 {
+        match self {
+            Self::Water => 1,
+            Self::Lava => 2,
+        }
+    }
+}
+/// Called when a player places a block. Placed fluid starts spreading straight away, like pouring a bucket.
+pub fn block_placed(game: &mut Game, position: BlockPosition) {
+    let block = game.world.get_block(&position);
+    if let Some(fluid) = Fluid::from_id(block.get_type()) {
+        if block.get_type() == fluid.still_id() {
+            let meta = block.get_meta();
+            game.set_block_quietly(position, fluid.flowing_id(), meta);
+        }
+        block_added(game, position);
+    }
+}
+/// A block next to this fluid changed.
+pub fn neighbour_changed(game: &mut Game, position: BlockPosition) {
+    let block = game.world.get_block(&position);
+    let fluid = match Fluid::from_id(block.get_type()) {
+        Some(fluid) => fluid,
+        None => return,
+    };
+    if harden(game, position, fluid) {
+        return;
+    }
+    if block.get_type() == fluid.still_id() {
+        let meta = block.get_meta();
+        game.set_block_quietly(position, fluid.flowing_id(), meta);
+        game.schedule_block_tick(position, fluid.tick_rate());
+    }
+}
+/// Fluid was just set here by flowing or being placed.
+fn block_added(game: &mut Game, position: BlockPosition) {
+    let block = game.world.get_block(&position);
+    if let Some(fluid) = Fluid::from_id(block.get_type()) {
+        if !harden(game, position, fluid) && block.get_type() == fluid.flowing_id() {
+            game.schedule_block_tick(position, fluid.tick_rate());
+        }
+    }
+}
+/// The scheduled tick of flowing water and lava.
+pub fn update_flow(game: &mut Game, position: BlockPosition) {
+    let (block_type, meta) = block_at(game, &position);
+    let fluid = match Fluid::from_id(block_type) {
+        Some(fluid) if block_type == fluid.flowing_id() => fluid,
+        _ => return,
+    };
+    let step = fluid.decay_step() as i32;
+    let mut level = meta as i32;
+    let mut settle = true;
+    if level > 0 {
+        let mut sources = 0;
+        let mut smallest = -100;
+        for side in horizontal(&position) {
+            if let Some(side_level) = flow_level(game, &side, fluid) {
+                if side_level == 0 {
+                    sources += 1;
+                }
+                let side_level = if side_level >= 8 { 0 } else { side_level as i32 };
+                if smallest < 0 || side_level < smallest {
+                    smallest = side_level;
+                }
+            }
+        }
+        let mut new_level = smallest + step;
+        if new_level >= 8 || smallest < 0 {
+            new_level = -1;
+        }
+        if let Some(above) = flow_level(game, &offset(&position, 0, 1, 0), fluid) {
+            new_level = if above >= 8 { above as i32 } else { above as i32 + 8 };
+        }
+        if sources >= 2 && fluid == Fluid::Water {
+            // Infinite water: two sources side by side make a third on anything solid or on more water.
+            let below = offset(&position, 0, -1, 0);
+            if is_solid(game, &below) || flow_level(game, &below, fluid) == Some(0) {
+                new_level = 0;
+            }
+        }
+        if fluid == Fluid::Lava
+            && level < 8
+            && new_level < 8
+            && new_level > level
+            && rand::thread_rng().gen_range(0..4) != 0
+        {
+            new_level = level;
+            settle = false;
+        }
+        if new_level != level {
+            level = new_level;
+            if level < 0 {
+                game.set_block(position, 0, 0);
+                return;
+            }
+            game.set_block(position, fluid.flowing_id(), level as u8);
+            game.schedule_block_tick(position, fluid.tick_rate());
+        } else if settle {
+            game.set_block_quietly(position, fluid.still_id(), level as u8);
+        }
+    } else {
+        game.set_block_quietly(position, fluid.still_id(), level as u8);
+    }
+    let below = offset(&position, 0, -1, 0);
+    if can_displace(game, &below, fluid) {
+        // Lava falling onto water turns it to stone instead of sinking through.
+        if fluid == Fluid::Lava && flow_level(game, &below, Fluid::Water).is_some() {
+            game.set_block(below, 1, 0);
+            return;
+        }
+        let falling = if level >= 8 { level } else { level + 8 };
+        flow_into(game, below, fluid, falling as u8);
+    } else if level == 0 || blocks_flow(game, &below) {
+        let spread = if level >= 8 { 1 } else { level + step };
+        if spread >= 8 {
+            return;
+        }
+        let directions = flow_directions(game, &position, fluid);
+        for (side, optimal) in horizontal(&position).into_iter().zip(directions) {
+            if optimal {
+                flow_into(game, side, fluid, spread as u8);
+            }
+        }
+    }
+}
+fn flow_into(game: &mut Game, position: BlockPosition, fluid: Fluid, level: u8) {
+    if !can_displace(game, &position, fluid) {
+        return;
+    }
+    game.set_block(position, fluid.flowing_id(), level);
+    block_added(game, position);
+}
+/// Lava touching water turns to obsidian if it was a source, or cobblestone if it was close to one.
+fn harden(game: &mut Game, position: BlockPosition, fluid: Fluid) -> bool {
+    if fluid != Fluid::Lava {
+        return false;
+    }
+    let mut touching = horizontal(&position).to_vec();
+    touching.push(offset(&position, 0, 1, 0));
+    let touching_water = touching
+        .iter()
+        .any(|p| flow_level(game, p, Fluid::Water).is_some());
+    if !touching_water {
+        return false;
+    }
+    let meta = game.world.get_block(&position).get_meta();
+    let hardened = if meta == 0 {
+        49
+    } else if meta <= 4 {
+        4
+    } else {
+        return false;
+    };
+    game.set_block(position, hardened, 0);
+    true
+}
+/// Sides the fluid should spread to: the ones with the shortest path to a drop within 4 blocks,
+/// or every side when there is no drop nearby.
+fn flow_directions(game: &mut Game, position: &BlockPosition, fluid: Fluid) -> [bool; 4] {
+    let mut costs = [1000; 4];
+    for (direction, side) in horizontal(position).iter().enumerate() {
+        if blocks_flow(game, side) || flow_level(game, side, fluid) == Some(0) {
+            continue;
+        }
+        costs[direction] = if !blocks_flow(game, &offset(side, 0, -1, 0)) {
+            0
+        } else {
+            flow_cost(game, side, fluid, 1, direction)
+        };
+    }
+    let cheapest = *costs.iter().min().unwrap();
+    costs.map(|cost| cost == cheapest)
+}
+fn flow_cost(
+    game: &mut Game,
+    position: &BlockPosition,
+    fluid: Fluid,
+    distance: i32,
+    came_from: usize,
+) -> i32 {
+    let mut cheapest = 1000;
+    for (direction, side) in horizontal(position).iter().enumerate() {
+        // Never step back the way we came.
+        if direction == opposite(came_from) {
+            continue;
+        }
+        if blocks_flow(game, side) || flow_level(game, side, fluid) == Some(0) {
+            continue;
+        }
+        if !blocks_flow(game, &offset(side, 0, -1, 0)) {
+            return distance;
+        }
+        if distance < 4 {
+            cheapest = cheapest.min(flow_cost(game, side, fluid, distance + 1, direction));
+        }
+    }
+    cheapest
+}
+/// -x, +x, -z, +z, the order the direction indices below refer to.
+fn horizontal(position: &BlockPosition) -> [BlockPosition; 4] {
+    [
+        offset(position, -1, 0, 0),
+        offset(position, 1, 0, 0),
+        offset(position, 0, 0, -1),
+        offset(position, 0, 0, 1),
+    ]
+}
+fn opposite(direction: usize) -> usize {
+    direction ^ 1
+}
+fn offset(position: &BlockPosition, x: i32, y: i32, z: i32) -> BlockPosition {
+    BlockPosition::new(position.x + x, position.y + y, position.z + z)
+}
+fn is_loaded(game: &Game, position: &BlockPosition) -> bool {
+    game.world.get_world().is_block_loaded(position)
+}
+fn block_at(game: &Game, position: &BlockPosition) -> (u8, u8) {
+    let block = game.world.get_block(position);
+    (block.get_type(), block.get_meta())
+}
+/// Flow level of `fluid` at a position, `None` if the block is something else.
+fn flow_level(game: &Game, position: &BlockPosition, fluid: Fluid) -> Option<u8> {
+    if !is_loaded(game, position) {
+        return None;
+    }
+    let (block_type, meta) = block_at(game, position);
+    if Fluid::from_id(block_type) == Some(fluid) {
+        Some(meta)
+    } else {
+        None
+    }
+}
+fn is_solid(game: &Game, position: &BlockPosition) -> bool {
+    if !is_loaded(game, position) {
+        return true;
+    }
+    match game.world.get_block(position).get_type() {
+        0 => false,
+        block_type => ItemRegistry::global()
+            .get_item(block_type as i16)
+            .and_then(|item| item.get_item().as_block().map(|block| block.is_solid()))
+            .unwrap_or(true),
+    }
+}
+/// Doors, signs, ladders and reeds hold fluid back even though they aren't solid.
+fn blocks_flow(game: &Game, position: &BlockPosition) -> bool {
+    if !is_loaded(game, position) {
+        return true;
+    }
+    match game.world.get_block(position).get_type() {
+        63 | 64 | 65 | 68 | 71 | 83 => true,
+        _ => is_solid(game, position),
+    }
+}
+/// Whether the fluid can wash this block away. Lava is never displaced, water only by lava.
+fn can_displace(game: &Game, position: &BlockPosition, fluid: Fluid) -> bool {
+    if !is_loaded(game, position) {
+        return false;
+    }
+    let other = Fluid::from_id(game.world.get_block(position).get_type());
+    other != Some(fluid) && other != Some(Fluid::Lava) && !blocks_flow(game, position)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn lava_falling_onto_water_makes_stone() {
+        let (dir, mut game) = Game::for_tests("lava_onto_water");
+        for y in 4..9 {
+            game.set_block_quietly(BlockPosition::new(8, y, 8), 9, 0);
+        }
+        game.set_block_quietly(BlockPosition::new(8, 10, 8), 11, 0);
+        game.set_block_quietly(BlockPosition::new(8, 9, 8), 10, 8);
+        update_flow(&mut game, BlockPosition::new(8, 9, 8));
+        assert_eq!(block_at(&game, &BlockPosition::new(8, 8, 8)), (1, 0));
+        assert_eq!(Fluid::from_id(block_at(&game, &BlockPosition::new(8, 9, 8)).0), Some(Fluid::Lava));
+        for y in 4..8 {
+            assert_eq!(block_at(&game, &BlockPosition::new(8, y, 8)).0, 9);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -27,8 +27,17 @@ pub trait Block {
     fn nearby_block_update(&self, game: &mut Game, from: BlockPosition, to: BlockPosition) {
         
     }
-    fn opacity(&self) -> u64 {
-        100
+    /// How much light the block absorbs, from 0 (fully transparent) to 15 (opaque).
+    fn opacity(&self) -> u8 {
+        if self.is_solid() {
+            15
+        } else {
+            0
+        }
+    }
+    /// Light level the block gives off.
+    fn light_emission(&self) -> u8 {
+        0
     }
     fn hardness(&self) -> f32;
     fn needs_align(&self) -> bool {
@@ -46,6 +55,8 @@ pub trait Block {
     fn random_tick(&self, game: &mut Game, block: BlockPosition) {
         //log::info!("I am grass, and I was ticked!");
     }
+    /// Runs when an update queued with `Game::schedule_block_tick` comes due.
+    fn scheduled_tick(&self, game: &mut Game, position: BlockPosition) {}
 }
 impl<T> Item for T
 where
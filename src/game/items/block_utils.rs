@@ -14,6 +14,9 @@ pub fn place_validator(game: &mut Game, packet: &crate::network::packet::PlayerB
             continue;
         } */
         //let mut pos = pos;
+        if user.1.get_world() != game.dimension {
+            continue;
+        }
         let pos = user.1.get_position_clone();
         if pos.contains_block(crate::game::BlockPosition {
             x: packet.x,
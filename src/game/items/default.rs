@@ -32,23 +32,28 @@ impl block::Block for SaplingBlock {
         0.6
     }
     fn random_tick(&self, game: &mut Game, position: BlockPosition) {
-        if rand::thread_rng().gen_range(0..4) == 3 {
-            game.world.get_block(&position).set_type(17);
-            for i in 1..rand::thread_rng().gen_range(2..7) {
-                let world_block = game.world.get_block(&BlockPosition::new(
-                    position.x,
-                    position.y + i,
-                    position.z,
-                ));
-                if let Some(block) = ItemRegistry::global().get_item(world_block.get_type() as i16)
-                {
-                    if let Some(block) = block.get_item().as_block() {
-                        if !block.is_solid() {
-                            world_block.set_type(17);
-                        }
-                    }
-                }
+        use crate::world::vanilla::features::Region;
+        use crate::world::vanilla::noise::JavaRandom;
+        if rand::thread_rng().gen_range(0..4) != 3 {
+            return;
+        }
+        let meta = game.world.get_block(&position).get_meta();
+        let mut random = JavaRandom::new(rand::thread_rng().gen());
+        let changes = {
+            let mut world = game.world.get_world();
+            // The tree kind follows the biome, so saplings grow what the generator would have put there.
+            let tree = world.get_biome(&position).tree(&mut random);
+            let mut region = Region::tracked(&mut world.chunks);
+            region.set(position.x, position.y, position.z, 0);
+            if !tree.grow(&mut region, &mut random, position.x, position.y, position.z) {
+                region.set_with_meta(position.x, position.y, position.z, 6, meta);
             }
+            region.take_changes()
+        };
+        for changed in changes {
+            let block = game.world.get_block(&changed);
+            let (block_type, meta) = (block.get_type(), block.get_meta());
+            game.set_block(changed, block_type, meta);
         }
     }
     fn insta_break(&self) -> bool {
@@ -188,6 +193,9 @@ impl block::Block for CobblestoneBlock {
 }
 pub struct FenceBlock {}
 impl block::Block for FenceBlock {
+    fn opacity(&self) -> u8 {
+        0
+    }
     fn stack_size(&self) -> i16 {
         64
     }
@@ -284,6 +292,9 @@ impl block::Block for StoneBlock {
 }
 pub struct WoodenDoorBlock {}
 impl block::Block for WoodenDoorBlock {
+    fn opacity(&self) -> u8 {
+        0
+    }
     fn stack_size(&self) -> i16 {
         64
     }
@@ -439,19 +450,21 @@ impl block::Block for GravelBlock {
         true
     }
     fn nearby_block_update(&self, game: &mut Game, from: BlockPosition, to: BlockPosition) {
-        log::info!("Got nearby");
+        game.schedule_block_tick(to, 3);
+    }
+    fn scheduled_tick(&self, game: &mut Game, position: BlockPosition) {
         let block = game
             .world
-            .get_block(&BlockPosition::new(to.x, to.y as i32 - 1, to.z));
+            .get_block(&BlockPosition::new(position.x, position.y as i32 - 1, position.z));
         if let Some(block) = ItemRegistry::global().get_item(block.get_type() as i16) {
             if let Some(block) = block.get_item().as_block() {
                 if !block.is_solid() {
                     game.spawn_entity(Box::new(
                         crate::game::entities::gravel_entity::GravelEntity::new(
                             Position::from_pos(
-                                to.x as f64 + 0.5,
-                                to.y as f64 + 0.5,
-                                to.z as f64 + 0.5,
+                                position.x as f64 + 0.5,
+                                position.y as f64 + 0.5,
+                                position.z as f64 + 0.5,
                             ),
                             game.ticks,
                         ),
@@ -485,6 +498,9 @@ impl block::Block for GravelBlock {
 }
 pub struct LeavesBlock {}
 impl block::Block for LeavesBlock {
+    fn opacity(&self) -> u8 {
+        1
+    }
     fn stack_size(&self) -> i16 {
         64
     }
@@ -574,6 +590,9 @@ impl block::Block for CraftingTableBlock {
 }
 pub struct TorchBlock {}
 impl block::Block for TorchBlock {
+    fn light_emission(&self) -> u8 {
+        14
+    }
     fn stack_size(&self) -> i16 {
         64
     }
@@ -690,8 +709,22 @@ impl block::Block for LogBlock {
         2.
     }
 }
-pub struct WaterBlock {}
-impl block::Block for WaterBlock {
+pub struct FluidBlock {
+    fluid: fluids::Fluid,
+}
+impl block::Block for FluidBlock {
+    fn opacity(&self) -> u8 {
+        match self.fluid {
+            fluids::Fluid::Water => 3,
+            fluids::Fluid::Lava => 15,
+        }
+    }
+    fn light_emission(&self) -> u8 {
+        match self.fluid {
+            fluids::Fluid::Water => 0,
+            fluids::Fluid::Lava => 15,
+        }
+    }
     fn stack_size(&self) -> i16 {
         64
     }
@@ -711,11 +744,16 @@ impl block::Block for WaterBlock {
         tool: ItemStack,
         position: BlockPosition,
     ) -> Option<ItemStack> {
-        //Some(ItemStack::new(5, 0, 1))
         None
     }
+    fn nearby_block_update(&self, game: &mut Game, from: BlockPosition, to: BlockPosition) {
+        fluids::neighbour_changed(game, to);
+    }
+    fn scheduled_tick(&self, game: &mut Game, position: BlockPosition) {
+        fluids::update_flow(game, position);
+    }
     fn hardness(&self) -> f32 {
-        2.
+        100.
     }
     fn is_fluid(&self) -> bool {
         true
@@ -726,6 +764,9 @@ impl block::Block for WaterBlock {
 }
 pub struct GlassBlock {}
 impl block::Block for GlassBlock {
+    fn opacity(&self) -> u8 {
+        0
+    }
     fn stack_size(&self) -> i16 {
         64
     }
@@ -779,6 +820,231 @@ impl block::Block for PlanksBlock {
         2.
     }
 }
+pub struct ObsidianBlock {}
+impl block::Block for ObsidianBlock {
+    fn stack_size(&self) -> i16 {
+        64
+    }
+    fn on_place(
+        &self,
+        game: &mut Game,
+        packet: &mut crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> bool {
+        true
+    }
+    fn on_break(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerDigging,
+        player: std::cell::RefMut<'_, Player>,
+        tool: ItemStack,
+        position: BlockPosition,
+    ) -> Option<ItemStack> {
+        let registry = ItemRegistry::global();
+        if let Some(item) = registry.get_item(tool.id) {
+            if let Some(item) = item.get_item().get_tool_type() {
+                match item {
+                    ToolType::PICKAXE => {
+                        return Some(ItemStack::new(49, 0, 1));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+    fn hardness(&self) -> f32 {
+        10.
+    }
+}
+pub struct NetherrackBlock {}
+impl block::Block for NetherrackBlock {
+    fn stack_size(&self) -> i16 {
+        64
+    }
+    fn on_place(
+        &self,
+        game: &mut Game,
+        packet: &mut crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> bool {
+        true
+    }
+    fn on_break(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerDigging,
+        player: std::cell::RefMut<'_, Player>,
+        tool: ItemStack,
+        position: BlockPosition,
+    ) -> Option<ItemStack> {
+        let registry = ItemRegistry::global();
+        if let Some(item) = registry.get_item(tool.id) {
+            if let Some(item) = item.get_item().get_tool_type() {
+                match item {
+                    ToolType::PICKAXE => {
+                        return Some(ItemStack::new(87, 0, 1));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+    fn hardness(&self) -> f32 {
+        0.4
+    }
+}
+pub struct SoulSandBlock {}
+impl block::Block for SoulSandBlock {
+    fn stack_size(&self) -> i16 {
+        64
+    }
+    fn on_place(
+        &self,
+        game: &mut Game,
+        packet: &mut crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> bool {
+        true
+    }
+    fn on_break(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerDigging,
+        player: std::cell::RefMut<'_, Player>,
+        tool: ItemStack,
+        position: BlockPosition,
+    ) -> Option<ItemStack> {
+        Some(ItemStack::new(88, 0, 1))
+    }
+    fn hardness(&self) -> f32 {
+        0.5
+    }
+}
+pub struct GlowstoneBlock {}
+impl block::Block for GlowstoneBlock {
+    fn light_emission(&self) -> u8 {
+        15
+    }
+    fn stack_size(&self) -> i16 {
+        64
+    }
+    fn on_place(
+        &self,
+        game: &mut Game,
+        packet: &mut crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> bool {
+        true
+    }
+    fn on_break(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerDigging,
+        player: std::cell::RefMut<'_, Player>,
+        tool: ItemStack,
+        position: BlockPosition,
+    ) -> Option<ItemStack> {
+        Some(ItemStack::new(348, 0, rand::thread_rng().gen_range(2..5)))
+    }
+    fn hardness(&self) -> f32 {
+        0.3
+    }
+}
+pub struct FireBlock {}
+impl FireBlock {
+    /// Ticks between fire updates.
+    pub const TICK_RATE: u128 = 40;
+}
+impl block::Block for FireBlock {
+    fn light_emission(&self) -> u8 {
+        15
+    }
+    fn stack_size(&self) -> i16 {
+        64
+    }
+    fn on_place(
+        &self,
+        game: &mut Game,
+        packet: &mut crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> bool {
+        true
+    }
+    fn on_break(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerDigging,
+        player: std::cell::RefMut<'_, Player>,
+        tool: ItemStack,
+        position: BlockPosition,
+    ) -> Option<ItemStack> {
+        None
+    }
+    /// Fire ages by its metadata and burns out, except on netherrack which burns forever. Spreading
+    /// to flammable blocks is not implemented.
+    fn scheduled_tick(&self, game: &mut Game, position: BlockPosition) {
+        let below = game.world.get_block(&BlockPosition::new(position.x, position.y - 1, position.z)).get_type();
+        if below == 87 {
+            game.schedule_block_tick(position, Self::TICK_RATE);
+            return;
+        }
+        let age = game.world.get_block(&position).get_meta() + rand::thread_rng().gen_range(0..3) / 2;
+        if below == 0 || age >= 15 {
+            game.set_block(position, 0, 0);
+            return;
+        }
+        game.set_block_quietly(position, 51, age);
+        game.schedule_block_tick(position, Self::TICK_RATE);
+    }
+    fn hardness(&self) -> f32 {
+        0.
+    }
+    fn insta_break(&self) -> bool {
+        true
+    }
+    fn is_solid(&self) -> bool {
+        false
+    }
+}
+pub struct PortalBlock {}
+impl block::Block for PortalBlock {
+    fn light_emission(&self) -> u8 {
+        11
+    }
+    fn stack_size(&self) -> i16 {
+        0
+    }
+    fn on_place(
+        &self,
+        game: &mut Game,
+        packet: &mut crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> bool {
+        true
+    }
+    fn on_break(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerDigging,
+        player: std::cell::RefMut<'_, Player>,
+        tool: ItemStack,
+        position: BlockPosition,
+    ) -> Option<ItemStack> {
+        None
+    }
+    fn nearby_block_update(&self, game: &mut Game, from: BlockPosition, to: BlockPosition) {
+        portals::check_frame(game, to);
+    }
+    fn hardness(&self) -> f32 {
+        -1.
+    }
+    fn is_solid(&self) -> bool {
+        false
+    }
+}
 pub struct GoldChestplateItem {}
 impl Item for GoldChestplateItem {
     fn is_block(&self) -> bool {
@@ -819,6 +1085,70 @@ impl Item for StickItem {
         Ok(())
     }
 }
+pub struct FlintAndSteelItem {}
+impl Item for FlintAndSteelItem {
+    fn is_block(&self) -> bool {
+        false
+    }
+    fn stack_size(&self) -> i16 {
+        1
+    }
+    fn on_use(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> anyhow::Result<()> {
+        let mut position = BlockPosition::new(packet.x, packet.y as i32, packet.z);
+        match packet.direction {
+            0 => position.y -= 1,
+            1 => position.y += 1,
+            2 => position.z -= 1,
+            3 => position.z += 1,
+            4 => position.x -= 1,
+            5 => position.x += 1,
+            _ => return Ok(()),
+        }
+        if game.world.get_block(&position).get_type() != 0 {
+            return Ok(());
+        }
+        let below = BlockPosition::new(position.x, position.y - 1, position.z);
+        if game.world.get_block(&below).get_type() != portals::OBSIDIAN
+            || !portals::try_create(game, position)
+        {
+            game.set_block(position, portals::FIRE, 0);
+            game.schedule_block_tick(position, FireBlock::TICK_RATE);
+        }
+        let mut hand = player.get_item_in_hand();
+        hand.damage += 1;
+        if hand.damage as u64 > self.max_uses().unwrap_or(0) {
+            hand.reset();
+        }
+        drop(hand);
+        player.sync_inventory();
+        Ok(())
+    }
+    fn max_uses(&self) -> Option<u64> {
+        Some(64)
+    }
+}
+pub struct GlowstoneDustItem {}
+impl Item for GlowstoneDustItem {
+    fn is_block(&self) -> bool {
+        false
+    }
+    fn stack_size(&self) -> i16 {
+        64
+    }
+    fn on_use(
+        &self,
+        game: &mut Game,
+        packet: crate::network::packet::PlayerBlockPlacement,
+        player: Arc<PlayerRef>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
 pub struct GoldPickaxeItem {}
 impl Item for GoldPickaxeItem {
     fn is_block(&self) -> bool {
@@ -1081,7 +1411,10 @@ pub fn init_items(registry: &mut ItemRegistry) {
     );
     registry.register_item(271, "wood_axe_item", Box::new(WoodAxeItem {}));
     registry.register_item(275, "stone_axe_item", Box::new(StoneAxeItem {}));
-    registry.register_item(9, "water_block", Box::new(WaterBlock {}));
+    registry.register_item(8, "flowing_water_block", Box::new(FluidBlock { fluid: fluids::Fluid::Water }));
+    registry.register_item(9, "water_block", Box::new(FluidBlock { fluid: fluids::Fluid::Water }));
+    registry.register_item(10, "flowing_lava_block", Box::new(FluidBlock { fluid: fluids::Fluid::Lava }));
+    registry.register_item(11, "lava_block", Box::new(FluidBlock { fluid: fluids::Fluid::Lava }));
     registry.register_item(20, "glass_block", Box::new(GlassBlock {}));
     registry.register_item(268, "wood_sword_item", Box::new(WoodSwordItem {}));
     registry.register_item(85, "fence_block", Box::new(FenceBlock {}));
@@ -1091,6 +1424,14 @@ pub fn init_items(registry: &mut ItemRegistry) {
     registry.register_item(6, "sapling_block", Box::new(SaplingBlock {}));
     registry.register_item(13, "gravel_block", Box::new(GravelBlock {}));
     registry.register_item(18, "leaves_block", Box::new(LeavesBlock {}));
+    registry.register_item(49, "obsidian_block", Box::new(ObsidianBlock {}));
+    registry.register_item(51, "fire_block", Box::new(FireBlock {}));
+    registry.register_item(87, "netherrack_block", Box::new(NetherrackBlock {}));
+    registry.register_item(88, "soul_sand_block", Box::new(SoulSandBlock {}));
+    registry.register_item(89, "glowstone_block", Box::new(GlowstoneBlock {}));
+    registry.register_item(90, "portal_block", Box::new(PortalBlock {}));
+    registry.register_item(259, "flint_and_steel_item", Box::new(FlintAndSteelItem {}));
+    registry.register_item(348, "glowstone_dust_item", Box::new(GlowstoneDustItem {}));
     let plank = ItemStack::new(5, 0, 1);
     let mut arrvec = arrayvec::ArrayVec::new();
     arrvec.push(ItemStack::new(17, 0, 1));
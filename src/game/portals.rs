@@ -0,0 +1,216 @@
+//! Nether portals, following the b1.7.3 frame rules.
+//!
+//! A portal is a 4 wide, 5 tall obsidian frame (corners optional) lit from inside. Standing in
+//! one long enough moves the player to the other dimension, where coordinates are scaled by 8.
+use super::*;
+use fluids::Fluid;
+pub const OBSIDIAN: u8 = 49;
+pub const FIRE: u8 = 51;
+pub const PORTAL: u8 = 90;
+/// Ticks a player has to stand in a portal before travelling.
+const TRAVEL_TICKS: u32 = 80;
+/// How far around the destination to look for an existing portal. Vanilla searches 128 blocks;
+/// this only searches the chunks that are loaded for the lookup.
+const SEARCH_RADIUS: i32 = 16;
+fn block_type(game: &mut Game, x: i32, y: i32, z: i32) -> u8 {
+    game.world.get_block(&BlockPosition::new(x, y, z)).get_type()
+}
+/// Fill the frame around `position`, the lowest block inside it, with portal. Returns false if
+/// there is no complete frame.
+pub fn try_create(game: &mut Game, position: BlockPosition) -> bool {
+    let (mut x, y, mut z) = (position.x, position.y, position.z);
+    let along_x = block_type(game, x - 1, y, z) == OBSIDIAN || block_type(game, x + 1, y, z) == OBSIDIAN;
+    let along_z = block_type(game, x, y, z - 1) == OBSIDIAN || block_type(game, x, y, z + 1) == OBSIDIAN;
+    if along_x == along_z {
+        return false;
+    }
+    let (dx, dz) = if along_x { (1, 0) } else { (0, 1) };
+    // Lit from the right hand column, so the frame starts one block over.
+    if block_type(game, x - dx, y, z - dz) == 0 {
+        x -= dx;
+        z -= dz;
+    }
+    for across in -1..=2 {
+        for up in -1..=3 {
+            let edge_across = across == -1 || across == 2;
+            let edge_up = up == -1 || up == 3;
+            if edge_across && edge_up {
+                continue;
+            }
+            let found = block_type(game, x + dx * across, y + up, z + dz * across);
+            if edge_across || edge_up {
+                if found != OBSIDIAN {
+                    return false;
+                }
+            } else if found != 0 && found != FIRE {
+                return false;
+            }
+        }
+    }
+    for across in 0..2 {
+        for up in 0..3 {
+            game.set_block_quietly(
+                BlockPosition::new(x + dx * across, y + up, z + dz * across),
+                PORTAL,
+                0,
+            );
+        }
+    }
+    true
+}
+/// A block next to the portal block at `position` changed. Breaks the portal if its frame is no
+/// longer whole.
+pub fn check_frame(game: &mut Game, position: BlockPosition) {
+    let (x, y, z) = (position.x, position.y, position.z);
+    let (dx, dz) = if block_type(game, x - 1, y, z) == PORTAL || block_type(game, x + 1, y, z) == PORTAL {
+        (1, 0)
+    } else {
+        (0, 1)
+    };
+    let mut bottom = y;
+    while block_type(game, x, bottom - 1, z) == PORTAL {
+        bottom -= 1;
+    }
+    let mut intact = block_type(game, x, bottom - 1, z) == OBSIDIAN;
+    if intact {
+        let mut height = 1;
+        while height < 4 && block_type(game, x, bottom + height, z) == PORTAL {
+            height += 1;
+        }
+        intact = height == 3 && block_type(game, x, bottom + height, z) == OBSIDIAN;
+    }
+    if intact {
+        let along_x = block_type(game, x - 1, y, z) == PORTAL || block_type(game, x + 1, y, z) == PORTAL;
+        let along_z = block_type(game, x, y, z - 1) == PORTAL || block_type(game, x, y, z + 1) == PORTAL;
+        let forward = block_type(game, x + dx, y, z + dz);
+        let back = block_type(game, x - dx, y, z - dz);
+        intact = !(along_x && along_z)
+            && ((forward == OBSIDIAN && back == PORTAL) || (back == OBSIDIAN && forward == PORTAL));
+    }
+    if !intact {
+        game.set_block(position, 0, 0);
+    }
+}
+/// Count up how long each player has stood in a portal and send the ones that waited long
+/// enough to the other dimension.
+pub fn tick(game: &mut Game) {
+    for (_, player) in game.players.iter() {
+        if player.is_dead() {
+            continue;
+        }
+        let dimension = player.get_world();
+        // Only the main world has a nether.
+        let (target, scale) = match dimension {
+            0 => (-1, 1. / 8.),
+            -1 => (0, 8.),
+            _ => continue,
+        };
+        let position = player.get_position_clone();
+        let in_portal = game
+            .in_dimension(dimension, |game| {
+                let (x, y, z) = (position.x.floor() as i32, position.y.floor() as i32, position.z.floor() as i32);
+                block_type(game, x, y, z) == PORTAL || block_type(game, x, y + 1, z) == PORTAL
+            })
+            .unwrap_or(false);
+        if !in_portal {
+            player.set_portal_ticks(0);
+            continue;
+        }
+        // Players arriving in a portal have to step out before it takes them back.
+        let ticks = player.get_portal_ticks();
+        if ticks >= TRAVEL_TICKS {
+            continue;
+        }
+        player.set_portal_ticks(ticks + 1);
+        if ticks + 1 < TRAVEL_TICKS {
+            continue;
+        }
+        let x = (position.x * scale).floor() as i32;
+        let z = (position.z * scale).floor() as i32;
+        let y = position.y.floor() as i32;
+        let destination = match game.in_dimension(target, |game| find_or_build(game, x, y, z)) {
+            Some(destination) => destination,
+            None => continue,
+        };
+        let mut position = position;
+        position.x = destination.x as f64 + 0.5;
+        position.y = destination.y as f64;
+        position.z = destination.z as f64 + 0.5;
+        position.stance = position.y + 1.62;
+        log::info!(
+            "{} went through a portal to {} at {}",
+            player.get_username(),
+            game.world_name(target),
+            position
+        );
+        game.move_to_dimension(&player, target, position);
+        player.set_portal_ticks(TRAVEL_TICKS);
+    }
+}
+/// The bottom block of the nearest portal to the given position, building one if there is none
+/// close enough.
+fn find_or_build(game: &mut Game, x: i32, y: i32, z: i32) -> BlockPosition {
+    for chunk_x in (x - SEARCH_RADIUS) >> 4..=(x + SEARCH_RADIUS) >> 4 {
+        for chunk_z in (z - SEARCH_RADIUS) >> 4..=(z + SEARCH_RADIUS) >> 4 {
+            game.world.get_world().init_chunk(&ChunkCoords { x: chunk_x, z: chunk_z });
+        }
+    }
+    let mut nearest: Option<(i32, BlockPosition)> = None;
+    for px in x - SEARCH_RADIUS..=x + SEARCH_RADIUS {
+        for pz in z - SEARCH_RADIUS..=z + SEARCH_RADIUS {
+            for py in 1..127 {
+                if block_type(game, px, py, pz) != PORTAL || block_type(game, px, py - 1, pz) == PORTAL {
+                    continue;
+                }
+                let distance = (px - x).pow(2) + (py - y).pow(2) + (pz - z).pow(2);
+                if nearest.map_or(true, |(best, _)| distance < best) {
+                    nearest = Some((distance, BlockPosition::new(px, py, pz)));
+                }
+            }
+        }
+    }
+    if let Some((_, position)) = nearest {
+        return position;
+    }
+    build(game, x, y.max(10).min(118), z)
+}
+/// Build a lit portal along the x axis with its bottom left inner block at `x`, `z`, on the
+/// free spot nearest `y`, and a platform to step out onto. Without a free spot it is carved out
+/// at `y`.
+fn build(game: &mut Game, x: i32, y: i32, z: i32) -> BlockPosition {
+    let fits = |game: &mut Game, y: i32| {
+        let floor = block_type(game, x, y - 1, z);
+        floor != 0
+            && Fluid::from_id(floor).is_none()
+            && (0..3).all(|up| block_type(game, x, y + up, z) == 0)
+    };
+    let mut found = y;
+    for offset in 0..110 {
+        let candidates = [y - offset, y + offset];
+        if let Some(candidate) = candidates
+            .iter()
+            .cloned()
+            .find(|candidate| (10..119).contains(candidate) && fits(game, *candidate))
+        {
+            found = candidate;
+            break;
+        }
+    }
+    let y = found;
+    for across in -1..=2 {
+        for up in -1..=3 {
+            let edge = across == -1 || across == 2 || up == -1 || up == 3;
+            let block = if edge { OBSIDIAN } else { PORTAL };
+            game.set_block_quietly(BlockPosition::new(x + across, y + up, z), block, 0);
+            if !edge {
+                for side in [-1, 1].iter() {
+                    game.set_block_quietly(BlockPosition::new(x + across, y + up, z + side), 0, 0);
+                    if up == 0 {
+                        game.set_block_quietly(BlockPosition::new(x + across, y - 1, z + side), OBSIDIAN, 0);
+                    }
+                }
+            }
+        }
+    }
+    BlockPosition::new(x, y, z)
+}
@@ -0,0 +1,378 @@
+//! Generating every chunk around spawn ahead of time, through the chunk workers and a few at a
+//! time so the server keeps up.
+//!
+//! Progress is kept in `pregen.dat` in the world directory, so a pregen stopped by a restart
+//! carries on where it left off.
+use super::*;
+use nbt::CompoundTag;
+/// Chunks requested from the workers at once. Drops to one while the server is behind.
+const MAX_IN_FLIGHT: usize = 16;
+/// Largest radius accepted, about 64 million chunks for a square.
+pub const MAX_RADIUS: i32 = 4000;
+/// Requester given to checkpoint saves, so their results come back here rather than to a player.
+/// Usernames can't contain `#`.
+pub const SAVE_REQUESTER: &str = "#pregen";
+/// How often progress is reported and saved.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+pub struct Pregen {
+    /// The world being generated.
+    pub dimension: i8,
+    pub radius: i32,
+    pub circle: bool,
+    center: ChunkCoords,
+    /// Told about progress through `op_status_message`.
+    requester: String,
+    /// Number of chunks to generate.
+    total: usize,
+    /// The ring the next chunk is taken from, as a distance from the center.
+    ring: i32,
+    /// The chunks of `ring`, in the order they are requested.
+    ring_chunks: Vec<ChunkCoords>,
+    /// Index in `ring_chunks` of the next chunk to request.
+    ring_next: usize,
+    /// Chunks requested so far, nearest to the center first.
+    next: usize,
+    /// Order and coordinates of the requested chunks that haven't arrived, oldest first.
+    in_flight: Vec<(usize, ChunkCoords)>,
+    /// `done()` when the checkpoint save being written was queued.
+    saving: Option<usize>,
+    started: Instant,
+    /// `done()` when this run started, so chunks from before a restart don't skew the rate.
+    started_done: usize,
+    last_report: Instant,
+}
+impl Pregen {
+    pub fn new(dimension: i8, center: ChunkCoords, radius: i32, circle: bool, requester: &str) -> Self {
+        let radius = radius as i64;
+        let total = match circle {
+            true => (-radius..=radius)
+                .map(|x| 2 * ((radius * radius - x * x) as f64).sqrt() as usize + 1)
+                .sum(),
+            false => ((2 * radius + 1) * (2 * radius + 1)) as usize,
+        };
+        Self {
+            dimension,
+            radius: radius as i32,
+            circle,
+            center,
+            requester: requester.to_string(),
+            total,
+            ring: -1,
+            ring_chunks: Vec::new(),
+            ring_next: 0,
+            next: 0,
+            in_flight: Vec::new(),
+            saving: None,
+            started: Instant::now(),
+            started_done: 0,
+            last_report: Instant::now(),
+        }
+    }
+    /// The chunks `ring` chunks from the center on both axes at most and exactly on one, by x then z.
+    /// Going ring by ring keeps the part already done a solid area around the center.
+    fn chunks_in_ring(&self, ring: i32) -> Vec<ChunkCoords> {
+        let radius = self.radius as i64;
+        let mut chunks = Vec::new();
+        for x in -ring..=ring {
+            let zs: Vec<i32> = match x.abs() == ring {
+                true => (-ring..=ring).collect(),
+                false => vec![-ring, ring],
+            };
+            for z in zs {
+                if !self.circle || (x as i64).pow(2) + (z as i64).pow(2) <= radius * radius {
+                    chunks.push(ChunkCoords { x: self.center.x + x, z: self.center.z + z });
+                }
+            }
+        }
+        chunks
+    }
+    /// The next chunk to request, or `None` once every ring has been handed out.
+    fn next_chunk(&mut self) -> Option<ChunkCoords> {
+        while self.ring_next >= self.ring_chunks.len() {
+            if self.ring >= self.radius {
+                return None;
+            }
+            self.ring += 1;
+            self.ring_chunks = self.chunks_in_ring(self.ring);
+            self.ring_next = 0;
+        }
+        self.ring_next += 1;
+        self.next += 1;
+        Some(self.ring_chunks[self.ring_next - 1])
+    }
+    /// Skip the first `done` chunks, for resuming.
+    fn skip(&mut self, done: usize) {
+        let done = done.min(self.total);
+        // Whole rings at a time, then chunk by chunk through the ring `done` falls in.
+        while self.next < done && self.ring < self.radius {
+            let ring = self.chunks_in_ring(self.ring + 1);
+            if self.next + ring.len() > done {
+                break;
+            }
+            self.ring += 1;
+            self.next += ring.len();
+        }
+        while self.next < done && self.next_chunk().is_some() {}
+    }
+    /// Chunks that have been generated or loaded. Everything before this many in the order is done.
+    fn done(&self) -> usize {
+        self.in_flight.first().map_or(self.next, |(index, _)| *index)
+    }
+    pub fn total(&self) -> usize {
+        self.total
+    }
+    /// Request more chunks as earlier ones arrive. Returns true once every chunk is done.
+    fn step(&mut self, game: &mut Game, behind: bool) -> bool {
+        let mut world = game.world.get_world();
+        self.in_flight.retain(|(_, coords)| world.is_chunk_pending(coords));
+        let limit = if behind { 1 } else { MAX_IN_FLIGHT };
+        while self.in_flight.len() < limit {
+            let index = self.next;
+            let coords = match self.next_chunk() {
+                Some(coords) => coords,
+                None => break,
+            };
+            world.request_chunk(&coords);
+            if world.is_chunk_pending(&coords) {
+                self.in_flight.push((index, coords));
+            }
+        }
+        self.ring >= self.radius && self.ring_next >= self.ring_chunks.len() && self.in_flight.is_empty()
+    }
+    /// Save the chunks generated so far. The progress is written by `save_finished` once they
+    /// are on disk, since chunks before the saved count are skipped when resuming.
+    fn checkpoint(&mut self, game: &mut Game) {
+        if self.saving.is_some() {
+            return;
+        }
+        let saved = game.in_dimension(self.dimension, |game| {
+            game.world.get_world().queue_save(Some(SAVE_REQUESTER))
+        });
+        match saved {
+            Some(Ok(())) => self.saving = Some(self.done()),
+            Some(Err(e)) => log::error!("Couldn't save pregenerated chunks: {:?}", e),
+            None => {}
+        }
+    }
+    fn progress_message(&self) -> String {
+        let done = self.done();
+        let rate = (done - self.started_done) as f64 / self.started.elapsed().as_secs_f64().max(1.);
+        let eta = match rate > 0. {
+            true => {
+                let secs = ((self.total() - done) as f64 / rate) as u64;
+                format!("{}m {}s", secs / 60, secs % 60)
+            }
+            false => "unknown".to_string(),
+        };
+        format!(
+            "Pregenerating: {}/{} chunks ({:.1}%), {:.1} chunks/s, ETA {}",
+            done,
+            self.total(),
+            done as f64 * 100. / self.total().max(1) as f64,
+            rate,
+            eta
+        )
+    }
+    fn save(&self, dir: &str, done: usize) -> anyhow::Result<()> {
+        let mut tag = CompoundTag::new();
+        tag.insert_i32("Radius", self.radius);
+        tag.insert_i8("Circle", self.circle as i8);
+        tag.insert_i32("CenterX", self.center.x);
+        tag.insert_i32("CenterZ", self.center.z);
+        tag.insert_i32("Done", done as i32);
+        tag.insert_str("Requester", &self.requester);
+        let mut file = std::fs::File::create(format!("{}/pregen.dat", dir))?;
+        nbt::encode::write_compound_tag(&mut file, &tag)?;
+        Ok(())
+    }
+    fn load(dimension: i8, dir: &str) -> anyhow::Result<Self> {
+        let mut file = std::fs::File::open(format!("{}/pregen.dat", dir))?;
+        let tag = nbt::decode::read_compound_tag(&mut file)?;
+        let center = ChunkCoords {
+            x: tag.get_i32("CenterX").or(Err(anyhow::anyhow!("Tag read error")))?,
+            z: tag.get_i32("CenterZ").or(Err(anyhow::anyhow!("Tag read error")))?,
+        };
+        let radius = tag.get_i32("Radius").or(Err(anyhow::anyhow!("Tag read error")))?;
+        check_radius(radius)?;
+        let mut pregen = Self::new(
+            dimension,
+            center,
+            radius,
+            tag.get_i8("Circle").or(Err(anyhow::anyhow!("Tag read error")))? != 0,
+            tag.get_str("Requester").unwrap_or("CONSOLE"),
+        );
+        let done = tag.get_i32("Done").or(Err(anyhow::anyhow!("Tag read error")))?;
+        pregen.skip(done.max(0) as usize);
+        pregen.started_done = pregen.next;
+        Ok(pregen)
+    }
+}
+fn check_radius(radius: i32) -> anyhow::Result<()> {
+    if !(0..=MAX_RADIUS).contains(&radius) {
+        return Err(anyhow::anyhow!("The radius must be between 0 and {} chunks", MAX_RADIUS));
+    }
+    Ok(())
+}
+fn remove_progress(dir: &str) {
+    let path = format!("{}/pregen.dat", dir);
+    if std::path::Path::new(&path).exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::error!("Couldn't remove {}: {:?}", path, e);
+        }
+    }
+}
+/// Start generating the chunks within `radius` chunks of `dimension`'s spawn.
+pub fn start(game: &mut Game, dimension: i8, radius: i32, circle: bool, requester: &str) -> anyhow::Result<()> {
+    if game.pregen.is_some() {
+        return Err(anyhow::anyhow!("A pregen is already running"));
+    }
+    check_radius(radius)?;
+    let center = game
+        .in_dimension(dimension, |game| {
+            ChunkCoords::from_pos(&game.world.get_world().spawn_position)
+        })
+        .ok_or(anyhow::anyhow!("No world {}", dimension))?;
+    let pregen = Pregen::new(dimension, center, radius, circle, requester);
+    let message = format!(
+        "Pregenerating {} chunks in {}",
+        pregen.total(),
+        game.world_name(dimension)
+    );
+    game.op_status_message(requester, &message);
+    game.pregen = Some(pregen);
+    Ok(())
+}
+/// Stop the running pregen, forgetting its progress. Returns false if there wasn't one.
+pub fn stop(game: &mut Game) -> bool {
+    match game.pregen.take() {
+        Some(pregen) => {
+            remove_progress(&game.world_name(pregen.dimension));
+            true
+        }
+        None => false,
+    }
+}
+/// Pick up a pregen a restart interrupted.
+pub fn resume(game: &mut Game) {
+    for (dimension, name) in game.world_names() {
+        match Pregen::load(dimension, &name) {
+            Ok(pregen) => {
+                log::info!(
+                    "Resuming pregen of {} at {}/{} chunks",
+                    name,
+                    pregen.next,
+                    pregen.total()
+                );
+                game.pregen = Some(pregen);
+                return;
+            }
+            Err(e) => {
+                if std::path::Path::new(&format!("{}/pregen.dat", name)).exists() {
+                    log::error!("Couldn't resume pregen: {:?}", e);
+                }
+            }
+        }
+    }
+}
+pub fn tick(game: &mut Game) {
+    let mut pregen = match game.pregen.take() {
+        Some(pregen) => pregen,
+        None => return,
+    };
+    // Back off while the server can't keep its tick rate.
+    let behind = game.tps > 0. && game.tps < CONFIGURATION.tps as f64 * 0.9;
+    let name = game.world_name(pregen.dimension);
+    let finished = match game.in_dimension(pregen.dimension, |game| pregen.step(game, behind)) {
+        Some(finished) => finished,
+        None => {
+            log::error!("Stopping pregen, world {} is gone", pregen.dimension);
+            return;
+        }
+    };
+    if finished {
+        let saved = game.in_dimension(pregen.dimension, |game| game.world.get_world().queue_save(None));
+        if let Some(Err(e)) = saved {
+            log::error!("Couldn't save pregenerated chunks: {:?}", e);
+        }
+        remove_progress(&name);
+        let message = format!(
+            "Pregenerated {} chunks in {} in {}s",
+            pregen.total(),
+            name,
+            pregen.started.elapsed().as_secs()
+        );
+        game.op_status_message(&pregen.requester, &message);
+        return;
+    }
+    if pregen.last_report.elapsed() >= REPORT_INTERVAL {
+        pregen.last_report = Instant::now();
+        if game.world_saving {
+            pregen.checkpoint(game);
+        }
+        game.op_status_message(&pregen.requester, &pregen.progress_message());
+    }
+    game.pregen = Some(pregen);
+}
+/// Write the progress once the checkpoint save it covers has finished.
+pub fn save_finished(game: &mut Game, result: &SaveResult) {
+    let name = game.world_name(game.dimension);
+    let pregen = match game.pregen.as_mut() {
+        Some(pregen) if pregen.dimension == game.dimension => pregen,
+        _ => return,
+    };
+    let done = match pregen.saving.take() {
+        Some(done) => done,
+        None => return,
+    };
+    if result.error.is_some() {
+        return;
+    }
+    if let Err(e) = pregen.save(&name, done) {
+        log::error!("Couldn't save pregen progress: {:?}", e);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn all_chunks(pregen: &mut Pregen) -> Vec<ChunkCoords> {
+        std::iter::from_fn(|| pregen.next_chunk()).collect()
+    }
+    #[test]
+    fn rings_cover_the_area_nearest_first() {
+        let center = ChunkCoords { x: 5, z: -3 };
+        for &circle in &[false, true] {
+            let mut pregen = Pregen::new(0, center, 6, circle, "CONSOLE");
+            let chunks = all_chunks(&mut pregen);
+            let mut expected = Vec::new();
+            for x in -6..=6 {
+                for z in -6..=6 {
+                    if !circle || x * x + z * z <= 36 {
+                        expected.push((x, z));
+                    }
+                }
+            }
+            expected.sort_by_key(|&(x, z): &(i32, i32)| (x.abs().max(z.abs()), x, z));
+            let offsets: Vec<(i32, i32)> = chunks.iter().map(|c| (c.x - center.x, c.z - center.z)).collect();
+            assert_eq!(offsets, expected);
+            assert_eq!(pregen.total(), expected.len());
+        }
+    }
+    #[test]
+    fn resuming_skips_what_was_done() {
+        let center = ChunkCoords { x: 0, z: 0 };
+        let all = all_chunks(&mut Pregen::new(0, center, 9, true, "CONSOLE"));
+        for &done in &[0, 1, 40, 81, all.len()] {
+            let mut pregen = Pregen::new(0, center, 9, true, "CONSOLE");
+            pregen.skip(done);
+            assert_eq!(pregen.next, done);
+            assert_eq!(all_chunks(&mut pregen), all[done..].to_vec());
+        }
+    }
+    #[test]
+    fn radius_is_capped() {
+        assert!(check_radius(MAX_RADIUS).is_ok());
+        assert!(check_radius(MAX_RADIUS + 1).is_err());
+        assert!(check_radius(-1).is_err());
+        assert_eq!(Pregen::new(0, ChunkCoords { x: 0, z: 0 }, MAX_RADIUS, false, "CONSOLE").total(), 8001 * 8001);
+    }
+}
@@ -36,6 +36,12 @@ async fn main() -> anyhow::Result<()> {
         game.poll_new_players(&mut server)?;
         Ok(())
     });
+    systems.add_system("update_online_players", |game| {
+        let obj = game.objects.clone();
+        let server = obj.get_mut::<server::Server>()?;
+        server.set_online_players(game.players.0.lock().unwrap().len());
+        Ok(())
+    });
     systems.add_system("tick_game_ticks", |game| {
         game.ticks += 1;
         Ok(())
@@ -49,8 +55,12 @@ async fn main() -> anyhow::Result<()> {
     systems.add_system("tick_entities", |game| {
         let obj = game.objects.clone();
         let mut server = obj.get_mut::<server::Server>()?;
-        systems::tick_entities(game, &mut server)?;
-        Ok(())
+        game.for_each_dimension(|game| systems::tick_entities(game, &mut server))
+    });
+    systems.add_system("chunk_loads", |game| {
+        let obj = game.objects.clone();
+        let mut server = obj.get_mut::<server::Server>()?;
+        game.for_each_dimension(|game| systems::chunk_loads(game, &mut server))
     });
     systems.add_system("tick_players", |game| {
         let obj = game.objects.clone();
@@ -67,8 +77,7 @@ async fn main() -> anyhow::Result<()> {
     systems.add_system("entity_positions", |game| {
         let obj = game.objects.clone();
         let mut server = obj.get_mut::<server::Server>()?;
-        systems::entity_positions(game, &mut server)?;
-        Ok(())
+        game.for_each_dimension(|game| systems::entity_positions(game, &mut server))
     });
     systems.add_system("ping", |game| {
         let obj = game.objects.clone();
@@ -91,27 +100,47 @@ async fn main() -> anyhow::Result<()> {
     systems.add_system("block_updates", |game| {
         let obj = game.objects.clone();
         let mut server = obj.get_mut::<server::Server>()?;
-        systems::block_updates(game, &mut server)?;
-        Ok(())
+        game.for_each_dimension(|game| systems::block_updates(game, &mut server))
     });
     systems.add_system("check_loaded_chunks", |game| {
         let obj = game.objects.clone();
         let mut server = obj.get_mut::<server::Server>()?;
-        systems::check_loaded_chunks(game, &mut server)?;
-        Ok(())
+        game.for_each_dimension(|game| systems::check_loaded_chunks(game, &mut server))
+    });
+    systems.add_system("unload_chunks", |game| {
+        let obj = game.objects.clone();
+        let mut server = obj.get_mut::<server::Server>()?;
+        game.for_each_dimension(|game| systems::unload_chunks(game, &mut server))
     });
     systems.add_system("random_ticks", |game| {
-        game.random_ticks();
-        Ok(())
+        game.for_each_dimension(|game| {
+            game.random_ticks();
+            Ok(())
+        })
+    });
+    systems.add_system("scheduled_ticks", |game| {
+        game.for_each_dimension(|game| {
+            game.scheduled_ticks();
+            Ok(())
+        })
+    });
+    systems.add_system("spawn_chunk_entities", |game| {
+        game.for_each_dimension(|game| {
+            game.spawn_chunk_entities();
+            Ok(())
+        })
     });
     systems.add_system("tile_entity_ticks", |game| {
-        game.tile_entity_ticks();
-        Ok(())
+        game.for_each_dimension(|game| {
+            game.tile_entity_ticks();
+            Ok(())
+        })
     });
     systems.add_system("world_block_updates", |game| {
-        let players = game.players.clone();
-        //game.world.send_block_updates(players);
-        Ok(())
+        game.for_each_dimension(|game| {
+            game.neighbour_updates();
+            Ok(())
+        })
     });
     systems.add_system("handle_events", |game| {
         let obj = game.objects.clone();
@@ -133,10 +162,22 @@ async fn main() -> anyhow::Result<()> {
             .run_tasks(game);
         Ok(())
     });
+    systems.add_system("portal_travel", |game| {
+        game::portals::tick(game);
+        Ok(())
+    });
+    systems.add_system("pregen", |game| {
+        game::pregen::tick(game);
+        Ok(())
+    });
     systems.add_system("check_world_save", |game| {
         game.check_world_save();
         Ok(())
     });
+    systems.add_system("poll_world_saves", |game| {
+        game.poll_world_saves();
+        Ok(())
+    });
 /*     let mut manager = PluginManager::new();
     load_plugins(&mut manager); */
     let (async_channel_send, async_channel_recv) = flume::unbounded();
@@ -210,7 +251,7 @@ fn setup_tick_loop(mut game: game::Game) -> TickLoop {
             tick_counter += 1;
         })) {
             game.save_playerdata().unwrap();
-            game.world.get_world().to_file(&CONFIGURATION.level_name);
+            let _ = game.save_world_blocking();
             println!("========================================");
             println!("\nPlease report this!\n");
             println!("========================================");
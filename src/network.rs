@@ -13,14 +13,19 @@ use crate::configuration::CONFIGURATION;
 // never used flume before, looks cool and feather uses it
 use flume::Sender;
 use worker::Worker;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use crate::api::SessionBackend;
 // this is rust's OOP
 pub struct Listener {
     listener: TcpListener,
     new_players: Sender<NewPlayer>,
     async_chat: Sender<AsyncChatCommand>,
+    online_players: Arc<AtomicUsize>,
+    session_backend: Arc<dyn SessionBackend>,
 }
 impl Listener {
-    pub async fn start_listening(new_players: Sender<NewPlayer>, async_chat: Sender<AsyncChatCommand>) -> anyhow::Result<()> {
+    pub async fn start_listening(new_players: Sender<NewPlayer>, async_chat: Sender<AsyncChatCommand>, online_players: Arc<AtomicUsize>, session_backend: Arc<dyn SessionBackend>) -> anyhow::Result<()> {
         let addr = format!("{}:{}", CONFIGURATION.listen_address, CONFIGURATION.listen_port);
         log::info!("Listening on {}", addr);
         let listener = TcpListener::bind(&addr).await?;
@@ -28,6 +33,8 @@ impl Listener {
             listener,
             new_players,
             async_chat,
+            online_players,
+            session_backend,
         };
         tokio::task::spawn(async move {
             listener.run().await;
@@ -43,7 +50,7 @@ impl Listener {
         }
     }
     async fn accept(&mut self, stream: TcpStream, addr: SocketAddr) {
-        let worker = Worker::new(stream, addr, self.new_players.clone(), self.async_chat.clone());
+        let worker = Worker::new(stream, addr, self.new_players.clone(), self.async_chat.clone(), self.online_players.clone(), self.session_backend.clone());
         worker.begin();
     }
 }
\ No newline at end of file
@@ -4,8 +4,17 @@ use super::worker::Worker;
 use super::packet::{ClientPacket, ClientPacketTypes, ServerPacket};
 use super::ids::EntityID;
 use crate::server::NewPlayer;
-pub async fn handle_connection(worker: &mut Worker) -> anyhow::Result<NewPlayer> {
+use crate::configuration::CONFIGURATION;
+use std::sync::atomic::Ordering;
+/// Returns `Ok(None)` if the connection was a server list ping that has already been answered.
+pub async fn handle_connection(worker: &mut Worker) -> anyhow::Result<Option<NewPlayer>> {
     let packet = worker.read().await?;
+    if matches!(packet.packet_type(), ClientPacketTypes::ServerListPing) {
+        let online = worker.online_players.load(Ordering::Relaxed);
+        let reason = format!("{}§{}§{}", CONFIGURATION.server_motd, online, CONFIGURATION.max_players);
+        worker.write(ServerPacket::Disconnect { reason }).await?;
+        return Ok(None);
+    }
     if !matches!(packet.packet_type(), ClientPacketTypes::Handshake) {
         return Err(anyhow::anyhow!("Wrong packet!"));
     }
@@ -16,7 +25,12 @@ pub async fn handle_connection(worker: &mut Worker) -> anyhow::Result<NewPlayer>
     };
     // log::info!("{} attempting to log in as {}", worker.addr, handshake_packet.username);
     //log::info!("Packet: {:?}", handshake_packet);
-    let packet = ServerPacket::Handshake { connection_hash: "-".to_string() };
+    let connection_hash = if CONFIGURATION.online_mode {
+        format!("{:x}", rand::thread_rng().next_u64())
+    } else {
+        "-".to_string()
+    };
+    let packet = ServerPacket::Handshake { connection_hash: connection_hash.clone() };
     worker.write(packet).await?;
     let packet = worker.read().await?;
     if !matches!(packet.packet_type(), ClientPacketTypes::LoginRequest) {
@@ -32,10 +46,36 @@ pub async fn handle_connection(worker: &mut Worker) -> anyhow::Result<NewPlayer>
         worker.write(ServerPacket::Disconnect { reason: "Wrong version.".to_string() }).await?;
         return Err(anyhow::anyhow!("Wrong protocol version!"));
     }
+    if CONFIGURATION.online_mode {
+        let backend = worker.session_backend.clone();
+        match backend.check_session(&lr_packet.username, &connection_hash).await {
+            Ok(true) => {}
+            Ok(false) => {
+                worker.write(ServerPacket::Disconnect { reason: "Failed to verify username!".to_string() }).await?;
+                return Err(anyhow::anyhow!("{} failed to verify username", lr_packet.username));
+            }
+            Err(e) => {
+                worker.write(ServerPacket::Disconnect { reason: "Authentication servers are down.".to_string() }).await?;
+                return Err(e);
+            }
+        }
+    }
+    match crate::configuration::check_access(&lr_packet.username, &worker.addr) {
+        Ok(None) => {}
+        Ok(Some(reason)) => {
+            worker.write(ServerPacket::Disconnect { reason: reason.clone() }).await?;
+            return Err(anyhow::anyhow!("{} was refused: {}", lr_packet.username, reason));
+        }
+        Err(e) => {
+            log::error!("Could not check access for {}: {}", lr_packet.username, e);
+            worker.write(ServerPacket::Disconnect { reason: "The server could not read its access lists.".to_string() }).await?;
+            return Err(e);
+        }
+    }
     let id = EntityID::new();
     //log::info!("Packet: {:?}", lr_packet);
     //log::info!("[Connection worker] User {} logging in with entity id {}", lr_packet.username, id.0);
 /*     let packet = ServerPacket::ServerLoginRequest { entity_id: id.0, unknown: "".to_string(), unknown_2: "".to_string(), map_seed: 0, dimension: 0};
     worker.write(packet).await?; */
-    Ok(NewPlayer { username: lr_packet.username, recv_packets_recv: worker.recv_packets_recv.clone(), packet_send_sender: worker.packet_send_sender.clone(), id, addr: worker.addr})
+    Ok(Some(NewPlayer { username: lr_packet.username, recv_packets_recv: worker.recv_packets_recv.clone(), packet_send_sender: worker.packet_send_sender.clone(), id, addr: worker.addr}))
 }
\ No newline at end of file
@@ -37,6 +37,7 @@ pub enum ClientPacketTypes {
     HoldingChange,
     WindowClick,
     Transaction,
+    ServerListPing,
 }
 impl PacketType for ClientPacketTypes {
 
@@ -185,6 +186,9 @@ impl <T: tokio::io::AsyncRead>InternalReader<T> {
             0x07 => {
                 return Ok(ClientPacket::UseEntity( UseEntity { user: Self::read_int(reader).await?, target: Self::read_int(reader).await?, left_click: Self::read_byte_raw(reader).await? != 0 }));
             }
+            0xFE => {
+                return Ok(ClientPacket::ServerListPing);
+            }
             0xFF => {
                 return Ok(ClientPacket::Disconnect( Disconnect { reason: Self::read_string16(reader).await? }));
             }
@@ -397,6 +401,7 @@ pub enum ClientPacket {
     HoldingChange(HoldingChange),
     WindowClick(WindowClick),
     Transaction(Transaction),
+    ServerListPing,
 }
 impl ClientPacket {
     pub fn packet_type(&self) -> ClientPacketTypes {
@@ -461,6 +466,9 @@ impl ClientPacket {
             ClientPacket::Transaction { .. } => {
                 ClientPacketTypes::Transaction
             }
+            ClientPacket::ServerListPing => {
+                ClientPacketTypes::ServerListPing
+            }
         }
     }
 }
@@ -488,6 +496,8 @@ pub enum ServerPacket {
     SoundEffect { effect_id: i32, x: i32, y: i8, z: i32, sound_data: i32 },
     Transaction { window_id: i8, action_number: i16, accepted: bool },
     BlockChange { x: i32, y: i8, z: i32, block_type: i8, block_metadata: i8 },
+    /// Several changes in one chunk. Coords are packed `x << 12 | z << 8 | y` within the chunk.
+    MultiBlockChange { chunk_x: i32, chunk_z: i32, coords: Vec<i16>, block_types: Vec<i8>, metadata: Vec<i8> },
     EntityEquipment { eid: i32, slot: i16, item_id: i16, damage: i16 },
     PlayerBlockPlacement { x: i32, y: i8, z: i32, direction: i8, block_or_item_id: i16, amount: i8, damage: i16 },
     EntityVelocity { eid: i32, velocity_x: i16, velocity_y: i16, velocity_z: i16 },
@@ -660,6 +670,22 @@ impl ServerPacket {
                 builder.insert_byte(*block_metadata);
                 builder.build(0x35)
             }
+            ServerPacket::MultiBlockChange { chunk_x, chunk_z, coords, block_types, metadata } => {
+                let mut builder = ClassicPacketBuilder::new();
+                builder.insert_int(*chunk_x);
+                builder.insert_int(*chunk_z);
+                builder.insert_short(coords.len() as i16);
+                for coord in coords {
+                    builder.insert_short(*coord);
+                }
+                for block_type in block_types {
+                    builder.insert_byte(*block_type);
+                }
+                for meta in metadata {
+                    builder.insert_byte(*meta);
+                }
+                builder.build(0x34)
+            }
             ServerPacket::Transaction { window_id, action_number, accepted } => {
                 let mut builder = ClassicPacketBuilder::new();
                 builder.insert_byte(*window_id);
@@ -822,4 +848,26 @@ impl ServerPacket {
     } 
 }
 impl Packet for ServerPacket {}
-impl Packet for ClientPacket {}
\ No newline at end of file
+impl Packet for ClientPacket {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn multi_block_change_layout() {
+        let packet = ServerPacket::MultiBlockChange {
+            chunk_x: -2,
+            chunk_z: 5,
+            coords: vec![3 << 12 | 15 << 8 | 64, 1 << 12 | 2 << 8 | 5],
+            block_types: vec![1, 20],
+            metadata: vec![0, 7],
+        };
+        let mut expected = vec![0x34];
+        expected.extend_from_slice(&(-2i32).to_be_bytes());
+        expected.extend_from_slice(&5i32.to_be_bytes());
+        expected.extend_from_slice(&2i16.to_be_bytes());
+        expected.extend_from_slice(&0x3f40i16.to_be_bytes());
+        expected.extend_from_slice(&0x1205i16.to_be_bytes());
+        expected.extend_from_slice(&[1, 20, 0, 7]);
+        assert_eq!(packet.as_bytes().unwrap(), expected);
+    }
+}
@@ -295,12 +295,17 @@ pub fn handle_packet(
             pos.z = 5.0;
             let mut pos = pos.clone();
             player.set_last_position(pos);
+            player.set_dead(false);
+            if game.world.get_world().dimension != 0 {
+                // Dying in the nether respawns in the overworld.
+                game.move_to_dimension(&player, 0, pos);
+                return Ok(());
+            }
             player.set_position(pos);
             player.write_packet(ServerPacket::Respawn {
-                world: player.get_world(),
+                world: game.world.get_world().dimension,
             });
             //let id = player.id.0;
-            player.set_dead(false);
             game.hide_player(&player.unwrap().unwrap())?;
             //game.broadcast_to_loaded(&player, ServerPacket::DestroyEntity { eid: id })?;
             //game.broadcast_packet(ServerPacket::EntityStatus { eid: player.id.0, entity_status: 0x00 })?;
@@ -1765,6 +1770,9 @@ pub fn handle_packet(
                     //player.write(ServerPacket::BlockChange { x: packet.x, y: packet.y, z: packet.z, block_type: item.id as i8, block_metadata: 0x00 });
                     log::debug!("Setting block.");
                     block.set_type(item.id as u8);
+                    let position = BlockPosition::new(packet.x, packet.y as i32, packet.z);
+                    game.queue_block_change(position, block.get_type(), block.get_meta());
+                    crate::game::fluids::block_placed(game, position);
                     success = true;
                 } else {
                     player.write(ServerPacket::BlockChange {
@@ -1812,20 +1820,6 @@ pub fn handle_packet(
                                 });
                                 return Ok(());
                             }
-                            let uspos = BlockPosition {
-                                x: packet.x,
-                                y: packet.y as i32,
-                                z: packet.z,
-                            };
-                            drop(block);
-                            for position in uspos.all_directions() {
-                                let block = game.world.get_block(&position);
-                                if let Some(block) = registry.get_item(block.get_type() as i16) {
-                                    if let Some(block) = block.get_item().as_block() {
-                                        block.nearby_block_update(game, uspos, position);
-                                    }
-                                }
-                            }
                         } else {
                             return Ok(());
                         }
@@ -1846,6 +1840,7 @@ pub fn handle_packet(
                         return Ok(());
                     } */
                     block.set_type(0);
+                    game.queue_block_change(BlockPosition::new(packet.x, packet.y as i32, packet.z), 0, block.get_meta());
                     log::debug!("orig_type: {}", orig_type);
                     game.broadcast_to_loaded(
                         &player,
@@ -1930,30 +1925,13 @@ pub fn handle_packet(
                         });
                         return Ok(());
                     }
-                    if let Some(item) = registry.get_item(orig_type as i16) {
-                        if let Some(block) = item.get_item().as_block() {
-                            let uspos = BlockPosition {
-                                x: packet.x,
-                                y: packet.y as i32,
-                                z: packet.z,
-                            };
-                            drop(block);
-                            for position in uspos.all_directions() {
-                                let block = game.world.get_block(&position);
-                                if let Some(block) = registry.get_item(block.get_type() as i16) {
-                                    if let Some(block) = block.get_item().as_block() {
-                                        block.nearby_block_update(game, uspos, position);
-                                    }
-                                }
-                            }
-                        } else {
-                            return Ok(());
-                        }
-                    } else {
-                        return Ok(());
+                    match registry.get_item(orig_type as i16) {
+                        Some(item) if item.get_item().as_block().is_some() => {}
+                        _ => return Ok(()),
                     }
                     let block = game.world.get_block(&BlockPosition::new(packet.x, packet.y as i32, packet.z));
                     block.set_type(0);
+                    game.queue_block_change(BlockPosition::new(packet.x, packet.y as i32, packet.z), 0, block.get_meta());
                     log::debug!("orig_type: {}", orig_type);
                     game.broadcast_to_loaded(
                         &player,
@@ -9,6 +9,9 @@ use super::handshake;
 use super::packet;
 use super::packet::{PacketReader, PacketWriter};
 use crate::network::packet::{ServerPacket, ClientPacket};
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use crate::api::SessionBackend;
 pub struct Worker {
     reader: PacketReader,
     writer: PacketWriter,
@@ -17,16 +20,18 @@ pub struct Worker {
     async_chat: Sender<AsyncChatCommand>,
     pub packet_send_sender: Sender<ServerPacket>,
     pub recv_packets_recv: Receiver<ClientPacket>,
+    pub online_players: Arc<AtomicUsize>,
+    pub session_backend: Arc<dyn SessionBackend>,
 }
 impl Worker {
-    pub fn new(stream: TcpStream, addr: SocketAddr, new_players: Sender<NewPlayer>, async_chat: Sender<AsyncChatCommand>) -> Self {
+    pub fn new(stream: TcpStream, addr: SocketAddr, new_players: Sender<NewPlayer>, async_chat: Sender<AsyncChatCommand>, online_players: Arc<AtomicUsize>, session_backend: Arc<dyn SessionBackend>) -> Self {
         let (reader, writer) = stream.into_split();
 
         let (recv_packets_send, recv_packets_recv) = flume::unbounded();
         let (packet_send_sender, packet_send_recv) = flume::unbounded();
         let reader = PacketReader::new(reader, recv_packets_send.clone());
         let writer = PacketWriter::new(writer, packet_send_recv.clone());
-        Self { reader, writer, addr, new_players, packet_send_sender: packet_send_sender.clone(), recv_packets_recv: recv_packets_recv.clone(), async_chat }
+        Self { reader, writer, addr, new_players, packet_send_sender: packet_send_sender.clone(), recv_packets_recv: recv_packets_recv.clone(), async_chat, online_players, session_backend }
     }
     pub fn begin(self) {
         tokio::task::spawn(async move {
@@ -35,7 +40,10 @@ impl Worker {
     }
     async fn run(mut self) -> anyhow::Result<()> {
         match handshake::handle_connection(&mut self).await {
-            Ok(res) => {
+            Ok(None) => {
+                log::debug!("[Connection worker] Answered server list ping from {:?}", self.addr);
+            }
+            Ok(Some(res)) => {
                 //log::debug!("Sending");
                 let user = res.username.clone();
                 self.new_players.send_async(res).await?;
@@ -10,6 +10,9 @@ use std::cell::RefCell;
 use std::time::Duration;
 use std::time::Instant;
 use std::net::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::api::APIHelper;
+use crate::configuration::CONFIGURATION;
 pub struct NewPlayer {
     pub username: String,
     pub recv_packets_recv: Receiver<ClientPacket>,
@@ -65,12 +68,19 @@ pub struct Server {
     async_chat: Sender<AsyncChatCommand>,
     pub clients: Arc<RefCell<HashMap<EntityID, Arc<RefCell<Client>>>>>,
     pub last_ping_time: Instant,
+    online_players: Arc<AtomicUsize>,
 }
 impl Server {
     pub async fn bind(async_chat: Sender<AsyncChatCommand>) -> anyhow::Result<Self> {
         let (new_players_send, new_players) = flume::bounded(4);
-        Listener::start_listening(new_players_send, async_chat.clone()).await?;
-        Ok( Self { new_players, clients: Arc::new(RefCell::new(HashMap::new())), last_ping_time: Instant::now(), async_chat: async_chat.clone() } )
+        let online_players = Arc::new(AtomicUsize::new(0));
+        let session_backend = Arc::new(APIHelper::new(&CONFIGURATION.session_server));
+        Listener::start_listening(new_players_send, async_chat.clone(), online_players.clone(), session_backend).await?;
+        Ok( Self { new_players, clients: Arc::new(RefCell::new(HashMap::new())), last_ping_time: Instant::now(), async_chat: async_chat.clone(), online_players } )
+    }
+    /// Publishes the online player count for the connection workers to report in server list pings.
+    pub fn set_online_players(&self, count: usize) {
+        self.online_players.store(count, Ordering::Relaxed);
     }
     pub fn register(self, game: &mut Game) {
         game.insert_object(self);
@@ -3,6 +3,7 @@ use crate::game::{Game, PlayerRef, Player, Message, DamageType, ChunkCoords};
 use crate::network::ids::{EntityID, IDS};
 use crate::network::packet::{ClientPacket, ServerPacket};
 use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::{Duration, Instant};
 use crate::configuration::CONFIGURATION;
 pub struct Systems {
@@ -86,21 +87,76 @@ pub fn tick_players(game: &mut Game, server: &mut Server) -> anyhow::Result<()>
     let interval = Duration::from_millis(750);
     let players = game.players.0.lock().unwrap().clone();
     for player in players.iter() {
-        player.1.tick(game)?;
+        let dimension = player.1.get_world();
+        game.in_dimension(dimension, |game| player.1.tick(game)).unwrap_or(Ok(()))?;
     }
     Ok(())
 }
+/// A chunk with more changes than this in one tick is sent again whole instead.
+const FULL_CHUNK_CHANGES: usize = 256;
+/// Turn block changes into one packet per chunk, keeping only the last change to each block.
+/// Chunks with more than `FULL_CHUNK_CHANGES` changes come back separately to be sent in full.
+fn batch_block_changes(updates: Vec<crate::game::Block>) -> (Vec<(ChunkCoords, ServerPacket)>, Vec<ChunkCoords>) {
+    let mut changes: HashMap<ChunkCoords, BTreeMap<i16, crate::game::Block>> = HashMap::new();
+    for update in updates {
+        if !(0..128).contains(&update.position.y) {
+            continue;
+        }
+        let chunk = ChunkCoords { x: update.position.x >> 4, z: update.position.z >> 4 };
+        let coord = ((update.position.x & 15) << 12 | (update.position.z & 15) << 8 | update.position.y) as i16;
+        changes.entry(chunk).or_default().insert(coord, update);
+    }
+    let mut packets = Vec::new();
+    let mut full = Vec::new();
+    for (chunk, blocks) in changes {
+        if blocks.len() > FULL_CHUNK_CHANGES {
+            full.push(chunk);
+            continue;
+        }
+        let packet = if blocks.len() == 1 {
+            let update = blocks.values().next().unwrap();
+            ServerPacket::BlockChange { x: update.position.x, y: update.position.y as i8, z: update.position.z, block_type: update.block.b_type as i8, block_metadata: update.block.b_metadata as i8 }
+        } else {
+            let (coords, blocks): (Vec<i16>, Vec<crate::game::Block>) = blocks.into_iter().unzip();
+            ServerPacket::MultiBlockChange {
+                chunk_x: chunk.x,
+                chunk_z: chunk.z,
+                coords,
+                block_types: blocks.iter().map(|update| update.block.b_type as i8).collect(),
+                metadata: blocks.iter().map(|update| update.block.b_metadata as i8).collect(),
+            }
+        };
+        packets.push((chunk, packet));
+    }
+    (packets, full)
+}
 pub fn block_updates(game: &mut Game, server: &mut Server) -> anyhow::Result<()> {
-    for _ in 0..game.block_updates.len() {
-        let update = game.block_updates.pop().unwrap();
-        if let Some(chunk) = game.world.get_world().chunks.get_mut(&update.position.to_chunk_coords()) {
-            //chunk.calculate_heightmap()?;
+    let (mut packets, full) = batch_block_changes(std::mem::take(&mut game.block_updates));
+    // Chunks decorated after they were sent go out again in full.
+    let mut resend = std::mem::take(&mut game.world.get_world().resend_chunks);
+    packets.retain(|(chunk, _)| !resend.contains(chunk));
+    for chunk in full {
+        if !resend.contains(&chunk) {
+            resend.push(chunk);
+        }
+    }
+    let clients = game.players.0.lock().unwrap();
+    for client in clients.iter() {
+        if client.1.get_world() != game.dimension {
+            continue;
         }
-        let clients = game.players.0.lock().unwrap();
+        let loaded = client.1.get_loaded_chunks();
+        for (chunk, packet) in packets.iter() {
+            if loaded.contains(chunk) {
+                client.1.write_packet(packet.clone());
+            }
+        }
+    }
+    for chunk in resend {
         for client in clients.iter() {
-            if client.1.get_loaded_chunks().contains(&update.position.to_chunk_coords()) {
-            //let mut cl = client.1.borrow_mut();
-            client.1.write_packet(ServerPacket::BlockChange { x: update.position.x, y: update.position.y as i8, z: update.position.z , block_type: update.block.b_type as i8, block_metadata: update.block.b_metadata as i8 });
+            if client.1.get_world() == game.dimension && client.1.get_loaded_chunks().contains(&chunk) {
+                let sender = client.1.unwrap()?.packet_send_sender.clone();
+                game.world.get_world().chunk_to_packets(chunk, sender)?;
             }
         }
     }
@@ -122,8 +178,16 @@ pub fn sync_positions(game: &mut Game, server: &mut Server) -> anyhow::Result<()
     }
     Ok(())
 }
+/// Make the chunks the worker threads have finished resident. Players waiting on them get them
+/// from their send queues.
+pub fn chunk_loads(game: &mut Game, server: &mut Server) -> anyhow::Result<()> {
+    game.world.get_world().poll_chunk_loads();
+    Ok(())
+}
 pub fn check_loaded_chunks(game: &mut Game, server: &mut Server) -> anyhow::Result<()> {
-    let players = game.players.0.lock().unwrap().clone();
+    let dimension = game.dimension;
+    let mut players = game.players.0.lock().unwrap().clone();
+    players.retain(|_, player| player.get_world() == dimension);
     //log::info!("Loaded chunks: {:?}", game.loaded_chunks.0);
     game.loaded_chunks.0.retain(|chunk, lifetime| {
         if *lifetime < 1200 {
@@ -145,6 +209,56 @@ pub fn check_loaded_chunks(game: &mut Game, server: &mut Server) -> anyhow::Resu
     });
     Ok(())
 }
+/// Save and unload chunks that have been out of every player's view for the grace period. Past
+/// `max_loaded_chunks` the longest idle chunks are unloaded early. Spawn chunks always stay loaded.
+pub fn unload_chunks(game: &mut Game, server: &mut Server) -> anyhow::Result<()> {
+    const INTERVAL: u128 = 20;
+    if game.ticks % INTERVAL != 0 {
+        return Ok(());
+    }
+    let mut in_view = HashSet::new();
+    for (_, player) in game.players.iter() {
+        if player.get_world() != game.dimension {
+            continue;
+        }
+        in_view.extend(player.get_loaded_chunks());
+        let position = player.get_position_clone();
+        let center = ChunkCoords::from_pos(&position);
+        let distance = player.get_view_distance();
+        for x in -distance..=distance {
+            for z in -distance..=distance {
+                in_view.insert(ChunkCoords { x: center.x + x, z: center.z + z });
+            }
+        }
+    }
+    let mut world = game.world.get_world();
+    let resident: Vec<ChunkCoords> = world.chunks.keys().cloned().collect();
+    let mut idle = Vec::new();
+    for coords in resident.iter() {
+        if in_view.contains(coords) || world.is_spawn_chunk(coords) {
+            world.idle_chunks.remove(coords);
+            continue;
+        }
+        let ticks = world.idle_chunks.entry(*coords).or_insert(0);
+        *ticks += INTERVAL;
+        idle.push((*coords, *ticks));
+    }
+    drop(world);
+    idle.sort_by(|a, b| b.1.cmp(&a.1));
+    let mut over_cap = resident.len().saturating_sub(CONFIGURATION.max_loaded_chunks);
+    let mut to_unload = HashSet::new();
+    for (coords, ticks) in idle {
+        if ticks < CONFIGURATION.chunk_unload_grace_period && over_cap == 0 {
+            break;
+        }
+        over_cap = over_cap.saturating_sub(1);
+        to_unload.insert(coords);
+    }
+    if !to_unload.is_empty() {
+        game.unload_chunks(&to_unload);
+    }
+    Ok(())
+}
 pub fn update_crouch(game: &mut Game, server: &mut Server, player_upd: Arc<PlayerRef>) -> anyhow::Result<()> {
     log::debug!("update_crouch called!");
     let len = game.players.0.lock().unwrap().len().clone();
@@ -271,6 +385,9 @@ pub fn entity_positions(game: &mut Game, server: &mut Server) -> anyhow::Result<
             continue;
         }; */
         let mut player = list2.1; // .borrow_mut();
+        if player.get_world() != game.dimension {
+            continue;
+        }
         // drop(list);
         let mut packets = Vec::new();
         for id in player.unwrap().unwrap().rendered_entities.iter_mut() {
@@ -336,4 +453,61 @@ pub fn cull_players(game: &mut Game, server: &mut Server) -> anyhow::Result<()>
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::BlockPosition;
+    fn change(x: i32, y: i32, z: i32, b_type: u8) -> crate::game::Block {
+        crate::game::Block {
+            position: BlockPosition::new(x, y, z),
+            block: crate::world::chunks::Block { b_type, b_metadata: 0, b_light: 0, b_skylight: 0 },
+        }
+    }
+    #[test]
+    fn single_change_is_a_block_change() {
+        let (packets, full) = batch_block_changes(vec![change(-3, 70, 20, 1), change(-3, 70, 20, 4)]);
+        assert!(full.is_empty());
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].0, ChunkCoords { x: -1, z: 1 });
+        match &packets[0].1 {
+            ServerPacket::BlockChange { x, y, z, block_type, .. } => {
+                assert_eq!((*x, *y, *z, *block_type), (-3, 70, 20, 4));
+            }
+            packet => panic!("expected a BlockChange, got {:?}", packet),
+        }
+    }
+    #[test]
+    fn changes_are_batched_per_chunk() {
+        let updates = vec![change(1, 5, 2, 1), change(3, 64, 15, 2), change(1, 5, 2, 3), change(17, 5, 2, 4), change(1, 200, 2, 5)];
+        let (packets, full) = batch_block_changes(updates);
+        assert!(full.is_empty());
+        assert_eq!(packets.len(), 2);
+        let (_, packet) = packets.iter().find(|(chunk, _)| *chunk == ChunkCoords { x: 0, z: 0 }).unwrap();
+        match packet {
+            ServerPacket::MultiBlockChange { chunk_x, chunk_z, coords, block_types, metadata } => {
+                assert_eq!((*chunk_x, *chunk_z), (0, 0));
+                assert_eq!(coords, &vec![1 << 12 | 2 << 8 | 5, 3 << 12 | 15 << 8 | 64]);
+                assert_eq!(block_types, &vec![3, 2]);
+                assert_eq!(metadata.len(), 2);
+            }
+            packet => panic!("expected a MultiBlockChange, got {:?}", packet),
+        }
+    }
+    #[test]
+    fn busy_chunks_are_sent_in_full() {
+        let mut updates = Vec::new();
+        for y in 0..(FULL_CHUNK_CHANGES as i32 + 1) / 16 + 1 {
+            for x in 0..16 {
+                updates.push(change(x, y, 0, 1));
+            }
+        }
+        let (packets, full) = batch_block_changes(updates.clone());
+        assert!(packets.is_empty());
+        assert_eq!(full, vec![ChunkCoords { x: 0, z: 0 }]);
+        updates.truncate(FULL_CHUNK_CHANGES);
+        let (packets, full) = batch_block_changes(updates);
+        assert_eq!(packets.len(), 1);
+        assert!(full.is_empty());
+    }
+}
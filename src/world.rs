@@ -1,2 +1,6 @@
 pub mod chunks;
-pub mod mcregion;
\ No newline at end of file
+pub mod loader;
+pub mod mcregion;
+pub mod saver;
+pub mod ticks;
+pub mod vanilla;
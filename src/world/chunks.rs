@@ -15,6 +15,11 @@ use std::collections::HashMap;
 use std::io::Write;
 use std::sync::Arc;
 use crate::game::RefContainer;
+use crate::world::loader::{ChunkLoader, LoadedChunk};
+use crate::world::saver::{SaveResult, WorldSaver};
+use crate::world::ticks::{PendingTick, TickQueue};
+use crate::game::items::ITEM_REGISTRY;
+use once_cell::sync::OnceCell;
 #[derive(Clone)]
 pub struct BlockRef {
     world: Arc<RefCell<World>>,
@@ -27,28 +32,62 @@ impl BlockRef {
 }
 impl BlockRef {
     pub fn set_type(&self, val: u8) {
-        self.world.borrow_mut().get_block_mut(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&mut Block::default()).b_type = val;
+        let mut world = self.world.borrow_mut();
+        world.update_block(self.pos.x, self.pos.y, self.pos.z, |block| block.b_type = val);
+        world.mark_dirty(&self.pos);
+        world.update_light(&self.pos);
+        world.queue_neighbour_update(self.pos);
     }
     pub fn set_meta(&self, val: u8) {
-        self.world.borrow_mut().get_block_mut(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&mut Block::default()).b_metadata = val;
+        let mut world = self.world.borrow_mut();
+        world.update_block(self.pos.x, self.pos.y, self.pos.z, |block| block.b_metadata = val);
+        world.mark_dirty(&self.pos);
+        world.queue_neighbour_update(self.pos);
+    }
+    /// Change the block without notifying its neighbours. For blocks switching between forms of
+    /// themselves, like water going still, where waking the neighbours would never settle.
+    pub fn set_quietly(&self, block_type: u8, meta: u8) {
+        let mut world = self.world.borrow_mut();
+        world.update_block(self.pos.x, self.pos.y, self.pos.z, |block| {
+            block.b_type = block_type;
+            block.b_metadata = meta;
+        });
+        world.mark_dirty(&self.pos);
+        world.update_light(&self.pos);
     }
     pub fn set_light(&self, val: u8) {
-        self.world.borrow_mut().get_block_mut(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&mut Block::default()).b_light = val;
+        let mut world = self.world.borrow_mut();
+        let mut changed = false;
+        world.update_block(self.pos.x, self.pos.y, self.pos.z, |block| {
+            changed = block.b_light != val;
+            block.b_light = val;
+        });
+        if changed {
+            world.mark_dirty(&self.pos);
+        }
     }
     pub fn set_skylight(&self, val: u8) {
-        self.world.borrow_mut().get_block_mut(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&mut Block::default()).b_skylight = val;
+        let mut world = self.world.borrow_mut();
+        let mut changed = false;
+        world.update_block(self.pos.x, self.pos.y, self.pos.z, |block| {
+            changed = block.b_skylight != val;
+            block.b_skylight = val;
+        });
+        if changed {
+            world.mark_dirty(&self.pos);
+        }
     }
     pub fn get_type(&self) -> u8 {
-        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&Block::default()).b_type
+        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or_default().b_type
     }
     pub fn get_meta(&self) -> u8 {
-        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&Block::default()).b_metadata
+        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or_default().b_metadata
     }
     pub fn get_light(&self) -> u8 {
-        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&Block::default()).b_light
+        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or_default().b_light
     }
     pub fn get_skylight(&self) -> u8 {
-        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or(&Block::default()).b_skylight
+        self.world.borrow_mut().get_block_internal(self.pos.x, self.pos.y, self.pos.z).unwrap_or_default().b_skylight
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -71,24 +110,85 @@ impl Block {
         self.b_type
     }
 }
+/// Blocks in one section.
+pub const SECTION_BLOCKS: usize = 4096;
+/// A 16x16x16 slice of a chunk, kept the way b1.7.3 sends and saves it: a byte per block id and
+/// a nibble per block for metadata, block light and sky light, all indexed by `pos_to_index`.
 #[derive(Clone)]
 pub struct ChunkSection {
-    data: Vec<Block>,
+    blocks: Vec<u8>,
+    metadata: Vec<u8>,
+    block_light: Vec<u8>,
+    sky_light: Vec<u8>,
     x: i32,
     z: i32,
     section: i8,
 }
+/// The nibble for block `index`, even indices in the low half of the byte like vanilla.
+fn get_nibble(array: &[u8], index: usize) -> u8 {
+    let byte = array[index >> 1];
+    if index & 1 == 0 {
+        byte & 15
+    } else {
+        byte >> 4
+    }
+}
+fn set_nibble(array: &mut [u8], index: usize, value: u8) {
+    let byte = &mut array[index >> 1];
+    if index & 1 == 0 {
+        *byte = (*byte & 0xf0) | (value & 15);
+    } else {
+        *byte = (*byte & 15) | (value << 4);
+    }
+}
 impl ChunkSection {
     pub fn new(x: i32, z: i32, section: i8) -> Self {
         Self {
-            data: Vec::new(),
+            blocks: vec![0; SECTION_BLOCKS],
+            metadata: vec![0; SECTION_BLOCKS / 2],
+            block_light: vec![0; SECTION_BLOCKS / 2],
+            sky_light: vec![0; SECTION_BLOCKS / 2],
             x,
             z,
             section,
         }
     }
-    pub fn get_data(&mut self) -> &mut Vec<Block> {
-        &mut self.data
+    /// A section from arrays already in its layout, such as ones cut out of a region file.
+    pub fn from_arrays(
+        x: i32,
+        z: i32,
+        section: i8,
+        blocks: Vec<u8>,
+        metadata: Vec<u8>,
+        block_light: Vec<u8>,
+        sky_light: Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        if blocks.len() != SECTION_BLOCKS
+            || [&metadata, &block_light, &sky_light].iter().any(|array| array.len() != SECTION_BLOCKS / 2)
+        {
+            return Err(anyhow::anyhow!("Section arrays are the wrong size"));
+        }
+        Ok(Self {
+            blocks,
+            metadata,
+            block_light,
+            sky_light,
+            x,
+            z,
+            section,
+        })
+    }
+    pub fn blocks(&self) -> &[u8] {
+        &self.blocks
+    }
+    pub fn metadata(&self) -> &[u8] {
+        &self.metadata
+    }
+    pub fn block_light(&self) -> &[u8] {
+        &self.block_light
+    }
+    pub fn sky_light(&self) -> &[u8] {
+        &self.sky_light
     }
 }
 impl ChunkSection {
@@ -98,201 +198,43 @@ impl ChunkSection {
         z %= 16; */
         (y + (z * 16) + (x * 16 * 16)) as usize
     }
-    pub fn get_block(&mut self, idx: usize) -> Option<&mut Block> {
-        //log::info!("Here! {}", idx);
-        let len = self.data.len();
-        let possible = self.data.get(idx);
-        if possible.is_some() {
-            return self.data.get_mut(idx);
-        } else {
-            for i in 0..idx + 5 {
-                if let None = self.data.get_mut(i) {
-                    self.data.push(Block {
-                        b_type: 0,
-                        b_metadata: 0,
-                        b_light: 0,
-                        b_skylight: 0,
-                    });
-                }
-            }
-            return self.data.get_mut(idx);
+    pub fn get_block(&self, idx: usize) -> Option<Block> {
+        Some(Block {
+            b_type: *self.blocks.get(idx)?,
+            b_metadata: get_nibble(&self.metadata, idx),
+            b_light: get_nibble(&self.block_light, idx),
+            b_skylight: get_nibble(&self.sky_light, idx),
+        })
+    }
+    pub fn set_block(&mut self, idx: usize, block: Block) {
+        if idx >= SECTION_BLOCKS {
+            return;
         }
-        None
+        self.blocks[idx] = block.b_type;
+        set_nibble(&mut self.metadata, idx, block.b_metadata);
+        set_nibble(&mut self.block_light, idx, block.b_light);
+        set_nibble(&mut self.sky_light, idx, block.b_skylight);
     }
-    pub fn to_packets_section_raw(
-        &self,
-        player: Sender<ServerPacket>,
-        has_loaded_before: &mut Vec<ChunkCoords>,
-    ) -> Option<()> {
-        //let mut packets = vec![];
-        let chunk = self;
-        //let chunk = chunk?;
-        //let coords = ChunkCoords { x: chunk.x, z: chunk.x };
-        let mut size_x = 0;
-        let mut size_y = 0;
-        let mut size_z = 0;
-        let mut z_counter = 16;
-        let mut x_counter = 128;
-        //log::info!("Len: {:?}", chunk.data.len());
-        for i in 0..chunk.data.len() + 0 {
-            if size_y < 16 {
-                size_y += 1;
-            } else if size_z < 16 {
-                if z_counter >= 16 {
-                    size_z += 1;
-                    // log::info!("+1 now {}", size_z);
-                    z_counter = 0;
-                    continue;
-                }
-                z_counter += 1;
-            } else if size_x < 16 {
-                //log::info!("Done {}", i);
-                if x_counter >= 128 {
-                    size_x += 1;
-                    //  log::info!("X+1 now {}", size_x);
-                    x_counter = 0;
-                    continue;
-                }
-                x_counter += 1;
-                //size_x += 1;
-            } else {
-                break;
-            }
+    pub fn block_type(&self, idx: usize) -> u8 {
+        self.blocks.get(idx).cloned().unwrap_or(0)
+    }
+    pub fn light(&self, channel: LightChannel, idx: usize) -> u8 {
+        if idx >= SECTION_BLOCKS {
+            return 0;
         }
-        /*         let size_y = 127;
-        let rest = chunk.data.len() % size_y;
-        let size_z = 16;
-        let size_x = 16; */
-        let mut blockdata =
-            Vec::with_capacity(chunk.data.len() + (chunk.data.len() as f32 * 1.5) as usize);
-        let mut metadata = Vec::with_capacity(chunk.data.len());
-        let mut blocklight = Vec::with_capacity(chunk.data.len());
-        let mut skylight = Vec::with_capacity(chunk.data.len());
-        for byte in &chunk.data {
-            blockdata.push(byte.b_type);
-            metadata.push(byte.b_metadata);
-            blocklight.push(byte.b_light);
-            skylight.push(byte.b_skylight);
-        }
-        //metadata.reverse();
-        blockdata.append(&mut compress_to_nibble(metadata)?);
-        blockdata.append(&mut compress_to_nibble(blocklight)?);
-        blockdata.append(&mut compress_to_nibble(skylight)?);
-        let start = Instant::now();
-        let mut data = Vec::with_capacity(blockdata.len());
-        let mut compressor =
-            flate2::write::ZlibEncoder::new(&mut data, flate2::Compression::fast());
-        compressor.write_all(&blockdata).ok()?;
-        compressor.finish().ok()?;
-        //log::info!("Compression took {}ns.", start.elapsed().as_nanos());
-        /*         let size_x = size_x - 1;
-        let size_y = size_y - 1;
-        let size_z = size_z - 1; */
-        let size_x = (size_x - 1).max(0);
-        //log::info!("size_x: {}", size_x);
-        let size_y = (size_y - 1).max(0);
-        let size_z = (size_z - 1).max(0);
-        let mut epicy = ((chunk.section * 1) as i16) * 16;
-        if chunk.section > 16 {
-            //epicy -= 1;
-        }
-        //log::info!("EPIC Y: {:?} ON SECTION {}", epicy, self.section);
-        let packet = ServerPacket::MapChunk {
-            x: chunk.x * 16,
-            y: epicy,
-            z: chunk.z * 16,
-            size_x: size_x as u8,
-            size_y: size_y as u8,
-            size_z: size_z as u8,
-            compressed_size: data.len() as i32,
-            compressed_data: data,
-        };
-        log::debug!("Packet {:?}", packet);
-        player.send(packet).ok()?;
-        //log::debug!("G");
-        return Some(());
-    }
-    pub async fn to_packets_section_async(&self, player: Sender<ServerPacket>) -> Option<()> {
-        //let mut packets = vec![];
-        let chunk = self;
-        //let chunk = chunk?;
-        //let coords = ChunkCoords { x: chunk.x, z: chunk.x };
-        let mut size_x = 0;
-        let mut size_y = 0;
-        let mut size_z = 0;
-        let mut z_counter = 16;
-        let mut x_counter = 128;
-        //log::info!("Len: {:?}", chunk.data.len());
-        for _ in 0..chunk.data.len() + 0 {
-            if size_y < 16 {
-                size_y += 1;
-            } else if size_z < 16 {
-                if z_counter >= 16 {
-                    size_z += 1;
-                    // log::info!("+1 now {}", size_z);
-                    z_counter = 0;
-                    continue;
-                }
-                z_counter += 1;
-            } else if size_x < 16 {
-                //log::info!("Done {}", i);
-                if x_counter >= 128 {
-                    size_x += 1;
-                    //  log::info!("X+1 now {}", size_x);
-                    x_counter = 0;
-                    continue;
-                }
-                x_counter += 1;
-                //size_x += 1;
-            } else {
-                break;
-            }
+        match channel {
+            LightChannel::Sky => get_nibble(&self.sky_light, idx),
+            LightChannel::Block => get_nibble(&self.block_light, idx),
+        }
+    }
+    pub fn set_light(&mut self, channel: LightChannel, idx: usize, level: u8) {
+        if idx >= SECTION_BLOCKS {
+            return;
+        }
+        match channel {
+            LightChannel::Sky => set_nibble(&mut self.sky_light, idx, level),
+            LightChannel::Block => set_nibble(&mut self.block_light, idx, level),
         }
-        /*         let size_y = 127;
-        let rest = chunk.data.len() % size_y;
-        let size_z = 16;
-        let size_x = 16; */
-        let mut blockdata =
-            Vec::with_capacity(chunk.data.len() + (chunk.data.len() as f32 * 1.5) as usize);
-        let mut metadata = Vec::with_capacity(chunk.data.len());
-        let mut blocklight = Vec::with_capacity(chunk.data.len());
-        let mut skylight = Vec::with_capacity(chunk.data.len());
-        for byte in &chunk.data {
-            blockdata.push(byte.b_type);
-            metadata.push(byte.b_metadata);
-            blocklight.push(byte.b_light);
-            skylight.push(byte.b_skylight);
-        }
-        blockdata.append(&mut compress_to_nibble(metadata)?);
-        blockdata.append(&mut compress_to_nibble(blocklight)?);
-        blockdata.append(&mut compress_to_nibble(skylight)?);
-        let data = deflate::deflate_bytes_zlib(&blockdata);
-        /*         let size_x = size_x - 1;
-        let size_y = size_y - 1;
-        let size_z = size_z - 1; */
-        let size_x = (size_x - 1).max(0);
-        //log::info!("size_x: {}", size_x);
-        let size_y = (size_y - 1).max(0);
-        let size_z = (size_z - 1).max(0);
-        let mut epicy = ((chunk.section * 1) as i16) * 16;
-        if chunk.section > 16 {
-            //epicy -= 1;
-        }
-        //log::info!("EPIC Y: {:?} ON SECTION {}", epicy, self.section);
-        let packet = ServerPacket::MapChunk {
-            x: chunk.x * 16,
-            y: epicy,
-            z: chunk.z * 16,
-            size_x: size_x as u8,
-            size_y: size_y as u8,
-            size_z: size_z as u8,
-            compressed_size: data.len() as i32,
-            compressed_data: data,
-        };
-        log::debug!("Packet {:?}", packet);
-        player.send_async(packet).await.ok()?;
-        //log::debug!("G");
-        return Some(());
     }
 }
 #[derive(Clone)]
@@ -301,18 +243,45 @@ pub struct Chunk {
     pub z: i32,
     pub data: [Option<ChunkSection>; 8],
     pub heightmap: [[i8; 16]; 16],
+    /// Saved entities and tile entities. Filled from disk until the game spawns them, and by the game right before a save.
+    pub entities: Vec<CompoundTag>,
+    pub tile_entities: Vec<CompoundTag>,
+    pub populated: bool,
+    pub last_update: i64,
+    /// Set when the chunk has changed since it was last written to disk.
+    pub dirty: bool,
+    /// Scheduled block updates, only filled while the chunk is being saved or loaded.
+    pub tile_ticks: Vec<PendingTick>,
+    /// The compressed MapChunk payload, kept until a block or light level in the chunk changes.
+    pub packet_cache: Option<Vec<u8>>,
 }
 impl Chunk {
-    pub fn to_packets_async(&mut self, player: Sender<ServerPacket>) {
-        use rayon::prelude::*;
-        self.data.par_iter().for_each(|section| {
-            if let Some(section) = section {
-                section.to_packets_section_raw(player.clone(), &mut Vec::new());
+    /// The whole column as one 16x128x16 MapChunk. Its payload is the region file arrays one
+    /// after another, compressed the first time it's asked for and reused until the chunk changes.
+    pub fn map_chunk_packet(&mut self) -> anyhow::Result<ServerPacket> {
+        let compressed_data = match &self.packet_cache {
+            Some(data) => data.clone(),
+            None => {
+                let mut data = Vec::new();
+                let mut compressor = flate2::write::ZlibEncoder::new(&mut data, flate2::Compression::default());
+                for array in self.region_arrays().iter() {
+                    compressor.write_all(array)?;
+                }
+                compressor.finish()?;
+                self.packet_cache = Some(data.clone());
+                data
             }
-        });
-        /*         for section in &self.data {
-            tokio::task::yield_now().await;
-        } */
+        };
+        Ok(ServerPacket::MapChunk {
+            x: self.x * 16,
+            y: 0,
+            z: self.z * 16,
+            size_x: 15,
+            size_y: 127,
+            size_z: 15,
+            compressed_size: compressed_data.len() as i32,
+            compressed_data,
+        })
     }
     pub fn calculate_skylight(&mut self, time: i64) -> anyhow::Result<()> {
         //log::info!("Calculating skylight for {}, {}", self.x, self.z);
@@ -320,9 +289,8 @@ impl Chunk {
             for z in 0..16 {
                 let y = self.heightmap[x as usize][z as usize];
                 //for y in y..127 {
-                self.get_block(x, y as i32, z)
-                    .ok_or(anyhow::anyhow!("Block does not exist!"))?
-                    .b_skylight = 15;
+                self.set_light(LightChannel::Sky, x, y as i32, z, 15)
+                    .ok_or(anyhow::anyhow!("Block does not exist!"))?;
                 //}
             }
         }
@@ -333,11 +301,9 @@ impl Chunk {
         for x in 0..16 {
             for z in 0..16 {
                 'y_loop: for y in (0..127).rev() {
-                    if let Some(block) = self.get_block(x, y, z) {
-                        if block.b_type != 0 {
-                            self.heightmap[x as usize][z as usize] = y as i8;
-                            break 'y_loop;
-                        }
+                    if self.block_type(x, y, z) != 0 {
+                        self.heightmap[x as usize][z as usize] = y as i8;
+                        break 'y_loop;
                     }
                 }
             }
@@ -353,28 +319,8 @@ impl Chunk {
         for section in self.data.iter() {
             if let Some(section) = section {
                 let mut tag = CompoundTag::new();
-                let mut blockdatadata = Vec::new();
-                let mut metadatadata = Vec::new();
-                let mut blocklightdata = Vec::new();
-                let mut skylightdata = Vec::new();
-                let mut metadata = Vec::with_capacity(section.data.len());
-                let mut blocklight = Vec::with_capacity(section.data.len());
-                let mut skylight = Vec::with_capacity(section.data.len());
-                for byte in &section.data {
-                    blockdatadata.push(byte.b_type as i8);
-                    metadata.push(byte.b_metadata);
-                    blocklight.push(byte.b_light);
-                    skylight.push(byte.b_skylight);
-                }
-                metadatadata.append(&mut compress_to_nibble(metadata).unwrap());
-                blocklightdata.append(&mut compress_to_nibble(blocklight).unwrap());
-                skylightdata.append(&mut compress_to_nibble(skylight).unwrap());
-                let mut newvec = Vec::new();
-                for byte in metadatadata {
-                    newvec.push(byte as i8);
-                }
-                tag.insert_i8_vec("metadata", newvec);
-                tag.insert_i8_vec("blox", blockdatadata);
+                tag.insert_i8_vec("metadata", section.metadata().iter().map(|b| *b as i8).collect());
+                tag.insert_i8_vec("blox", section.blocks().iter().map(|b| *b as i8).collect());
                 tag.insert_i32("chunkx", self.x);
                 tag.insert_i32("chunkz", self.z);
                 tag.insert_i8("section", section.section);
@@ -402,30 +348,18 @@ impl Chunk {
             let chunk_x = section.get_i32("chunkx").unwrap();
             let chunk_z = section.get_i32("chunkz").unwrap();
             let section = section.get_i8("section").unwrap();
-            let mut newvec = Vec::with_capacity(metadata.len());
-            for byte in metadata {
-                newvec.push(*byte as u8);
-            }
-            let metadata = decompress_vec(newvec).unwrap();
-            let mut data = Vec::new();
-            let mut iter = 0;
-            for block in blox {
-                data.push(Block {
-                    b_type: *block as u8,
-                    b_metadata: metadata[iter],
-                    b_light: 0,
-                    b_skylight: 0,
-                });
-                iter += 1;
-            }
             x = chunk_x;
             z = chunk_z;
-            let section = ChunkSection {
+            let section = ChunkSection::from_arrays(
+                chunk_x,
+                chunk_z,
                 section,
-                x: chunk_x,
-                z: chunk_z,
-                data,
-            };
+                blox.iter().map(|b| *b as u8).collect(),
+                metadata.iter().map(|b| *b as u8).collect(),
+                vec![0; SECTION_BLOCKS / 2],
+                vec![0; SECTION_BLOCKS / 2],
+            )
+            .ok()?;
             chunksections.push(section);
         }
         let mut chunk = Chunk {
@@ -442,6 +376,13 @@ impl Chunk {
                 Some(chunksections[7].clone()),
             ],
             heightmap: [[0; 16]; 16],
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            populated: false,
+            last_update: 0,
+            dirty: false,
+            tile_ticks: Vec::new(),
+            packet_cache: None,
         };
         chunk.calculate_heightmap().ok()?;
         chunk.calculate_skylight(GAME_GLOBAL.get_time()).ok()?;
@@ -462,46 +403,129 @@ impl Chunk {
             }
         }
     } */
-    pub fn get_block(&mut self, x: i32, y: i32, z: i32) -> Option<&mut Block> {
-        let idx = World::pos_to_index(x, y, z)?;
-        if x > 15 {
-            //log::info!("Coords {} {} {}", x, y, z);
-            //panic!("g");
-        }
-        if idx.2 > 1 {
-            //log::info!("Getting section {}", idx.2);
-        }
-        let section = self.data.get_mut(idx.2 as usize)?;
-        if section.is_none() {
-            *section = Some(ChunkSection::new(self.x, self.z, idx.2 as i8));
-        }
-        let section = section.as_mut().unwrap();
-        section.get_block(ChunkSection::pos_to_index(
-            x.rem_euclid(16),
-            y.rem_euclid(16),
-            z.rem_euclid(16),
+    /// The chunk's block ids indexed `x << 11 | z << 7 | y`, the layout carvers work on.
+    /// Missing sections read as air and aren't created.
+    pub fn block_types(&self) -> Vec<u8> {
+        let mut blocks = vec![0; 32768];
+        for (section_y, section) in self.data.iter().enumerate() {
+            if let Some(section) = section {
+                copy_columns(&section.blocks, &mut blocks, section_y, true);
+            }
+        }
+        blocks
+    }
+    /// Block ids, metadata, block light and sky light in the `x << 11 | z << 7 | y` layout of a
+    /// region file. Missing sections are written as zeroes.
+    pub fn region_arrays(&self) -> [Vec<u8>; 4] {
+        let mut arrays = [vec![0; 32768], vec![0; 16384], vec![0; 16384], vec![0; 16384]];
+        for (section_y, section) in self.data.iter().enumerate() {
+            if let Some(section) = section {
+                let from = [&section.blocks, &section.metadata, &section.block_light, &section.sky_light];
+                for (array, from) in arrays.iter_mut().zip(from.iter()) {
+                    copy_columns(from, array, section_y, true);
+                }
+            }
+        }
+        arrays
+    }
+    /// Cut a region file's arrays into sections.
+    pub fn sections_from_region(
+        x: i32,
+        z: i32,
+        blocks: &[u8],
+        metadata: &[u8],
+        block_light: &[u8],
+        sky_light: &[u8],
+    ) -> anyhow::Result<[Option<ChunkSection>; 8]> {
+        if blocks.len() != 32768 || [metadata, block_light, sky_light].iter().any(|array| array.len() != 16384) {
+            return Err(anyhow::anyhow!("Chunk arrays are the wrong size"));
+        }
+        let mut sections: [Option<ChunkSection>; 8] = Default::default();
+        for (section_y, slot) in sections.iter_mut().enumerate() {
+            let mut section = ChunkSection::new(x, z, section_y as i8);
+            copy_columns(blocks, &mut section.blocks, section_y, false);
+            copy_columns(metadata, &mut section.metadata, section_y, false);
+            copy_columns(block_light, &mut section.block_light, section_y, false);
+            copy_columns(sky_light, &mut section.sky_light, section_y, false);
+            *slot = Some(section);
+        }
+        Ok(sections)
+    }
+    /// Write back ids from `block_types`. Changed blocks lose their metadata.
+    pub fn set_block_types(&mut self, blocks: &[u8]) {
+        for (index, block_type) in blocks.iter().enumerate() {
+            let (x, z, y) = ((index >> 11) as i32, ((index >> 7) & 15) as i32, (index & 127) as i32);
+            if self.data[(y / 16) as usize].is_none() && *block_type == 0 {
+                continue;
+            }
+            if self.block_type(x, y, z) != *block_type {
+                self.update_block(x, y, z, |block| {
+                    block.b_type = *block_type;
+                    block.b_metadata = 0;
+                });
+            }
+        }
+    }
+    /// The section and index within it of a block, `None` above or below the world.
+    fn section_index(x: i32, y: i32, z: i32) -> Option<(usize, usize)> {
+        if !(0..128).contains(&y) {
+            return None;
+        }
+        Some((
+            (y >> 4) as usize,
+            ChunkSection::pos_to_index(x.rem_euclid(16), y & 15, z.rem_euclid(16)),
         ))
     }
-    pub fn epic_generate(x: i32, z: i32) -> Self {
-        let mut blocks = Vec::new();
-        for _ in 0..4096 {
-            blocks.push(Block {
-                b_type: 1,
-                b_metadata: 0,
-                b_light: 0,
-                b_skylight: 0,
-            });
+    /// The section at `section`, created empty if it doesn't exist yet. Every change to the chunk
+    /// goes through here, so this is also where the cached packet is dropped.
+    fn section_mut(&mut self, section: usize) -> &mut ChunkSection {
+        self.packet_cache = None;
+        let (x, z) = (self.x, self.z);
+        self.data[section].get_or_insert_with(|| ChunkSection::new(x, z, section as i8))
+    }
+    /// The block at `x`, `y`, `z` within the chunk. Blocks in sections that don't exist are air.
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        let (section, idx) = Self::section_index(x, y, z)?;
+        match &self.data[section] {
+            Some(section) => section.get_block(idx),
+            None => Some(Block::default()),
+        }
+    }
+    /// Overwrite a block. Returns `None` outside the chunk.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) -> Option<()> {
+        let (section, idx) = Self::section_index(x, y, z)?;
+        self.section_mut(section).set_block(idx, block);
+        Some(())
+    }
+    /// Change some of a block's fields in place. Returns `None` outside the chunk.
+    pub fn update_block(&mut self, x: i32, y: i32, z: i32, update: impl FnOnce(&mut Block)) -> Option<()> {
+        let mut block = self.get_block(x, y, z)?;
+        update(&mut block);
+        self.set_block(x, y, z, block)
+    }
+    pub fn block_type(&self, x: i32, y: i32, z: i32) -> u8 {
+        match Self::section_index(x, y, z) {
+            Some((section, idx)) => self.data[section].as_ref().map_or(0, |section| section.block_type(idx)),
+            None => 0,
         }
+    }
+    pub fn light(&self, channel: LightChannel, x: i32, y: i32, z: i32) -> Option<u8> {
+        let (section, idx) = Self::section_index(x, y, z)?;
+        Some(self.data[section].as_ref().map_or(0, |section| section.light(channel, idx)))
+    }
+    pub fn set_light(&mut self, channel: LightChannel, x: i32, y: i32, z: i32, level: u8) -> Option<()> {
+        let (section, idx) = Self::section_index(x, y, z)?;
+        self.section_mut(section).set_light(channel, idx, level);
+        Some(())
+    }
+    pub fn epic_generate(x: i32, z: i32) -> Self {
+        let mut section = ChunkSection::new(x, z, 0);
+        section.blocks = vec![1; SECTION_BLOCKS];
         let chunk = Chunk {
             x: x,
             z: z,
             data: [
-                Some(ChunkSection {
-                    data: blocks,
-                    x: x,
-                    z: z,
-                    section: 0,
-                }),
+                Some(section),
                 None,
                 None,
                 None,
@@ -511,32 +535,23 @@ impl Chunk {
                 None,
             ],
             heightmap: [[0; 16]; 16],
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            populated: false,
+            last_update: 0,
+            dirty: false,
+            tile_ticks: Vec::new(),
+            packet_cache: None,
         };
         chunk
     }
     pub fn fill_layer(&mut self, y: i32, block: Block) -> anyhow::Result<()> {
-        let section = y / 16;
-        if section < 0 {
-            return Err(anyhow::anyhow!("Section below zero!"));
+        if !(0..128).contains(&y) {
+            return Err(anyhow::anyhow!("Layer outside the world!"));
         }
-        let sec_num = section;
-        let section = self
-            .data
-            .get_mut(section as usize)
-            .ok_or(anyhow::anyhow!("Can't get section!"))?;
-        if section.is_none() {
-            *section = Some(ChunkSection::new(self.x, self.z, sec_num as i8));
-        }
-        let section = section.as_mut().unwrap();
         for x in 0..16 {
             for z in 0..16 {
-                if x == 0 && z == 0 {
-                    log::debug!("Doing {} {} {}", x, y, z);
-                }
-                **section
-                    .get_block(ChunkSection::pos_to_index(x, y, z - section.section as i32))
-                    .as_mut()
-                    .unwrap() = block;
+                self.set_block(x, y, z, block);
             }
         }
         self.calculate_heightmap()?;
@@ -544,33 +559,13 @@ impl Chunk {
         Ok(())
     }
     pub fn fill_layer_air(&mut self, y: i32, block: Block) -> anyhow::Result<()> {
-        let section = y / 16;
-        if section < 0 {
-            return Err(anyhow::anyhow!("Section below zero!"));
+        if !(0..128).contains(&y) {
+            return Err(anyhow::anyhow!("Layer outside the world!"));
         }
-        let sec_num = section;
-        let section = self
-            .data
-            .get_mut(section as usize)
-            .ok_or(anyhow::anyhow!("Can't get section!"))?;
-        if section.is_none() {
-            *section = Some(ChunkSection::new(self.x, self.z, sec_num as i8));
-        }
-        let section = section.as_mut().unwrap();
         for x in 0..16 {
             for z in 0..16 {
-                let mut w_block =
-                    section.get_block(ChunkSection::pos_to_index(x, y, z - section.section as i32));
-                let w_block = w_block.as_mut().unwrap();
-                if w_block.b_type == 0 {
-                    if x == 0 && z == 0 {
-                        log::debug!("Doing {} {} {}", x, y, z);
-                    }
-                    **w_block = block;
-                } else {
-                    if x == 0 && z == 0 {
-                        log::debug!("Not doing {} {} {} it is type {}", x, y, z, w_block.b_type);
-                    }
+                if self.block_type(x, y, z) == 0 {
+                    self.set_block(x, y, z, block);
                 }
             }
         }
@@ -592,19 +587,235 @@ impl WorldRef {
     pub fn get_world(&self) -> RefMut<'_, World> {
         self.world.borrow_mut()
     }
-    pub fn to_file(&self, file: &str) -> anyhow::Result<()> {
+    pub fn to_file(&self, file: &str) -> anyhow::Result<SaveSummary> {
         self.world.borrow_mut().to_file(file)
     }
 }
+/// Writes a gzipped level.dat into the world directory.
+pub fn write_level_dat(dir: &str, tag: &CompoundTag) -> anyhow::Result<()> {
+    use nbt::encode::write_gzip_compound_tag;
+    // Write to a temporary file first so a crash mid-save can't leave a truncated level.dat.
+    let path = format!("{}/level.dat", dir);
+    let tmp_path = format!("{}/level.dat_new", dir);
+    let mut file = std::fs::File::create(&tmp_path)?;
+    write_gzip_compound_tag(&mut file, tag)?;
+    drop(file);
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
+/// World state other than chunks that is persisted in level.dat.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct LevelData {
+    pub time: i64,
+    pub raining: bool,
+    pub rain_time: i32,
+    pub thundering: bool,
+    pub thunder_time: i32,
+}
+/// How much of the world a save actually wrote.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct SaveSummary {
+    pub chunks_saved: usize,
+    pub chunks_loaded: usize,
+}
 pub struct World {
     pub chunks: HashMap<ChunkCoords, Chunk>,
     pub generator: Arc<Box<dyn WorldGenerator>>,
     pub spawn_position: Position,
-    pub block_updates: VecDeque<(BlockPosition, Block)>,
+    /// Blocks changed through a `BlockRef` whose neighbours haven't been told yet.
+    pub block_updates: VecDeque<BlockPosition>,
+    pub scheduled_ticks: TickQueue,
     pub mcr_helper: Option<MCRegionLoader>,
+    pub level_data: LevelData,
+    /// Chunks loaded from disk that still carry entities for the game to spawn.
+    pub pending_entity_chunks: Vec<ChunkCoords>,
+    /// Ticks each resident chunk has gone without a player in view.
+    pub idle_chunks: HashMap<ChunkCoords, u128>,
+    pub saver: Option<WorldSaver>,
+    /// Worker threads that read and generate chunks requested with `request_chunk`.
+    pub loader: Option<ChunkLoader>,
+    /// Chunks changed by population after players may have been sent them.
+    pub resend_chunks: Vec<ChunkCoords>,
+    /// Climate noise for the world seed, whichever generator made the terrain.
+    pub biomes: BiomeSource,
+    /// 0 for the overworld, -1 for the nether. Only the overworld has spawn chunks and a level.dat.
+    pub dimension: i8,
+    /// The directory the world is saved in, which is also how players and `/world` refer to it.
+    pub name: String,
 }
+/// Radius in chunks around the spawn point that is never unloaded.
+pub const SPAWN_CHUNK_RADIUS: i32 = 8;
 use std::time::*;
 impl World {
+    fn insert_loaded_chunk(&mut self, coords: ChunkCoords, mut chunk: Chunk) {
+        if !chunk.entities.is_empty() || !chunk.tile_entities.is_empty() {
+            self.pending_entity_chunks.push(coords);
+        }
+        self.scheduled_ticks.restore(std::mem::take(&mut chunk.tile_ticks));
+        // The chunk keeps the light it was saved with.
+        self.chunks.insert(coords, chunk);
+        self.light_borders(&coords);
+        self.populate_around(coords);
+    }
+    /// Move the entity and tile entity tags out of the chunks loaded since the last call.
+    /// The game owns them from then on, so those chunks are marked dirty and rewritten
+    /// on the next save even if nothing is left in them.
+    pub fn take_pending_entities(&mut self) -> (Vec<CompoundTag>, Vec<CompoundTag>) {
+        let mut entities = Vec::new();
+        let mut tile_entities = Vec::new();
+        for coords in std::mem::take(&mut self.pending_entity_chunks) {
+            if let Some(chunk) = self.chunks.get_mut(&coords) {
+                if !chunk.entities.is_empty() || !chunk.tile_entities.is_empty() {
+                    chunk.dirty = true;
+                }
+                entities.append(&mut chunk.entities);
+                tile_entities.append(&mut chunk.tile_entities);
+            }
+        }
+        (entities, tile_entities)
+    }
+    /// Whether the block is inside the world's height and its chunk is in memory.
+    pub fn is_block_loaded(&self, pos: &BlockPosition) -> bool {
+        (0..128).contains(&pos.y)
+            && self.chunks.contains_key(&ChunkCoords {
+                x: pos.x >> 4,
+                z: pos.z >> 4,
+            })
+    }
+    pub fn is_spawn_chunk(&self, coords: &ChunkCoords) -> bool {
+        if self.dimension != 0 {
+            return false;
+        }
+        let spawn_x = (self.spawn_position.x.floor() as i32) >> 4;
+        let spawn_z = (self.spawn_position.z.floor() as i32) >> 4;
+        (coords.x - spawn_x).abs() <= SPAWN_CHUNK_RADIUS
+            && (coords.z - spawn_z).abs() <= SPAWN_CHUNK_RADIUS
+    }
+    /// Queue a chunk to be saved to its region file and drop it from memory.
+    pub fn unload_chunk(&mut self, coords: &ChunkCoords) -> anyhow::Result<()> {
+        if let Some(mut chunk) = self.chunks.remove(coords) {
+            chunk.tile_ticks = self.scheduled_ticks.chunk_ticks(coords, true);
+            if chunk.dirty {
+                chunk.dirty = false;
+                let queued = match self.saver.as_mut() {
+                    Some(saver) => saver.queue(None, vec![chunk.clone()], 0, None),
+                    None => Err(anyhow::anyhow!("World has no saver")),
+                };
+                if let Err(e) = queued {
+                    self.restore_chunk(*coords, chunk);
+                    return Err(e);
+                }
+            }
+            self.idle_chunks.remove(coords);
+        }
+        Ok(())
+    }
+    /// Put back a chunk that could not be saved. Its entities were despawned when it was
+    /// unloaded, so they are handed to the game to spawn again.
+    fn restore_chunk(&mut self, coords: ChunkCoords, mut chunk: Chunk) {
+        chunk.dirty = true;
+        self.scheduled_ticks.restore(std::mem::take(&mut chunk.tile_ticks));
+        if !chunk.entities.is_empty() || !chunk.tile_entities.is_empty() {
+            self.pending_entity_chunks.push(coords);
+        }
+        self.chunks.insert(coords, chunk);
+    }
+    /// Read a chunk from disk, or from a save that hasn't finished writing it yet.
+    fn load_chunk(&mut self, coords: ChunkCoords) -> Option<Chunk> {
+        let mcr_helper = self.mcr_helper.as_mut()?;
+        match self.saver.as_ref() {
+            Some(saver) => {
+                if let Some(chunk) = saver.in_flight_chunk(&coords) {
+                    return Some(chunk);
+                }
+                let _guard = saver.lock_regions();
+                mcr_helper.get_chunk(coords)
+            }
+            None => mcr_helper.get_chunk(coords),
+        }
+    }
+    fn generate_chunk(&mut self, coords: ChunkCoords) {
+        let chunk = generate_terrain(&**self.generator, coords);
+        self.insert_generated_chunk(coords, chunk);
+    }
+    /// Add structures to fresh terrain and populate whatever it completes.
+    fn insert_generated_chunk(&mut self, coords: ChunkCoords, chunk: Chunk) {
+        self.chunks.insert(coords, chunk);
+        self.light_borders(&coords);
+        // Structures can reach into the chunks around this one.
+        let mut around = Vec::new();
+        for x in coords.x - 1..=coords.x + 1 {
+            for z in coords.z - 1..=coords.z + 1 {
+                around.push(ChunkCoords { x, z });
+            }
+        }
+        let before = self.block_snapshot(&around);
+        self.generator.clone().gen_structures(self, coords);
+        self.relight_changes(before);
+        let populated = !self.generator.populates_lazily();
+        if let Some(chunk) = self.chunks.get_mut(&coords) {
+            chunk.populated = populated;
+            chunk.dirty = true;
+        }
+        self.populate_around(coords);
+    }
+    /// Populate every chunk that `coords` was the last missing neighbour of. Like vanilla, a chunk
+    /// is decorated once it and its +x, +z and +x+z neighbours exist, since decorations spill into them.
+    fn populate_around(&mut self, coords: ChunkCoords) {
+        if !self.generator.populates_lazily() {
+            return;
+        }
+        for (dx, dz) in &[(0, 0), (-1, 0), (0, -1), (-1, -1)] {
+            let origin = ChunkCoords {
+                x: coords.x + dx,
+                z: coords.z + dz,
+            };
+            let square = [
+                origin,
+                ChunkCoords { x: origin.x + 1, z: origin.z },
+                ChunkCoords { x: origin.x, z: origin.z + 1 },
+                ChunkCoords { x: origin.x + 1, z: origin.z + 1 },
+            ];
+            let ready = self.chunks.get(&origin).map_or(false, |chunk| !chunk.populated)
+                && square.iter().all(|coords| self.chunks.contains_key(coords));
+            if !ready {
+                continue;
+            }
+            let before = self.block_snapshot(&square);
+            self.generator.clone().populate(self, origin);
+            self.relight_changes(before);
+            if let Some(chunk) = self.chunks.get_mut(&origin) {
+                chunk.populated = true;
+                chunk.dirty = true;
+            }
+            for coords in square.iter() {
+                if !self.resend_chunks.contains(coords) {
+                    self.resend_chunks.push(*coords);
+                }
+            }
+        }
+    }
+    /// Flag the chunk containing `pos` as needing a save.
+    pub fn mark_dirty(&mut self, pos: &BlockPosition) {
+        if let Some(chunk) = self.chunks.get_mut(&ChunkCoords {
+            x: pos.x >> 4,
+            z: pos.z >> 4,
+        }) {
+            chunk.dirty = true;
+        }
+    }
+    fn queue_neighbour_update(&mut self, pos: BlockPosition) {
+        if self.block_updates.back() != Some(&pos) {
+            self.block_updates.push_back(pos);
+        }
+    }
+    /// Queue a block update for `tick`. The update is dropped if the block has changed type by then.
+    pub fn schedule_tick(&mut self, pos: BlockPosition, block_type: u8, tick: u128) {
+        self.scheduled_ticks.schedule(pos, block_type, tick);
+        // Pending updates are saved with the chunk.
+        self.mark_dirty(&pos);
+    }
+    /// Load or generate a chunk right away.
     pub fn init_chunk(&mut self, coords: &ChunkCoords) {
         let chunk = self.check_chunk_exists(coords);
         if !chunk {
@@ -612,37 +823,122 @@ impl World {
                 x: coords.x,
                 z: coords.z,
             };
-            if let Some(c) = self.mcr_helper.as_mut().unwrap().get_chunk(coords) {
-                self.chunks.insert(coords, c);
+            // Already being worked on, so wait for it rather than doing it twice.
+            if let Some(loaded) = self.loader.as_mut().and_then(|loader| loader.wait_for(&coords)) {
+                if !matches!(loaded, LoadedChunk::Failed) {
+                    self.insert_finished_chunk(coords, loaded);
+                    return;
+                }
+            }
+            if let Some(c) = self.load_chunk(coords) {
+                self.insert_loaded_chunk(coords, c);
             } else {
                 ////////////////////log::info!("Generating");
-                self.chunks.insert(coords, self.generator.gen_chunk(coords));
-                self.generator.clone().gen_structures(self, coords);
+                self.generate_chunk(coords);
             }
         }
-        let _ = self
-            .chunks
-            .get_mut(coords)
-            .unwrap()
-            .calculate_skylight(GAME_GLOBAL.get_time());
-    }
-    pub fn to_file(&mut self, file: &str) -> anyhow::Result<()> {
-        let mut mcr_helper = std::mem::replace(&mut self.mcr_helper, None);
-        if let Some(mcr_helper) = mcr_helper.as_mut() {
-            mcr_helper.save_all(self)?;
-        }
-        self.mcr_helper = mcr_helper;
-        let mut file = std::fs::File::create(&format!(
-            "{}/level.dat",
-            self.mcr_helper.as_ref().unwrap().world_dir
-        ))?;
-        let mut root_tag = CompoundTag::new();
-        let mut tag = CompoundTag::new();
-        tag.insert_i32("SpawnX", self.spawn_position.x as i32);
-        tag.insert_i32("SpawnY", self.spawn_position.x as i32);
-        tag.insert_i32("SpawnZ", self.spawn_position.x as i32);
-        write_compound_tag(&mut file, &root_tag)?;
-        return Ok(());
+    }
+    /// Load or generate a chunk on the worker threads. It becomes resident in a later
+    /// `poll_chunk_loads`. Chunks still waiting to be saved are taken straight back.
+    pub fn request_chunk(&mut self, coords: &ChunkCoords) {
+        if self.check_chunk_exists(coords) {
+            return;
+        }
+        if let Some(chunk) = self.saver.as_ref().and_then(|saver| saver.in_flight_chunk(coords)) {
+            self.insert_loaded_chunk(*coords, chunk);
+        } else if let Some(loader) = self.loader.as_mut() {
+            loader.request(*coords);
+        } else {
+            self.init_chunk(coords);
+        }
+    }
+    /// Whether a chunk has been requested and hasn't arrived yet.
+    pub fn is_chunk_pending(&self, coords: &ChunkCoords) -> bool {
+        self.loader.as_ref().map_or(false, |loader| loader.is_pending(coords))
+    }
+    /// Make the chunks the workers have finished resident.
+    pub fn poll_chunk_loads(&mut self) {
+        let finished = match self.loader.as_mut() {
+            Some(loader) => loader.poll(),
+            None => return,
+        };
+        for (coords, loaded) in finished {
+            self.insert_finished_chunk(coords, loaded);
+        }
+    }
+    fn insert_finished_chunk(&mut self, coords: ChunkCoords, loaded: LoadedChunk) {
+        if self.chunks.contains_key(&coords) {
+            return;
+        }
+        match loaded {
+            LoadedChunk::Disk(chunk) => self.insert_loaded_chunk(coords, chunk),
+            LoadedChunk::Generated(chunk) => self.insert_generated_chunk(coords, chunk),
+            LoadedChunk::Failed => {}
+        }
+    }
+    /// Snapshot the dirty chunks and level.dat and hand them to the saver thread.
+    pub fn queue_save(&mut self, requester: Option<&str>) -> anyhow::Result<()> {
+        let level_dat = match self.dimension {
+            0 => Some(self.level_dat_tag()?),
+            _ => None,
+        };
+        let mut snapshot = Vec::new();
+        for (coords, chunk) in self.chunks.iter_mut() {
+            if chunk.dirty {
+                chunk.dirty = false;
+                chunk.tile_ticks = self.scheduled_ticks.chunk_ticks(coords, false);
+                snapshot.push(chunk.clone());
+                chunk.tile_ticks.clear();
+            }
+        }
+        let chunks_loaded = self.chunks.len();
+        self.saver
+            .as_mut()
+            .ok_or(anyhow::anyhow!("World has no saver"))?
+            .queue(requester, snapshot, chunks_loaded, level_dat)
+    }
+    /// Results of finished saves. Chunks that failed to write are put back as dirty.
+    pub fn poll_saves(&mut self) -> Vec<SaveResult> {
+        let results = match self.saver.as_mut() {
+            Some(saver) => saver.poll(),
+            None => Vec::new(),
+        };
+        self.restore_failed_chunks(results)
+    }
+    /// Block until every queued save has been written.
+    pub fn wait_for_saves(&mut self) -> Vec<SaveResult> {
+        let results = match self.saver.as_mut() {
+            Some(saver) => saver.wait(),
+            None => Vec::new(),
+        };
+        self.restore_failed_chunks(results)
+    }
+    fn restore_failed_chunks(&mut self, mut results: Vec<SaveResult>) -> Vec<SaveResult> {
+        for result in results.iter_mut() {
+            for chunk in result.failed.drain(..) {
+                let coords = ChunkCoords {
+                    x: chunk.x,
+                    z: chunk.z,
+                };
+                match self.chunks.get_mut(&coords) {
+                    Some(resident) => resident.dirty = true,
+                    None => self.restore_chunk(coords, chunk),
+                }
+            }
+        }
+        results
+    }
+    /// Write every dirty chunk and the level.dat, waiting for the saver thread to finish.
+    pub fn to_file(&mut self, file: &str) -> anyhow::Result<SaveSummary> {
+        self.queue_save(None)?;
+        let mut summary = SaveSummary::default();
+        for mut result in self.wait_for_saves() {
+            if let Some(e) = result.error.take() {
+                return Err(e);
+            }
+            summary = result.summary;
+        }
+        return Ok(summary);
         /*         let start = Instant::now();
         log::info!("Saving world to \"{}\"", file);
         use std::fs;
@@ -672,9 +968,42 @@ impl World {
         write_compound_tag(&mut file, &root).unwrap();
         log::info!("Done in {}ms.", start.elapsed().as_millis()); */
     }
+    /// Builds a vanilla-compatible level.dat.
+    pub fn level_dat_tag(&self) -> anyhow::Result<CompoundTag> {
+        let dir = &self
+            .mcr_helper
+            .as_ref()
+            .ok_or(anyhow::anyhow!("World has no region loader"))?
+            .world_dir;
+        let mut tag = CompoundTag::new();
+        tag.insert_i64("RandomSeed", self.generator.get_seed() as i64);
+        tag.insert_str("generatorName", self.generator.get_name());
+        tag.insert_str("generatorOptions", self.generator.get_options());
+        tag.insert_i32("SpawnX", self.spawn_position.x.floor() as i32);
+        tag.insert_i32("SpawnY", self.spawn_position.y.floor() as i32);
+        tag.insert_i32("SpawnZ", self.spawn_position.z.floor() as i32);
+        tag.insert_i64("Time", self.level_data.time);
+        tag.insert_i8("raining", self.level_data.raining as i8);
+        tag.insert_i32("rainTime", self.level_data.rain_time);
+        tag.insert_i8("thundering", self.level_data.thundering as i8);
+        tag.insert_i32("thunderTime", self.level_data.thunder_time);
+        tag.insert_i64(
+            "LastPlayed",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as i64),
+        );
+        tag.insert_i64("SizeOnDisk", 0);
+        tag.insert_i32("version", 19132);
+        tag.insert_str("LevelName", dir);
+        let mut root_tag = CompoundTag::new();
+        root_tag.insert_compound_tag("Data", tag);
+        Ok(root_tag)
+    }
     pub fn from_file_mcr(dir: &str) -> anyhow::Result<Self> {
+        use nbt::decode::read_gzip_compound_tag;
         let mut file = std::fs::File::open(&format!("{}/level.dat", dir))?;
-        let tag = read_compound_tag(&mut file)?;
+        let tag = read_gzip_compound_tag(&mut file)?;
         let tag = tag
             .get_compound_tag("Data")
             .or(Err(anyhow::anyhow!("Tag read error")))?
@@ -688,14 +1017,30 @@ impl World {
         let spawn_z = tag
             .get_i32("SpawnZ")
             .or(Err(anyhow::anyhow!("Tag read error")))?;
-        let mut world = Self::new(
-            Box::new(MountainWorldGenerator::new(
-                tag.get_i64("RandomSeed")
-                    .or(Err(anyhow::anyhow!("Tag read error")))? as u64,
-            )),
-            MCRegionLoader::new(dir)?,
-        );
+        let seed = tag
+            .get_i64("RandomSeed")
+            .or(Err(anyhow::anyhow!("Tag read error")))? as u64;
+        // Worlds saved before the generator was recorded were all made with the mountain generator.
+        let generator = generator_from_name(
+            tag.get_str("generatorName").unwrap_or("MountainChunkGenerator"),
+            seed,
+            tag.get_str("generatorOptions").unwrap_or(""),
+        )
+        .unwrap_or_else(|e| {
+            // Failing here would make the caller generate a fresh world over this one.
+            log::error!("{}, falling back to the mountain generator", e);
+            Box::new(MountainWorldGenerator::new(seed))
+        });
+        let mut world = Self::new(generator, MCRegionLoader::new(dir)?);
         world.spawn_position = Position::from_pos(spawn_x as f64, spawn_y as f64, spawn_z as f64);
+        // Weather and time are optional, worlds from older versions may not have them.
+        world.level_data = LevelData {
+            time: tag.get_i64("Time").unwrap_or(0),
+            raining: tag.get_i8("raining").unwrap_or(0) != 0,
+            rain_time: tag.get_i32("rainTime").unwrap_or(0),
+            thundering: tag.get_i8("thundering").unwrap_or(0) != 0,
+            thunder_time: tag.get_i32("thunderTime").unwrap_or(0),
+        };
         drop(tag);
         Ok(world)
     }
@@ -764,10 +1109,20 @@ impl World {
         log::info!("Done in {}s.", start.elapsed().as_secs());
         Ok(Self {
             chunks,
+            biomes: BiomeSource::new(generator.get_seed() as i64),
             generator: Arc::new(generator),
             spawn_position: Position::from_pos(3., 45., 8.),
             block_updates: VecDeque::new(),
+            scheduled_ticks: TickQueue::default(),
             mcr_helper: Some(MCRegionLoader::new("")?),
+            level_data: LevelData::default(),
+            pending_entity_chunks: Vec::new(),
+            idle_chunks: HashMap::new(),
+            saver: None,
+            loader: None,
+            resend_chunks: Vec::new(),
+            dimension: 0,
+            name: file.to_string(),
         })
     }
     pub fn epic_test(&mut self) {
@@ -809,58 +1164,35 @@ impl World {
             Instant::now().duration_since(start_time).as_secs()
         );
     }
+    /// The biome of the column `position` is in.
+    pub fn get_biome(&self, position: &BlockPosition) -> Biome {
+        self.biomes.biome(position)
+    }
     pub fn check_chunk_exists(&self, coords: &ChunkCoords) -> bool {
         self.chunks.get(coords).is_some()
     }
     pub fn chunk_to_packets(
-        &self,
+        &mut self,
         coords: ChunkCoords,
         player: Sender<ServerPacket>,
     ) -> anyhow::Result<()> {
-        use rayon::prelude::*;
-        let mut initialized = Vec::new();
-        let chunk = self.chunks.get(&coords).ok_or(anyhow::anyhow!("Balls"))?;
-        for section in &chunk.data {
-            if section.is_some() {
-                log::debug!("Sending section");
-                if !initialized.contains(&coords) {
-                    player.send(ServerPacket::PreChunk {
-                        x: chunk.x,
-                        z: chunk.z,
-                        mode: true,
-                    })?;
-                    initialized.push(coords);
-                }
-                section
-                    .as_ref()
-                    .unwrap()
-                    .to_packets_section_raw(player.clone(), &mut Vec::new());
-                //return Ok(());
-            }
-        }
+        let chunk = self.chunks.get_mut(&coords).ok_or(anyhow::anyhow!("Balls"))?;
+        player.send(ServerPacket::PreChunk {
+            x: chunk.x,
+            z: chunk.z,
+            mode: true,
+        })?;
+        player.send(chunk.map_chunk_packet()?)?;
         Ok(())
     }
-    pub fn bad_to_packets(&self, player: Sender<ServerPacket>) -> anyhow::Result<()> {
-        let mut initialized = Vec::new();
-        for (coords, chunk) in &self.chunks {
-            for section in &chunk.data {
-                if section.is_some() {
-                    log::debug!("Sending section");
-                    if !initialized.contains(coords) {
-                        player.send(ServerPacket::PreChunk {
-                            x: chunk.x,
-                            z: chunk.z,
-                            mode: true,
-                        })?;
-                        initialized.push(*coords);
-                    }
-                    section
-                        .as_ref()
-                        .unwrap()
-                        .to_packets_section_raw(player.clone(), &mut Vec::new());
-                    //return Ok(());
-                }
-            }
+    pub fn bad_to_packets(&mut self, player: Sender<ServerPacket>) -> anyhow::Result<()> {
+        for chunk in self.chunks.values_mut() {
+            player.send(ServerPacket::PreChunk {
+                x: chunk.x,
+                z: chunk.z,
+                mode: true,
+            })?;
+            player.send(chunk.map_chunk_packet()?)?;
         }
         Ok(())
     }
@@ -903,69 +1235,16 @@ impl World {
             }
         }
     } */
-    fn get_block_mut(&mut self, x: i32, y: i32, z: i32) -> Option<&mut Block> {
-        let idx = Self::pos_to_index(x, y, z)?;
-        let chunk = self
-            .chunks
-            .get(&ChunkCoords { x: idx.0, z: idx.1 })
-            .is_some();
-        if !chunk {
-            self.init_chunk(&ChunkCoords { x: idx.0, z: idx.1 });
-        }
-        drop(chunk);
-        let chunk = self.chunks.get_mut(&ChunkCoords { x: idx.0, z: idx.1 })?;
-        let section = chunk.data.get_mut(idx.2 as usize)?;
-        if section.is_none() {
-            *section = Some(ChunkSection::new(idx.0, idx.1, idx.2 as i8));
-        }
-        let section = section.as_mut().unwrap();
-        let block = section.get_block(ChunkSection::pos_to_index(
-            x.rem_euclid(16),
-            y.rem_euclid(16),
-            z.rem_euclid(16),
-        ))?;
-        self.block_updates
-            .push_back((BlockPosition { x, y, z }, block.clone()));
-        Some(block)
-    }
-    fn get_block_internal(&mut self, x: i32, y: i32, z: i32) -> Option<&Block> {
-        let idx = Self::pos_to_index(x, y, z)?;
-        let chunk = self
-            .chunks
-            .get(&ChunkCoords { x: idx.0, z: idx.1 })
-            .is_some();
-        if !chunk {
-            if let Some(c) = self
-                .mcr_helper
-                .as_mut()
-                .unwrap()
-                .get_chunk(ChunkCoords { x: idx.0, z: idx.1 })
-            {
-                self.chunks.insert(ChunkCoords { x: idx.0, z: idx.1 }, c);
-            } else {
-                // TODO a standardized thing for generating missing chunks, not repeating code
-                //////////////////log::info!("Generating");
-                self.chunks.insert(
-                    ChunkCoords { x: idx.0, z: idx.1 },
-                    self.generator.gen_chunk(ChunkCoords { x: idx.0, z: idx.1 }),
-                );
-                self.generator
-                    .clone()
-                    .gen_structures(self, ChunkCoords { x: idx.0, z: idx.1 });
-            }
-        }
-        drop(chunk);
-        let chunk = self.chunks.get_mut(&ChunkCoords { x: idx.0, z: idx.1 })?;
-        let section = chunk.data.get_mut(idx.2 as usize)?;
-        if section.is_none() {
-            *section = Some(ChunkSection::new(idx.0, idx.1, idx.2 as i8));
-        }
-        let section = section.as_mut().unwrap();
-        Some(section.get_block(ChunkSection::pos_to_index(
-            x.rem_euclid(16),
-            y.rem_euclid(16),
-            z.rem_euclid(16),
-        ))?)
+    /// Change some of a block's fields in place, loading its chunk if needed.
+    fn update_block(&mut self, x: i32, y: i32, z: i32, update: impl FnOnce(&mut Block)) -> Option<()> {
+        let coords = ChunkCoords { x: x >> 4, z: z >> 4 };
+        self.init_chunk(&coords);
+        self.chunks.get_mut(&coords)?.update_block(x, y, z, update)
+    }
+    fn get_block_internal(&mut self, x: i32, y: i32, z: i32) -> Option<Block> {
+        let coords = ChunkCoords { x: x >> 4, z: z >> 4 };
+        self.init_chunk(&coords);
+        self.chunks.get(&coords)?.get_block(x, y, z)
     }
     pub fn pos_to_index(x: i32, y: i32, z: i32) -> Option<(i32, i32, i32)> {
         //log::info!("X {} Y {} Z {}", x, y, z);
@@ -994,12 +1273,30 @@ impl World {
         let mut chunks = HashMap::new();
         //let coords = ChunkCoords { x: 0, z: 0 };
         //chunks.insert(coords, generator.gen_chunk(coords));
+        let generator = Arc::new(generator);
+        let saver = WorldSaver::new(&mcr.world_dir);
+        let loader = ChunkLoader::new(
+            &mcr.world_dir,
+            generator.clone(),
+            saver.region_lock(),
+            CONFIGURATION.chunk_workers,
+        );
         let mut world = Self {
             chunks: chunks,
-            generator: Arc::new(generator),
+            biomes: BiomeSource::new(generator.get_seed() as i64),
+            generator,
             spawn_position: Position::from_pos(3., 45., 8.),
             block_updates: VecDeque::new(),
+            scheduled_ticks: TickQueue::default(),
+            level_data: LevelData::default(),
+            pending_entity_chunks: Vec::new(),
+            idle_chunks: HashMap::new(),
+            saver: Some(saver),
+            loader: Some(loader),
+            name: mcr.world_dir.clone(),
             mcr_helper: Some(mcr),
+            resend_chunks: Vec::new(),
+            dimension: 0,
         };
         //world.generator.clone().gen_structures(&mut world, coords);
         world
@@ -1040,6 +1337,23 @@ impl World {
         Self { chunks }
     } */
 }
+/// Copy each column of section `section_y` between a section array and the matching region
+/// array, whole bytes or nibbles depending on the length of `section`. Both run up y fastest, so
+/// a column is one run of 16 bytes or 8 nibble bytes.
+fn copy_columns(from: &[u8], to: &mut [u8], section_y: usize, to_region: bool) {
+    let section_len = if to_region { from.len() } else { to.len() };
+    let run = section_len / 256;
+    for column in 0..256 {
+        let (x, z) = (column >> 4, column & 15);
+        let region = ((x << 11) | (z << 7) | (section_y << 4)) * run / 16;
+        let section = column * run;
+        if to_region {
+            to[region..region + run].copy_from_slice(&from[section..section + run]);
+        } else {
+            to[section..section + run].copy_from_slice(&from[region..region + run]);
+        }
+    }
+}
 fn make_nibble_byte(mut a: u8, mut b: u8) -> Option<u8> {
     if a > 15 || b > 15 {
         return None;
@@ -1083,13 +1397,366 @@ pub fn compress_to_nibble(input: Vec<u8>) -> Option<Vec<u8>> {
     //output.remove(output.len() - 1);
     return Some(output);
 }
-pub trait WorldGenerator {
+/// Light opacity and emission for every block id, read from the item registry.
+struct LightTable {
+    opacity: [u8; 256],
+    emission: [u8; 256],
+}
+static LIGHT_TABLE: OnceCell<LightTable> = OnceCell::new();
+/// Looks up how blocks interact with light. Until the item registry exists every block but air is
+/// treated as opaque and nothing glows.
+#[derive(Clone, Copy)]
+struct LightProperties(Option<&'static LightTable>);
+impl LightProperties {
+    fn get() -> Self {
+        Self(ITEM_REGISTRY.get().map(|registry| {
+            LIGHT_TABLE.get_or_init(|| {
+                let mut table = LightTable {
+                    opacity: [15; 256],
+                    emission: [0; 256],
+                };
+                table.opacity[0] = 0;
+                for id in 0..256 {
+                    if let Some(item) = registry.get_item(id as i16) {
+                        if let Some(block) = item.get_item().as_block() {
+                            table.opacity[id] = block.opacity().min(15);
+                            table.emission[id] = block.light_emission().min(15);
+                        }
+                    }
+                }
+                table
+            })
+        }))
+    }
+    fn opacity(&self, id: u8) -> u8 {
+        match self.0 {
+            Some(table) => table.opacity[id as usize],
+            None if id == 0 => 0,
+            None => 15,
+        }
+    }
+    fn emission(&self, id: u8) -> u8 {
+        self.0.map_or(0, |table| table.emission[id as usize])
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightChannel {
+    Sky,
+    Block,
+}
+const NEIGHBOURS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+/// The light `level` turns into after moving one block in direction `dy` into a block of `opacity`.
+/// Full sky light travels straight down through transparent blocks without fading.
+fn spread_light(channel: LightChannel, level: u8, dy: i32, opacity: u8) -> u8 {
+    if channel == LightChannel::Sky && dy == -1 && level == 15 && opacity == 0 {
+        15
+    } else {
+        level.saturating_sub(opacity.max(1))
+    }
+}
+impl Chunk {
+    /// Compute sky and block light as if no other chunk were loaded. This only needs the chunk, so
+    /// it runs on the worker threads; `World::light_borders` joins it up with its neighbours.
+    pub fn compute_light(&mut self) {
+        let props = LightProperties::get();
+        let mut sky_queue = VecDeque::new();
+        let mut block_queue = VecDeque::new();
+        // Lowest y in each column that still gets full sky light.
+        let mut sky_floor = [[128i32; 16]; 16];
+        for x in 0..16 {
+            for z in 0..16 {
+                let mut sky = 15;
+                for y in (0..128).rev() {
+                    let block_type = self.block_type(x, y, z);
+                    sky = spread_light(LightChannel::Sky, sky, -1, props.opacity(block_type));
+                    let emission = props.emission(block_type);
+                    self.set_light(LightChannel::Sky, x, y, z, sky);
+                    self.set_light(LightChannel::Block, x, y, z, emission);
+                    if sky == 15 {
+                        sky_floor[x as usize][z as usize] = y;
+                    } else if sky > 1 {
+                        sky_queue.push_back((x, y, z));
+                    }
+                    if emission > 1 {
+                        block_queue.push_back((x, y, z));
+                    }
+                }
+            }
+        }
+        // Fully lit blocks only need to spread sideways where a neighbouring column is darker.
+        for x in 0..16usize {
+            for z in 0..16usize {
+                let floor = sky_floor[x][z];
+                let mut top = floor;
+                if x > 0 {
+                    top = top.max(sky_floor[x - 1][z]);
+                }
+                if x < 15 {
+                    top = top.max(sky_floor[x + 1][z]);
+                }
+                if z > 0 {
+                    top = top.max(sky_floor[x][z - 1]);
+                }
+                if z < 15 {
+                    top = top.max(sky_floor[x][z + 1]);
+                }
+                for y in floor..top {
+                    sky_queue.push_back((x as i32, y, z as i32));
+                }
+            }
+        }
+        self.propagate_light(LightChannel::Sky, sky_queue, props);
+        self.propagate_light(LightChannel::Block, block_queue, props);
+    }
+    /// `World::propagate_light` within this chunk, in chunk-relative coordinates.
+    fn propagate_light(
+        &mut self,
+        channel: LightChannel,
+        mut queue: VecDeque<(i32, i32, i32)>,
+        props: LightProperties,
+    ) {
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = match self.light(channel, x, y, z) {
+                Some(level) if level > 1 => level,
+                _ => continue,
+            };
+            for (dx, dy, dz) in NEIGHBOURS.iter() {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                if !(0..16).contains(&nx) || !(0..16).contains(&nz) {
+                    continue;
+                }
+                let current = match self.light(channel, nx, ny, nz) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                let new_level = spread_light(channel, level, *dy, props.opacity(self.block_type(nx, ny, nz)));
+                if new_level > current {
+                    self.set_light(channel, nx, ny, nz, new_level);
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+    }
+}
+impl World {
+    /// The type of a block in an already loaded chunk. Never loads or generates chunks.
+    fn loaded_block_type(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+        if y < 0 || y > 127 {
+            return None;
+        }
+        Some(self.chunks.get(&ChunkCoords { x: x >> 4, z: z >> 4 })?.block_type(x, y, z))
+    }
+    pub fn get_light(&mut self, channel: LightChannel, x: i32, y: i32, z: i32) -> Option<u8> {
+        self.chunks.get(&ChunkCoords { x: x >> 4, z: z >> 4 })?.light(channel, x, y, z)
+    }
+    /// Light is saved with the chunk, so a chunk whose light changes has to be written again.
+    fn set_light_level(&mut self, channel: LightChannel, x: i32, y: i32, z: i32, level: u8) {
+        if let Some(chunk) = self.chunks.get_mut(&ChunkCoords { x: x >> 4, z: z >> 4 }) {
+            if chunk.light(channel, x, y, z).map_or(false, |current| current != level) {
+                chunk.set_light(channel, x, y, z, level);
+                chunk.dirty = true;
+            }
+        }
+    }
+    /// Flood light outwards from every position in `queue`, only ever raising levels.
+    fn propagate_light(
+        &mut self,
+        channel: LightChannel,
+        mut queue: VecDeque<(i32, i32, i32)>,
+        props: LightProperties,
+    ) {
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = match self.get_light(channel, x, y, z) {
+                Some(level) if level > 1 => level,
+                _ => continue,
+            };
+            for (dx, dy, dz) in NEIGHBOURS.iter() {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let chunk = match self.chunks.get_mut(&ChunkCoords { x: nx >> 4, z: nz >> 4 }) {
+                    Some(chunk) => chunk,
+                    None => continue,
+                };
+                let current = match chunk.light(channel, nx, ny, nz) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                let new_level = spread_light(channel, level, *dy, props.opacity(chunk.block_type(nx, ny, nz)));
+                if new_level > current {
+                    chunk.set_light(channel, nx, ny, nz, new_level);
+                    chunk.dirty = true;
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+    }
+    /// Let light flow both ways between a chunk that just entered memory and the loaded chunks
+    /// around it. The chunk's own light is already computed, so only the blocks along its edges and
+    /// the neighbours' facing edges are looked at.
+    pub fn light_borders(&mut self, coords: &ChunkCoords) {
+        let props = LightProperties::get();
+        let mut sky_queue = VecDeque::new();
+        let mut block_queue = VecDeque::new();
+        let (base_x, base_z) = (coords.x * 16, coords.z * 16);
+        let borders = [
+            (ChunkCoords { x: coords.x - 1, z: coords.z }, base_x - 1..base_x + 1, base_z..base_z + 16),
+            (ChunkCoords { x: coords.x + 1, z: coords.z }, base_x + 15..base_x + 17, base_z..base_z + 16),
+            (ChunkCoords { x: coords.x, z: coords.z - 1 }, base_x..base_x + 16, base_z - 1..base_z + 1),
+            (ChunkCoords { x: coords.x, z: coords.z + 1 }, base_x..base_x + 16, base_z + 15..base_z + 17),
+        ];
+        for (neighbour, xs, zs) in borders.iter() {
+            if !self.chunks.contains_key(neighbour) {
+                continue;
+            }
+            for x in xs.clone() {
+                for z in zs.clone() {
+                    for y in 0..128 {
+                        if self.get_light(LightChannel::Sky, x, y, z).unwrap_or(0) > 1 {
+                            sky_queue.push_back((x, y, z));
+                        }
+                        if self.get_light(LightChannel::Block, x, y, z).unwrap_or(0) > 1 {
+                            block_queue.push_back((x, y, z));
+                        }
+                    }
+                }
+            }
+        }
+        self.propagate_light(LightChannel::Sky, sky_queue, props);
+        self.propagate_light(LightChannel::Block, block_queue, props);
+    }
+    /// Block ids of whichever of `chunks` are loaded, to hand to `relight_changes` later.
+    fn block_snapshot(&self, chunks: &[ChunkCoords]) -> Vec<(ChunkCoords, Vec<u8>)> {
+        chunks
+            .iter()
+            .filter_map(|coords| Some((*coords, self.chunks.get(coords)?.block_types())))
+            .collect()
+    }
+    /// Relight the blocks that stopped or started letting light through or glowing since
+    /// `block_snapshot`, such as the ones structures and decorations were placed in.
+    fn relight_changes(&mut self, before: Vec<(ChunkCoords, Vec<u8>)>) {
+        let props = LightProperties::get();
+        let mut changed = Vec::new();
+        for (coords, old) in before {
+            let new = match self.chunks.get(&coords) {
+                Some(chunk) => chunk.block_types(),
+                None => continue,
+            };
+            for (idx, (old, new)) in old.iter().zip(new.iter()).enumerate() {
+                if props.opacity(*old) != props.opacity(*new) || props.emission(*old) != props.emission(*new) {
+                    changed.push(BlockPosition::new(
+                        coords.x * 16 + (idx >> 11) as i32,
+                        (idx & 127) as i32,
+                        coords.z * 16 + ((idx >> 7) & 15) as i32,
+                    ));
+                }
+            }
+        }
+        for pos in changed {
+            self.update_light(&pos);
+        }
+    }
+    /// Bring the light around a block up to date after it changed type.
+    pub fn update_light(&mut self, pos: &BlockPosition) {
+        let props = LightProperties::get();
+        self.relight_block(LightChannel::Sky, pos, props);
+        self.relight_block(LightChannel::Block, pos, props);
+    }
+    fn relight_block(&mut self, channel: LightChannel, pos: &BlockPosition, props: LightProperties) {
+        let (x, y, z) = (pos.x, pos.y, pos.z);
+        let old = match self.get_light(channel, x, y, z) {
+            Some(level) => level,
+            None => return,
+        };
+        // Take away all light that came through this block, remembering the sources that remain.
+        let mut remove_queue = VecDeque::new();
+        let mut add_queue = VecDeque::new();
+        self.set_light_level(channel, x, y, z, 0);
+        remove_queue.push_back((x, y, z, old));
+        while let Some((x, y, z, level)) = remove_queue.pop_front() {
+            for (dx, dy, dz) in NEIGHBOURS.iter() {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let neighbour = match self.get_light(channel, nx, ny, nz) {
+                    Some(neighbour) if neighbour > 0 => neighbour,
+                    _ => continue,
+                };
+                let fed_by_sky_column = channel == LightChannel::Sky
+                    && *dy == -1
+                    && level == 15
+                    && neighbour == 15;
+                if neighbour < level || fed_by_sky_column {
+                    self.set_light_level(channel, nx, ny, nz, 0);
+                    remove_queue.push_back((nx, ny, nz, neighbour));
+                } else {
+                    add_queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+        // Then light the block itself again and let everything flow back in.
+        let block_type = match self.loaded_block_type(x, y, z) {
+            Some(block_type) => block_type,
+            None => return,
+        };
+        let source = match channel {
+            LightChannel::Block => props.emission(block_type),
+            LightChannel::Sky => {
+                let above = if y == 127 {
+                    15
+                } else {
+                    self.get_light(channel, x, y + 1, z).unwrap_or(0)
+                };
+                if above == 15 && props.opacity(block_type) == 0 {
+                    15
+                } else {
+                    0
+                }
+            }
+        };
+        self.set_light_level(channel, x, y, z, source);
+        add_queue.push_back((x, y, z));
+        for (dx, dy, dz) in NEIGHBOURS.iter() {
+            add_queue.push_back((x + dx, y + dy, z + dz));
+        }
+        self.propagate_light(channel, add_queue, props);
+    }
+}
+/// Generators run on the chunk worker threads as well as the tick thread.
+pub trait WorldGenerator: Send + Sync {
     fn gen_chunk(&self, coords: ChunkCoords) -> Chunk;
     fn gen_structures(&self, world: &mut World, coords: ChunkCoords);
     fn get_seed(&self) -> u64 {
         0
     }
     fn get_name(&self) -> String;
+    /// Extra settings needed to recreate the generator, such as a flat layer preset.
+    fn get_options(&self) -> String {
+        String::new()
+    }
+    /// Hollow out caves and the like between `gen_chunk` and `gen_structures`. Carvers only get
+    /// this chunk, so anything crossing into it has to be worked out from the seed alone.
+    fn carve(&self, _coords: ChunkCoords, _chunk: &mut Chunk) {}
+    /// Whether chunks come out of `gen_chunk` undecorated and wait for `populate`.
+    fn populates_lazily(&self) -> bool {
+        false
+    }
+    /// Decorate the chunk at `coords` once it and its +x, +z and +x+z neighbours are resident.
+    fn populate(&self, _world: &mut World, _coords: ChunkCoords) {}
+}
+/// Terrain for `coords` as the generator makes it, lit on its own, before structures and
+/// population, which need the rest of the world.
+pub fn generate_terrain(generator: &dyn WorldGenerator, coords: ChunkCoords) -> Chunk {
+    let mut chunk = generator.gen_chunk(coords);
+    generator.carve(coords, &mut chunk);
+    if let Err(e) = chunk.calculate_heightmap() {
+        log::error!("Couldn't recalculate the heightmap of {:?}: {:?}", coords, e);
+    }
+    chunk.compute_light();
+    chunk
 }
 pub trait StructureGenerator {
     fn gen_chunk(&self, world: &mut World, coords: ChunkCoords);
@@ -1098,12 +1765,15 @@ pub trait StructureGenerator {
     }
     fn get_name(&self) -> String;
 }
-pub trait ChunkGenerator {
+pub trait ChunkGenerator: Send + Sync {
     fn gen_chunk(&self, coords: ChunkCoords) -> Chunk;
     fn get_seed(&self) -> u64 {
         0
     }
     fn get_name(&self) -> String;
+    fn get_options(&self) -> String {
+        String::new()
+    }
 }
 use rand::Rng;
 use rand::SeedableRng;
@@ -1116,6 +1786,12 @@ use worldgen::world::tile::{Constraint, ConstraintType};
 use worldgen::world::{Tile, World as NGWorld};
 
 use super::mcregion::MCRegionLoader;
+use super::vanilla::biome::{Biome, BiomeSource};
+use super::vanilla::features::{self, Region};
+use super::vanilla::noise::JavaRandom;
+use super::vanilla::{caves, ravines};
+use super::vanilla::nether::NetherChunkGenerator;
+use super::vanilla::VanillaChunkGenerator;
 pub struct MountainStructureGenerator {
     seed: u64,
 }
@@ -1159,54 +1835,34 @@ impl WorldGenerator for MountainWorldGenerator {
     fn gen_chunk(&self, coords: ChunkCoords) -> Chunk {
         self.chunk_gen.gen_chunk(coords)
     }
+    fn carve(&self, coords: ChunkCoords, chunk: &mut Chunk) {
+        let seed = self.chunk_gen.seed as i64;
+        let mut blocks = chunk.block_types();
+        caves::carve(seed, coords.x, coords.z, &mut blocks);
+        ravines::carve(seed, coords.x, coords.z, &mut blocks);
+        chunk.set_block_types(&blocks);
+    }
     fn gen_structures(&self, world: &mut World, coords: ChunkCoords) {
         use siphasher::sip::SipHasher13;
         use std::hash::Hasher;
         let mut hash = SipHasher13::new_with_keys(self.chunk_gen.seed, self.chunk_gen.seed);
         hash.write_i32(coords.x);
         hash.write_i32(coords.z);
-        let hash = hash.finish();
-        let mut rng = XorShiftRng::seed_from_u64(hash);
-        let tree_x = rng.gen_range(0..16);
-        let tree_z = rng.gen_range(0..16);
-        let chunk = world.chunks.get_mut(&coords).unwrap();
-        let mut last_y = 0;
-        for y in (25..127).rev() {
-            let block = chunk.get_block(tree_x, y, tree_z).unwrap();
-            if block.b_type != 2 {
-                continue;
-            }
-            for offset in 1..rng.gen_range(3..8) {
-                let block = chunk.get_block(tree_x, y + offset, tree_z).unwrap();
-                block.set_type(17);
-                last_y = y + offset;
-            }
+        let mut random = JavaRandom::new(hash.finish() as i64);
+        let tree_x = coords.x * 16 + random.next_int_bounded(16);
+        let tree_z = coords.z * 16 + random.next_int_bounded(16);
+        let biome = world.biomes.biome_at(tree_x, tree_z);
+        let temperatures = world.biomes.temperatures(coords.x * 16, coords.z * 16, 16, 16);
+        let mut region = Region::new(&mut world.chunks);
+        features::ores(&mut region, &mut random, coords.x * 16, coords.z * 16);
+        if biome != Biome::Desert {
+            let tree_y = region.height(tree_x, tree_z);
+            biome.tree(&mut random).grow(&mut region, &mut random, tree_x, tree_y, tree_z);
         }
-        drop(chunk);
-        let blocks = BlockPosition {
-            x: tree_x + (coords.x * 16),
-            y: last_y,
-            z: tree_z + (coords.z * 16),
-        }
-        .all_directions();
-        for block in blocks {
-            if let Some(block) = world.get_block_mut(block.x, block.y, block.z) {
-                if ItemRegistry::global()
-                    .get_item(block.b_type as i16)
-                    .is_none()
-                {
-                    continue;
-                }
-                if !ItemRegistry::global()
-                    .get_item(block.b_type as i16)
-                    .unwrap()
-                    .get_item()
-                    .as_block()
-                    .unwrap()
-                    .is_solid()
-                {
-                    block.set_type(18);
-                }
+        for x in 0..16 {
+            for z in 0..16 {
+                let temperature = temperatures[(x * 16 + z) as usize];
+                features::snow(&mut region, coords.x * 16 + x, coords.z * 16 + z, temperature);
             }
         }
     }
@@ -1225,6 +1881,7 @@ impl StructureGenerator for MountainStructureGenerator {
 }
 pub struct MountainChunkGenerator {
     noise: ScaledNoiseMap<NoiseMap<PerlinNoise>>,
+    biomes: BiomeSource,
     seed: u64,
 }
 /*
@@ -1246,6 +1903,7 @@ impl MountainChunkGenerator {
         let nm = nm * 25;
         Self {
             noise: nm,
+            biomes: BiomeSource::new(seed as i64),
             seed: seed,
         }
     }
@@ -1308,17 +1966,11 @@ impl ChunkGenerator for MountainChunkGenerator {
             log::info!("Generating chunk at ({}, {})", coords.x, coords.z);
         }
         //log::info!("coords: {:?}", coords);
-        let mut blocks = Vec::new();
         let mut chunk = Chunk {
             x: coords.x,
             z: coords.z,
             data: [
-                Some(ChunkSection {
-                    data: blocks,
-                    x: coords.x,
-                    z: coords.z,
-                    section: 0,
-                }),
+                Some(ChunkSection::new(coords.x, coords.z, 0)),
                 None,
                 None,
                 None,
@@ -1328,12 +1980,20 @@ impl ChunkGenerator for MountainChunkGenerator {
                 None,
             ],
             heightmap: [[0; 16]; 16],
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            populated: false,
+            last_update: 0,
+            dirty: false,
+            tile_ticks: Vec::new(),
+            packet_cache: None,
         };
         chunk.calculate_heightmap().unwrap();
         let noise = self
             .noise
             .generate_chunk(-(coords.z as i64), -(coords.x as i64));
         let mut noisevec = Vec::new();
+        let biomes = self.biomes.climate(coords.x * 16, coords.z * 16, 16, 16).biomes;
         /*         for value in noise[0].iter() {
             noisevec.push(*value);
         } */
@@ -1350,39 +2010,40 @@ impl ChunkGenerator for MountainChunkGenerator {
                 }
                 let mut num = noisevec.pop().unwrap() as i32;
                 num += 40;
+                let biome = biomes[(x * 16 + z) as usize];
                 if num > 11 {
                     //continue;
                 }
                 if num < WATER_HEIGHT {
-                    *chunk.get_block(x, num, z).unwrap() = Block {
+                    chunk.set_block(x, num, z, Block {
                         b_type: 13,
                         b_metadata: 0,
                         b_light: 0,
                         b_skylight: 0,
-                    };
+                    });
                 } else {
-                    *chunk.get_block(x, num, z).unwrap() = Block {
-                        b_type: 2,
+                    chunk.set_block(x, num, z, Block {
+                        b_type: biome.top_block(),
                         b_metadata: 0,
                         b_light: 0,
                         b_skylight: 0,
-                    };
+                    });
                 }
                 for y in 0..num - 3 {
-                    *chunk.get_block(x, y, z).unwrap() = Block {
+                    chunk.set_block(x, y, z, Block {
                         b_type: 1,
                         b_metadata: 0,
                         b_light: 0,
                         b_skylight: 0,
-                    };
+                    });
                 }
                 for y in num - 3..num {
-                    *chunk.get_block(x, y, z).unwrap() = Block {
-                        b_type: 3,
+                    chunk.set_block(x, y, z, Block {
+                        b_type: biome.filler_block(),
                         b_metadata: 0,
                         b_light: 0,
                         b_skylight: 0,
-                    };
+                    });
                 }
             }
         }
@@ -1435,17 +2096,11 @@ impl ChunkGenerator for FunnyChunkGenerator {
             log::info!("Generating chunk at ({}, {})", coords.x, coords.z);
         }
         //log::info!("coords: {:?}", coords);
-        let mut blocks = Vec::new();
         let mut chunk = Chunk {
             x: coords.x,
             z: coords.z,
             data: [
-                Some(ChunkSection {
-                    data: blocks,
-                    x: coords.x,
-                    z: coords.z,
-                    section: 0,
-                }),
+                Some(ChunkSection::new(coords.x, coords.z, 0)),
                 None,
                 None,
                 None,
@@ -1455,6 +2110,13 @@ impl ChunkGenerator for FunnyChunkGenerator {
                 None,
             ],
             heightmap: [[0; 16]; 16],
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            populated: false,
+            last_update: 0,
+            dirty: false,
+            tile_ticks: Vec::new(),
+            packet_cache: None,
         };
         let noise = self
             .noise
@@ -1479,40 +2141,38 @@ impl ChunkGenerator for FunnyChunkGenerator {
                 if num > 11 {
                     //continue;
                 }
-                *chunk.get_block(x, num, z).unwrap() = Block {
+                chunk.set_block(x, num, z, Block {
                     b_type: 2,
                     b_metadata: 0,
                     b_light: 0,
                     b_skylight: 0,
-                };
+                });
                 for y in 0..num - 3 {
-                    *chunk.get_block(x, y, z).unwrap() = Block {
+                    chunk.set_block(x, y, z, Block {
                         b_type: 1,
                         b_metadata: 0,
                         b_light: 0,
                         b_skylight: 0,
-                    };
+                    });
                 }
                 for y in num - 3..num {
-                    *chunk.get_block(x, y, z).unwrap() = Block {
+                    chunk.set_block(x, y, z, Block {
                         b_type: 3,
                         b_metadata: 0,
                         b_light: 0,
                         b_skylight: 0,
-                    };
+                    });
                 }
             }
         }
         let tree_x = rng.gen_range(0..16);
         let tree_z = rng.gen_range(0..16);
         for y in (0..127).rev() {
-            let block = chunk.get_block(tree_x, y, tree_z).unwrap();
-            if block.b_type != 2 {
+            if chunk.block_type(tree_x, y, tree_z) != 2 {
                 continue;
             }
             for offset in 1..rng.gen_range(3..8) {
-                let block = chunk.get_block(tree_x, y + offset, tree_z).unwrap();
-                block.set_type(17);
+                chunk.update_block(tree_x, y + offset, tree_z, |block| block.set_type(17));
             }
         }
         chunk
@@ -1532,31 +2192,62 @@ impl ChunkGenerator for FunnyChunkGenerator {
         chunk
     }
 }
-pub struct FlatChunkGenerator {}
+/// Flat world made of layers stacked from y = 0 up.
+pub struct FlatChunkGenerator {
+    /// (count, block id) pairs, bottom layer first.
+    layers: Vec<(i32, u8)>,
+}
+impl FlatChunkGenerator {
+    pub const DEFAULT_PRESET: &'static str = "7,31x3,2";
+    /// Parse a layer preset such as `7,31x3,2`: comma separated block ids from the bottom up,
+    /// each optionally prefixed with `<count>x`.
+    pub fn from_preset(preset: &str) -> anyhow::Result<Self> {
+        let mut layers = Vec::new();
+        let mut height = 0;
+        for layer in preset.split(',') {
+            let layer = layer.trim();
+            let (count, block) = match layer.split_once('x') {
+                Some((count, block)) => (count.trim().parse::<i32>()?, block.trim()),
+                None => (1, layer),
+            };
+            let block = block.parse::<u8>()?;
+            if count < 1 {
+                return Err(anyhow::anyhow!("Layer \"{}\" has no height", layer));
+            }
+            height += count;
+            layers.push((count, block));
+        }
+        if height > 128 {
+            return Err(anyhow::anyhow!("Flat preset is {} blocks tall, the limit is 128", height));
+        }
+        Ok(Self { layers })
+    }
+}
+impl std::default::Default for FlatChunkGenerator {
+    fn default() -> Self {
+        Self::from_preset(Self::DEFAULT_PRESET).unwrap()
+    }
+}
 impl ChunkGenerator for FlatChunkGenerator {
     fn get_name(&self) -> String {
         "FlatChunkGenerator".to_string()
     }
+    fn get_options(&self) -> String {
+        self.layers
+            .iter()
+            .map(|(count, block)| match count {
+                1 => block.to_string(),
+                count => format!("{}x{}", count, block),
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
     fn gen_chunk(&self, coords: ChunkCoords) -> Chunk {
-        let blocks = Vec::new();
-        /*         for _ in 0..4096 {
-            blocks.push(Block {
-                b_type: 3,
-                b_metadata: 0,
-                b_light: 0,
-                b_skylight: 0,
-            });
-        } */
         let mut chunk = Chunk {
             x: coords.x,
             z: coords.z,
             data: [
-                Some(ChunkSection {
-                    data: blocks.clone(),
-                    x: coords.x,
-                    z: coords.z,
-                    section: 0,
-                }),
+                Some(ChunkSection::new(coords.x, coords.z, 0)),
                 None,
                 None,
                 None,
@@ -1566,42 +2257,396 @@ impl ChunkGenerator for FlatChunkGenerator {
                 None,
             ],
             heightmap: [[0; 16]; 16],
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            populated: false,
+            last_update: 0,
+            dirty: false,
+            tile_ticks: Vec::new(),
+            packet_cache: None,
         };
-        chunk
-            .fill_layer(
-                32,
-                Block {
-                    b_type: 2,
-                    b_metadata: 0,
-                    b_light: 0,
-                    b_skylight: 0,
-                },
-            )
-            .unwrap();
-        for i in 1..32 {
-            chunk
-                .fill_layer(
-                    i,
-                    Block {
-                        b_type: 3,
-                        b_metadata: 0,
-                        b_light: 0,
-                        b_skylight: 0,
-                    },
-                )
-                .unwrap();
+        let mut y = 0;
+        for (count, block) in self.layers.iter() {
+            for _ in 0..*count {
+                chunk
+                    .fill_layer(
+                        y,
+                        Block {
+                            b_type: *block,
+                            b_metadata: 0,
+                            b_light: 0,
+                            b_skylight: 0,
+                        },
+                    )
+                    .unwrap();
+                y += 1;
+            }
         }
         chunk
-            .fill_layer(
-                0,
-                Block {
-                    b_type: 7,
-                    b_metadata: 0,
-                    b_light: 0,
-                    b_skylight: 0,
-                },
-            )
-            .unwrap();
-        chunk
+    }
+}
+/// World generator for chunk generators that place no structures of their own.
+pub struct PlainWorldGenerator {
+    chunk_gen: Box<dyn ChunkGenerator>,
+}
+impl PlainWorldGenerator {
+    pub fn new(chunk_gen: impl ChunkGenerator + 'static) -> Self {
+        Self {
+            chunk_gen: Box::new(chunk_gen),
+        }
+    }
+}
+impl WorldGenerator for PlainWorldGenerator {
+    fn gen_chunk(&self, coords: ChunkCoords) -> Chunk {
+        self.chunk_gen.gen_chunk(coords)
+    }
+    fn gen_structures(&self, _world: &mut World, _coords: ChunkCoords) {}
+    fn get_seed(&self) -> u64 {
+        self.chunk_gen.get_seed()
+    }
+    fn get_name(&self) -> String {
+        self.chunk_gen.get_name()
+    }
+    fn get_options(&self) -> String {
+        self.chunk_gen.get_options()
+    }
+}
+/// Build the generator for a config `chunk_generator` value (flat, noise, mountain or vanilla).
+pub fn generator_from_config(
+    kind: &str,
+    seed: u64,
+    flat_preset: &str,
+) -> anyhow::Result<Box<dyn WorldGenerator>> {
+    Ok(match kind {
+        "flat" => Box::new(PlainWorldGenerator::new(FlatChunkGenerator::from_preset(flat_preset)?)),
+        "noise" => Box::new(PlainWorldGenerator::new(FunnyChunkGenerator::new(
+            seed,
+            FunnyChunkPreset::MOUNTAIN,
+        ))),
+        "mountain" => Box::new(MountainWorldGenerator::new(seed)),
+        "vanilla" => Box::new(VanillaChunkGenerator::new(seed)),
+        unknown => return Err(anyhow::anyhow!("Unknown chunk generator \"{}\"", unknown)),
+    })
+}
+/// Build the generator a world was saved with from its level.dat name and options.
+pub fn generator_from_name(
+    name: &str,
+    seed: u64,
+    options: &str,
+) -> anyhow::Result<Box<dyn WorldGenerator>> {
+    match name {
+        "FlatChunkGenerator" => generator_from_config("flat", seed, options),
+        "FunnyChunkGenerator" => generator_from_config("noise", seed, options),
+        "MountainChunkGenerator" => generator_from_config("mountain", seed, options),
+        "VanillaChunkGenerator" => generator_from_config("vanilla", seed, options),
+        "NetherChunkGenerator" => Ok(Box::new(NetherChunkGenerator::new(seed))),
+        unknown => Err(anyhow::anyhow!("Unknown chunk generator \"{}\"", unknown)),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn init_registry() {
+        ITEM_REGISTRY.get_or_init(|| {
+            let mut registry = ItemRegistry::new();
+            crate::game::items::default::init_items(&mut registry);
+            crate::game::entities::tile_entity::init_items(&mut registry);
+            registry
+        });
+    }
+    fn flat_world(name: &str) -> (String, WorldRef) {
+        init_registry();
+        let dir = temp_world_dir(name);
+        let world = World::new(
+            generator_from_config("flat", 0, "7,3x1").unwrap(),
+            MCRegionLoader::new(&dir).unwrap(),
+        );
+        let world = WorldRef {
+            world: Arc::new(RefCell::new(world)),
+        };
+        for x in -1..=1 {
+            for z in -1..=1 {
+                world.get_world().init_chunk(&ChunkCoords { x, z });
+            }
+        }
+        (dir, world)
+    }
+    fn light(world: &WorldRef, channel: LightChannel, x: i32, y: i32, z: i32) -> u8 {
+        world.get_world().get_light(channel, x, y, z).unwrap()
+    }
+    #[test]
+    fn torch_light_spreads_across_chunks() {
+        let (dir, world) = flat_world("torch_light");
+        world.get_block(&BlockPosition::new(15, 10, 8)).set_type(50);
+        assert_eq!(light(&world, LightChannel::Block, 15, 10, 8), 14);
+        assert_eq!(light(&world, LightChannel::Block, 16, 10, 8), 13);
+        assert_eq!(light(&world, LightChannel::Block, 15, 10, 3), 9);
+        assert_eq!(light(&world, LightChannel::Block, 15, 3, 8), 0);
+        world.get_block(&BlockPosition::new(15, 10, 8)).set_type(0);
+        assert_eq!(light(&world, LightChannel::Block, 15, 10, 8), 0);
+        assert_eq!(light(&world, LightChannel::Block, 16, 10, 8), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn sky_light_fills_under_overhangs() {
+        let (dir, world) = flat_world("sky_light");
+        assert_eq!(light(&world, LightChannel::Sky, 8, 4, 8), 15);
+        assert_eq!(light(&world, LightChannel::Sky, 8, 3, 8), 0);
+        for x in 6..=10 {
+            for z in 6..=10 {
+                world.get_block(&BlockPosition::new(x, 6, z)).set_type(1);
+            }
+        }
+        // Under the middle of a 5x5 roof the light has come three blocks sideways and one down.
+        assert_eq!(light(&world, LightChannel::Sky, 8, 5, 8), 12);
+        assert_eq!(light(&world, LightChannel::Sky, 8, 4, 8), 12);
+        assert_eq!(light(&world, LightChannel::Sky, 8, 7, 8), 15);
+        world.get_block(&BlockPosition::new(8, 6, 8)).set_type(0);
+        assert_eq!(light(&world, LightChannel::Sky, 8, 4, 8), 15);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    fn temp_world_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("exo_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+    #[test]
+    fn level_dat_round_trip() {
+        let dir = temp_world_dir("level_dat");
+        let mut world = World::new(
+            Box::new(MountainWorldGenerator::new(1234567890123)),
+            MCRegionLoader::new(&dir).unwrap(),
+        );
+        world.spawn_position = Position::from_pos(12., 70., -34.);
+        world.level_data = LevelData {
+            time: 18000,
+            raining: true,
+            rain_time: 4321,
+            thundering: true,
+            thunder_time: 99,
+        };
+        world.to_file(&dir).unwrap();
+        let loaded = World::from_file_mcr(&dir).unwrap();
+        assert_eq!(loaded.generator.get_seed(), 1234567890123);
+        assert_eq!(loaded.spawn_position.x, 12.);
+        assert_eq!(loaded.spawn_position.y, 70.);
+        assert_eq!(loaded.spawn_position.z, -34.);
+        assert_eq!(loaded.level_data, world.level_data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn generator_is_restored_from_level_dat() {
+        let dir = temp_world_dir("generator_name");
+        let mut world = World::new(
+            generator_from_config("flat", 0, "7, 2x3 ,12x1").unwrap(),
+            MCRegionLoader::new(&dir).unwrap(),
+        );
+        world.to_file(&dir).unwrap();
+        let loaded = World::from_file_mcr(&dir).unwrap();
+        assert_eq!(loaded.generator.get_name(), "FlatChunkGenerator");
+        assert_eq!(loaded.generator.get_options(), "7,2x3,12x1");
+        let chunk = loaded.generator.gen_chunk(ChunkCoords { x: 0, z: 0 });
+        assert_eq!(chunk.get_block(0, 0, 0).unwrap().b_type, 7);
+        assert_eq!(chunk.get_block(5, 2, 5).unwrap().b_type, 3);
+        assert_eq!(chunk.get_block(5, 14, 5).unwrap().b_type, 1);
+        assert_eq!(chunk.get_block(5, 15, 5).unwrap().b_type, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn flat_preset_rejects_bad_layers() {
+        assert!(FlatChunkGenerator::from_preset("7,0x3").is_err());
+        assert!(FlatChunkGenerator::from_preset("7,abc").is_err());
+        assert!(FlatChunkGenerator::from_preset("129x1").is_err());
+    }
+    #[test]
+    fn only_dirty_chunks_are_saved() {
+        init_registry();
+        let dir = temp_world_dir("dirty_chunks");
+        let mut world = World::new(
+            Box::new(MountainWorldGenerator::new(1)),
+            MCRegionLoader::new(&dir).unwrap(),
+        );
+        world.init_chunk(&ChunkCoords { x: 0, z: 0 });
+        world.init_chunk(&ChunkCoords { x: 1, z: 0 });
+        let summary = world.to_file(&dir).unwrap();
+        assert_eq!(summary.chunks_saved, summary.chunks_loaded);
+        assert_eq!(world.to_file(&dir).unwrap().chunks_saved, 0);
+        let world = WorldRef {
+            world: Arc::new(RefCell::new(world)),
+        };
+        world.get_block(&BlockPosition::new(20, 100, 3)).set_type(1);
+        assert_eq!(world.to_file(&dir).unwrap().chunks_saved, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn unloaded_chunk_reloads_with_changes() {
+        init_registry();
+        let dir = temp_world_dir("unload_reload");
+        let world = WorldRef {
+            world: Arc::new(RefCell::new(World::new(
+                Box::new(MountainWorldGenerator::new(1)),
+                MCRegionLoader::new(&dir).unwrap(),
+            ))),
+        };
+        let pos = BlockPosition::new(100, 120, 100);
+        world.get_block(&pos).set_type(20);
+        let coords = ChunkCoords { x: 100 >> 4, z: 100 >> 4 };
+        // Reload straight away, most likely while the write is still in flight, then again after.
+        world.get_world().unload_chunk(&coords).unwrap();
+        assert_eq!(world.get_block(&pos).get_type(), 20);
+        world.get_world().unload_chunk(&coords).unwrap();
+        world.get_world().wait_for_saves();
+        assert_eq!(world.get_block(&pos).get_type(), 20);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn scheduled_ticks_survive_unload() {
+        let (dir, world) = flat_world("tick_unload");
+        let pos = BlockPosition::new(-5, 3, 7);
+        let coords = ChunkCoords { x: -1, z: 0 };
+        world.get_world().scheduled_ticks.take_due(100);
+        world.get_world().schedule_tick(pos, 8, 104);
+        world.get_world().unload_chunk(&coords).unwrap();
+        world.get_world().wait_for_saves();
+        assert_eq!(world.get_world().scheduled_ticks.len(), 0);
+        world.get_world().scheduled_ticks.take_due(500);
+        world.get_block(&pos).get_type();
+        let mut world = world.get_world();
+        assert!(world.scheduled_ticks.take_due(503).is_empty());
+        assert_eq!(world.scheduled_ticks.take_due(504), vec![(pos, 8)]);
+        drop(world);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn removed_entities_stay_removed_after_reload() {
+        let (dir, world) = flat_world("entity_reload");
+        let coords = ChunkCoords { x: 0, z: 0 };
+        let mut item = CompoundTag::new();
+        item.insert_str("id", "Item");
+        {
+            let mut world = world.get_world();
+            let chunk = world.chunks.get_mut(&coords).unwrap();
+            chunk.entities.push(item);
+            chunk.dirty = true;
+            world.unload_chunk(&coords).unwrap();
+            world.wait_for_saves();
+            world.init_chunk(&coords);
+            // The game spawns the item and it is then picked up, so nothing is stored back.
+            let (entities, _) = world.take_pending_entities();
+            assert_eq!(entities.len(), 1);
+            world.unload_chunk(&coords).unwrap();
+            world.wait_for_saves();
+            world.init_chunk(&coords);
+            let (entities, _) = world.take_pending_entities();
+            assert!(entities.is_empty());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn failed_unload_keeps_entities() {
+        let (dir, world) = flat_world("failed_unload");
+        let coords = ChunkCoords { x: 1, z: 0 };
+        let mut item = CompoundTag::new();
+        item.insert_str("id", "Item");
+        let mut world = world.get_world();
+        let chunk = world.chunks.get_mut(&coords).unwrap();
+        chunk.entities.push(item);
+        chunk.dirty = true;
+        // Without a saver the write can't be queued.
+        world.saver = None;
+        assert!(world.unload_chunk(&coords).is_err());
+        assert!(world.chunks.get(&coords).unwrap().dirty);
+        let (entities, _) = world.take_pending_entities();
+        assert_eq!(entities.len(), 1);
+        drop(world);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn disk_chunks_keep_their_saved_light() {
+        let (dir, world) = flat_world("saved_light");
+        let coords = ChunkCoords { x: 0, z: 0 };
+        let mut world = world.get_world();
+        // Underground, where lighting the chunk again would give 0.
+        world.chunks.get_mut(&coords).unwrap().set_light(LightChannel::Sky, 3, 1, 3, 9);
+        world.chunks.get_mut(&coords).unwrap().dirty = true;
+        world.unload_chunk(&coords).unwrap();
+        world.wait_for_saves();
+        world.init_chunk(&coords);
+        assert_eq!(world.get_light(LightChannel::Sky, 3, 1, 3), Some(9));
+        drop(world);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn light_setters_mark_the_chunk_dirty() {
+        let (dir, world) = flat_world("light_setters");
+        world.to_file(&dir).unwrap();
+        let block = world.get_block(&BlockPosition::new(4, 20, 4));
+        block.set_skylight(block.get_skylight());
+        assert_eq!(world.to_file(&dir).unwrap().chunks_saved, 0);
+        block.set_light(7);
+        assert_eq!(world.to_file(&dir).unwrap().chunks_saved, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn light_spreading_into_a_chunk_marks_it_dirty() {
+        let (dir, world) = flat_world("light_dirty");
+        world.to_file(&dir).unwrap();
+        world.get_block(&BlockPosition::new(15, 10, 8)).set_type(50);
+        let world = world.get_world();
+        assert!(world.chunks.get(&ChunkCoords { x: 1, z: 0 }).unwrap().dirty);
+        // Too far for the torch to reach.
+        assert!(!world.chunks.get(&ChunkCoords { x: -1, z: 0 }).unwrap().dirty);
+        drop(world);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn level_dat_is_gzipped() {
+        let dir = temp_world_dir("level_dat_gzip");
+        let mut world = World::new(
+            Box::new(MountainWorldGenerator::new(1)),
+            MCRegionLoader::new(&dir).unwrap(),
+        );
+        world.to_file(&dir).unwrap();
+        let bytes = std::fs::read(format!("{}/level.dat", dir)).unwrap();
+        assert_eq!(&bytes[0..2], &[0x1f, 0x8b]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn sections_pack_blocks_and_nibbles() {
+        let mut chunk = Chunk::epic_generate(0, 0);
+        let block = Block { b_type: 89, b_metadata: 5, b_light: 15, b_skylight: 3 };
+        chunk.set_block(3, 37, 14, block).unwrap();
+        chunk.set_light(LightChannel::Block, 3, 38, 14, 9).unwrap();
+        assert_eq!(chunk.get_block(3, 37, 14), Some(block));
+        assert_eq!(chunk.light(LightChannel::Block, 3, 38, 14), Some(9));
+        assert_eq!(chunk.get_block(3, 38, 14).unwrap().b_metadata, 0);
+        assert_eq!(chunk.get_block(3, 128, 14), None);
+        let [blocks, metadata, block_light, sky_light] = chunk.region_arrays();
+        let index = 3 << 11 | 14 << 7 | 37;
+        assert_eq!(blocks[index], 89);
+        assert_eq!(metadata[index / 2] >> 4, 5);
+        let sections = Chunk::sections_from_region(0, 0, &blocks, &metadata, &block_light, &sky_light).unwrap();
+        let section = sections[2].as_ref().unwrap();
+        assert_eq!(section.get_block(ChunkSection::pos_to_index(3, 5, 14)), Some(block));
+        assert_eq!(section.light(LightChannel::Block, ChunkSection::pos_to_index(3, 6, 14)), 9);
+    }
+    #[test]
+    fn map_chunk_is_cached_until_the_chunk_changes() {
+        use std::io::Read;
+        let mut chunk = Chunk::epic_generate(2, -1);
+        chunk.map_chunk_packet().unwrap();
+        assert!(chunk.packet_cache.is_some());
+        chunk.set_block(5, 20, 7, Block { b_type: 4, ..Block::default() }).unwrap();
+        assert!(chunk.packet_cache.is_none());
+        match chunk.map_chunk_packet().unwrap() {
+            ServerPacket::MapChunk { x, y, z, size_y, compressed_data, .. } => {
+                assert_eq!((x, y, z, size_y), (32, 0, -16, 127));
+                let mut data = Vec::new();
+                flate2::read::ZlibDecoder::new(&compressed_data[..]).read_to_end(&mut data).unwrap();
+                assert_eq!(data.len(), 81920);
+                assert_eq!(data[5 << 11 | 7 << 7 | 20], 4);
+            }
+            _ => panic!("Expected a MapChunk"),
+        }
     }
 }
@@ -0,0 +1,109 @@
+use crate::game::ChunkCoords;
+use crate::world::chunks::{generate_terrain, Chunk, WorldGenerator};
+use crate::world::mcregion::MCRegionLoader;
+use flume::{Receiver, Sender};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+/// Where a finished chunk came from, which decides what the world still has to do with it.
+pub enum LoadedChunk {
+    /// Read from a region file, with the light it was saved with.
+    Disk(Chunk),
+    /// Fresh terrain, carved and lit but without structures or population.
+    Generated(Chunk),
+    /// The worker panicked. The chunk can be requested again.
+    Failed,
+}
+/// Reads and generates chunks on a pool of worker threads so the tick loop never waits on a
+/// region file or the noise generators. Finished chunks are handed back through `poll`.
+pub struct ChunkLoader {
+    jobs: Sender<ChunkCoords>,
+    results: Receiver<(ChunkCoords, LoadedChunk)>,
+    /// Chunks requested and not yet polled, so each is only worked on once.
+    pending: HashSet<ChunkCoords>,
+    /// Results received by `wait_for` while looking for another chunk.
+    ready: Vec<(ChunkCoords, LoadedChunk)>,
+}
+impl ChunkLoader {
+    pub fn new(
+        world_dir: &str,
+        generator: Arc<Box<dyn WorldGenerator>>,
+        region_lock: Arc<Mutex<()>>,
+        workers: usize,
+    ) -> Self {
+        let (jobs, job_recv) = flume::unbounded::<ChunkCoords>();
+        let (result_send, results) = flume::unbounded();
+        for i in 0..workers.max(1) {
+            let job_recv = job_recv.clone();
+            let result_send = result_send.clone();
+            let generator = generator.clone();
+            let region_lock = region_lock.clone();
+            let dir = world_dir.to_string();
+            std::thread::Builder::new()
+                .name(format!("chunk-worker-{}", i))
+                .spawn(move || {
+                    let mut loader = MCRegionLoader::new(&dir).ok();
+                    for coords in job_recv.iter() {
+                        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                            let read = loader.as_mut().and_then(|loader| {
+                                let _guard = region_lock.lock().unwrap();
+                                loader.get_chunk(coords)
+                            });
+                            match read {
+                                Some(chunk) => LoadedChunk::Disk(chunk),
+                                None => LoadedChunk::Generated(generate_terrain(&**generator, coords)),
+                            }
+                        }))
+                        .unwrap_or_else(|_| {
+                            log::error!("Chunk worker panicked on ({}, {})", coords.x, coords.z);
+                            LoadedChunk::Failed
+                        });
+                        if result_send.send((coords, result)).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("Failed to start a chunk worker thread");
+        }
+        Self {
+            jobs,
+            results,
+            pending: HashSet::new(),
+            ready: Vec::new(),
+        }
+    }
+    /// Queue a chunk. Does nothing if it is already on its way.
+    pub fn request(&mut self, coords: ChunkCoords) {
+        if self.pending.insert(coords) && self.jobs.send(coords).is_err() {
+            self.pending.remove(&coords);
+        }
+    }
+    pub fn is_pending(&self, coords: &ChunkCoords) -> bool {
+        self.pending.contains(coords)
+    }
+    /// Chunks finished since the last poll.
+    pub fn poll(&mut self) -> Vec<(ChunkCoords, LoadedChunk)> {
+        let mut finished = std::mem::take(&mut self.ready);
+        finished.extend(self.results.try_iter());
+        for (coords, _) in finished.iter() {
+            self.pending.remove(coords);
+        }
+        finished
+    }
+    /// Block until a requested chunk is done, keeping anything else that finishes meanwhile for
+    /// the next poll. `None` if the chunk was never requested.
+    pub fn wait_for(&mut self, coords: &ChunkCoords) -> Option<LoadedChunk> {
+        if !self.pending.remove(coords) {
+            return None;
+        }
+        if let Some(index) = self.ready.iter().position(|(c, _)| c == coords) {
+            return Some(self.ready.remove(index).1);
+        }
+        while let Ok((c, chunk)) = self.results.recv() {
+            if c == *coords {
+                return Some(chunk);
+            }
+            self.ready.push((c, chunk));
+        }
+        None
+    }
+}
@@ -10,6 +10,7 @@ use crate::game::ChunkCoords;
 use crate::game::Position;
 use crate::world;
 use crate::world::chunks::*;
+use crate::world::ticks::PendingTick;
 use anvil_region::*;
 pub struct MCRegionLoader {
     pub world_dir: String,
@@ -28,19 +29,18 @@ impl MCRegionLoader {
             cheating: FolderRegionProvider::new(&format!("{}/region", path.to_string())),
         })
     }
-    pub fn save_all(&mut self, world: &mut World) -> anyhow::Result<()> {
+    /// Save the dirty chunks in the world, returning how many were written.
+    pub fn save_all(&mut self, world: &mut World) -> anyhow::Result<usize> {
+        let mut saved = 0;
         for (coords, chunk) in world.chunks.iter_mut() {
-            let mut region = self
-                .cheating
-                .get_region(RegionPosition::from_chunk_position(coords.x, coords.z))?;
-            region
-                .write_chunk(
-                    RegionChunkPosition::from_chunk_position(coords.x, coords.z),
-                    Self::chunk_to_nbt(chunk)?,
-                )
-                .or(Err(anyhow::anyhow!("Bad write")))?;
+            if chunk.dirty {
+                chunk.tile_ticks = world.scheduled_ticks.chunk_ticks(coords, false);
+                self.save_chunk(chunk)?;
+                chunk.tile_ticks.clear();
+                saved += 1;
+            }
         }
-        return Ok(());
+        return Ok(saved);
         /*         std::fs::create_dir_all(&format!("{}/region", self.world_dir))?;
                 let mut i = 0;
                 for (coords, chunk) in world.chunks.iter_mut() {
@@ -108,25 +108,45 @@ impl MCRegionLoader {
         //std::process::exit(0);
         Ok(())
     }
+    pub fn save_chunk(&mut self, chunk: &mut Chunk) -> anyhow::Result<()> {
+        let mut region = self
+            .cheating
+            .get_region(RegionPosition::from_chunk_position(chunk.x, chunk.z))?;
+        region
+            .write_chunk(
+                RegionChunkPosition::from_chunk_position(chunk.x, chunk.z),
+                Self::chunk_to_nbt(chunk)?,
+            )
+            .or(Err(anyhow::anyhow!("Bad write")))?;
+        chunk.dirty = false;
+        Ok(())
+    }
     pub fn chunk_to_nbt(chunk: &mut Chunk) -> anyhow::Result<CompoundTag> {
         let mut root_tag = CompoundTag::new();
         let mut level_tag = CompoundTag::new();
-        let mut block_data = Vec::with_capacity(chunk.data.len());
-        let mut metadata = Vec::with_capacity(chunk.data.len() / 2);
-        for x in 0..16 {
-            for z in 0..16 {
-                for y in 0..128 {
-                    block_data.push(chunk.get_block(x, y, z).unwrap().b_type as i8);
-                    metadata.push(chunk.get_block(x, y, z).unwrap().b_metadata);
-                }
+        let [block_data, metadata, blocklight, skylight] = chunk.region_arrays();
+        // Vanilla stores the height of the first block above the highest opaque one, indexed z * 16 + x.
+        let mut heightmap = Vec::with_capacity(256);
+        for z in 0..16 {
+            for x in 0..16 {
+                heightmap.push((chunk.heightmap[x][z] as u8).wrapping_add(1) as i8);
             }
         }
-        //log::info!("Block len: {:?}", block_data.len());
-        let metadata = crate::world::chunks::compress_to_nibble(metadata)
-            .ok_or(anyhow::anyhow!("Bad compression to nibbles!"))?;
-        let metadata = vec_u8_into_i8(metadata);
-        level_tag.insert_i8_vec("Data", metadata);
-        level_tag.insert_i8_vec("Blocks", block_data);
+        level_tag.insert_i8_vec("Data", vec_u8_into_i8(metadata));
+        level_tag.insert_i8_vec("Blocks", vec_u8_into_i8(block_data));
+        level_tag.insert_i8_vec("BlockLight", vec_u8_into_i8(blocklight));
+        level_tag.insert_i8_vec("SkyLight", vec_u8_into_i8(skylight));
+        level_tag.insert_i8_vec("HeightMap", heightmap);
+        level_tag.insert_i64("LastUpdate", chunk.last_update);
+        level_tag.insert_i8("TerrainPopulated", chunk.populated as i8);
+        level_tag.insert_compound_tag_vec("Entities", chunk.entities.clone());
+        level_tag.insert_compound_tag_vec("TileEntities", chunk.tile_entities.clone());
+        if !chunk.tile_ticks.is_empty() {
+            level_tag.insert_compound_tag_vec(
+                "TileTicks",
+                chunk.tile_ticks.iter().map(|tick| tick.to_tag()).collect::<Vec<_>>(),
+            );
+        }
         level_tag.insert_i32("xPos", chunk.x);
         level_tag.insert_i32("zPos", chunk.z);
         root_tag.insert_compound_tag("Level", level_tag);
@@ -266,18 +286,17 @@ impl Region {
         //log::info!("Got to here!");
         let block_metadata = vec_i8_into_u8(val.clone());
         use super::chunks::*;
-        let metadata = super::chunks::decompress_vec(block_metadata).unwrap();
-        let mut blocks = Vec::new();
-        let mut i = 0;
-        for block in block_ids {
-            blocks.push(Block {
-                b_type: block,
-                b_metadata: metadata[i],
-                b_light: 0,
-                b_skylight: 0,
-            });
-            i += 1;
-        }
+        // Light is optional, chunks written by older versions of the server don't have it.
+        let blocklight = tag
+            .get_i8_vec("BlockLight")
+            .ok()
+            .filter(|v| v.len() == 16384)
+            .map_or(vec![0; 16384], |v| vec_i8_into_u8(v.clone()));
+        let skylight = tag
+            .get_i8_vec("SkyLight")
+            .ok()
+            .filter(|v| v.len() == 16384)
+            .map_or(vec![0; 16384], |v| vec_i8_into_u8(v.clone()));
         let x_pos = tag
             .get_i32("xPos")
             .or(Err(anyhow::anyhow!("Does not exist!")))?;
@@ -286,40 +305,38 @@ impl Region {
             .or(Err(anyhow::anyhow!("Does not exist!")))?;
         //log::info!("Compression type: {}", comp_type);
         //log::info!("Pos: {} {}", x_pos, z_pos);
-        let mut chunksections = Vec::new();
-        for i in 0..8 {
-            chunksections.push(ChunkSection::new(x_pos, z_pos, i));
-        }
-        for section in 0..8 {
-            for x in 0..16 {
-                for z in 0..16 {
-                    for y in 0..16 {
-                        let y = y + (section * 16);
-                        //log::info!("Doing section {}, {} {} {}", section, x, y, z);
-                        let section = chunksections.get_mut(section).unwrap();
-                        section
-                            .get_data()
-                            .push(blocks[Self::pos_to_idx(x, y as i32, z)]);
-                    }
-                }
-            }
-        }
         let mut chunk = Chunk {
             x: x_pos,
             z: z_pos,
-            data: [
-                Some(chunksections[0].clone()),
-                Some(chunksections[1].clone()),
-                Some(chunksections[2].clone()),
-                Some(chunksections[3].clone()),
-                Some(chunksections[4].clone()),
-                Some(chunksections[5].clone()),
-                Some(chunksections[6].clone()),
-                Some(chunksections[7].clone()),
-            ],
+            data: Chunk::sections_from_region(
+                x_pos,
+                z_pos,
+                &block_ids,
+                &block_metadata,
+                &blocklight,
+                &skylight,
+            )?,
             heightmap: [[0; 16]; 16],
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            populated: false,
+            last_update: 0,
+            dirty: false,
+            tile_ticks: Vec::new(),
+            packet_cache: None,
         };
         chunk.calculate_heightmap()?;
+        chunk.populated = tag.get_i8("TerrainPopulated").map_or(true, |v| v != 0);
+        chunk.last_update = tag.get_i64("LastUpdate").unwrap_or(0);
+        chunk.entities = tag
+            .get_compound_tag_vec("Entities")
+            .map_or(Vec::new(), |v| v.into_iter().cloned().collect());
+        chunk.tile_entities = tag
+            .get_compound_tag_vec("TileEntities")
+            .map_or(Vec::new(), |v| v.into_iter().cloned().collect());
+        chunk.tile_ticks = tag
+            .get_compound_tag_vec("TileTicks")
+            .map_or(Vec::new(), |v| v.into_iter().filter_map(PendingTick::from_tag).collect());
         return Ok(chunk);
     }
     pub fn from_file(file: &str) -> anyhow::Result<Self> {
@@ -415,18 +432,6 @@ impl Region {
             log::info!("Got to here!");
             let block_metadata = vec_i8_into_u8(val.clone());
             use super::chunks::*;
-            let metadata = super::chunks::decompress_vec(block_metadata).unwrap();
-            let mut blocks = Vec::new();
-            let mut i = 0;
-            for block in block_ids {
-                blocks.push(Block {
-                    b_type: block,
-                    b_metadata: metadata[i],
-                    b_light: 0,
-                    b_skylight: 0,
-                });
-                i += 1;
-            }
             let x_pos = tag
                 .get_i32("xPos")
                 .or(Err(anyhow::anyhow!("Does not exist!")))?;
@@ -435,38 +440,25 @@ impl Region {
                 .or(Err(anyhow::anyhow!("Does not exist!")))?;
             //log::info!("Compression type: {}", comp_type);
             log::info!("Pos: {} {}", x_pos, z_pos);
-            let mut chunksections = Vec::new();
-            for i in 0..8 {
-                chunksections.push(ChunkSection::new(x_pos, z_pos, i));
-            }
-            for section in 0..8 {
-                for x in 0..16 {
-                    for z in 0..16 {
-                        for y in 0..16 {
-                            let y = y + (section * 16);
-                            //log::info!("Doing section {}, {} {} {}", section, x, y, z);
-                            let section = chunksections.get_mut(section).unwrap();
-                            section
-                                .get_data()
-                                .push(blocks[Self::pos_to_idx(x, y as i32, z)]);
-                        }
-                    }
-                }
-            }
             let mut chunk = Chunk {
                 x: x_pos,
                 z: z_pos,
-                data: [
-                    Some(chunksections[0].clone()),
-                    Some(chunksections[1].clone()),
-                    Some(chunksections[2].clone()),
-                    Some(chunksections[3].clone()),
-                    Some(chunksections[4].clone()),
-                    Some(chunksections[5].clone()),
-                    Some(chunksections[6].clone()),
-                    Some(chunksections[7].clone()),
-                ],
+                data: Chunk::sections_from_region(
+                    x_pos,
+                    z_pos,
+                    &block_ids,
+                    &block_metadata,
+                    &[0; 16384],
+                    &[0; 16384],
+                )?,
                 heightmap: [[0; 16]; 16],
+                entities: Vec::new(),
+                tile_entities: Vec::new(),
+                populated: false,
+                last_update: 0,
+                dirty: false,
+                tile_ticks: Vec::new(),
+                packet_cache: None,
             };
             chunk.calculate_heightmap()?;
             chunks.push(chunk);
@@ -474,9 +466,6 @@ impl Region {
         Ok(Self { chunks })
         //Err(anyhow::anyhow!("Balls"))
     }
-    fn pos_to_idx(x: i32, y: i32, z: i32) -> usize {
-        (y + (z * 128) + (x * 128 * 16)) as usize
-    }
 }
 
 fn vec_u8_into_i8(v: Vec<u8>) -> Vec<i8> {
@@ -513,3 +502,43 @@ fn vec_i8_into_u8(v: Vec<i8>) -> Vec<u8> {
     // finally, adopt the data into a new Vec
     unsafe { Vec::from_raw_parts(p as *mut u8, len, cap) }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn chunk_nbt_round_trip() {
+        let mut chunk = FlatChunkGenerator::default().gen_chunk(ChunkCoords { x: 3, z: -2 });
+        chunk.update_block(4, 70, 9, |block| {
+            block.b_type = 50;
+            block.b_light = 14;
+            block.b_skylight = 7;
+        });
+        chunk.populated = true;
+        chunk.last_update = 1234;
+        let mut entity = CompoundTag::new();
+        entity.insert_str("id", "Item");
+        chunk.entities.push(entity);
+        let mut tile_entity = CompoundTag::new();
+        tile_entity.insert_str("id", "Trapdoor");
+        chunk.tile_entities.push(tile_entity);
+        let tick = PendingTick {
+            position: crate::game::BlockPosition::new(52, 70, -23),
+            block_type: 8,
+            delay: 4,
+        };
+        chunk.tile_ticks.push(tick);
+        let tag = MCRegionLoader::chunk_to_nbt(&mut chunk).unwrap();
+        let level = tag.get_compound_tag("Level").unwrap();
+        assert_eq!(level.get_i8_vec("SkyLight").unwrap().len(), 16384);
+        assert_eq!(level.get_i8_vec("HeightMap").unwrap().len(), 256);
+        let loaded = Region::chunk_from_tag(level).unwrap();
+        assert_eq!((loaded.x, loaded.z), (3, -2));
+        assert_eq!(loaded.get_block(4, 70, 9), chunk.get_block(4, 70, 9));
+        assert_eq!(loaded.get_block(0, 0, 0), chunk.get_block(0, 0, 0));
+        assert!(loaded.populated);
+        assert_eq!(loaded.last_update, 1234);
+        assert_eq!(loaded.entities.len(), 1);
+        assert_eq!(loaded.tile_entities[0].get_str("id").unwrap(), "Trapdoor");
+        assert_eq!(loaded.tile_ticks, vec![tick]);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::game::ChunkCoords;
+use crate::world::chunks::{write_level_dat, Chunk, SaveSummary};
+use crate::world::mcregion::MCRegionLoader;
+use flume::{Receiver, Sender};
+use nbt::CompoundTag;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+/// A snapshot of everything one save needs to write.
+struct SaveJob {
+    requester: Option<String>,
+    chunks: Vec<(u64, Arc<Chunk>)>,
+    chunks_loaded: usize,
+    level_dat: Option<CompoundTag>,
+}
+/// Sent back to the game once a job has been written.
+pub struct SaveResult {
+    /// Who asked for the save, `None` for chunks written because they were unloaded.
+    pub requester: Option<String>,
+    pub summary: SaveSummary,
+    /// Chunks that could not be written, handed back so they aren't lost.
+    pub failed: Vec<Chunk>,
+    pub error: Option<anyhow::Error>,
+}
+/// Compresses and writes world saves on a background thread so the tick loop never waits on disk.
+pub struct WorldSaver {
+    jobs: Sender<SaveJob>,
+    results: Receiver<SaveResult>,
+    /// Chunks queued but not yet on disk. Loads check here first so they never see stale data.
+    in_flight: Arc<Mutex<HashMap<ChunkCoords, (u64, Arc<Chunk>)>>>,
+    /// Held around every region file access, the tick thread's reads included.
+    region_lock: Arc<Mutex<()>>,
+    next_generation: u64,
+    pending_jobs: usize,
+}
+impl WorldSaver {
+    pub fn new(world_dir: &str) -> Self {
+        let (jobs, job_recv) = flume::unbounded::<SaveJob>();
+        let (result_send, results) = flume::unbounded();
+        let in_flight = Arc::new(Mutex::new(HashMap::new()));
+        let region_lock = Arc::new(Mutex::new(()));
+        let dir = world_dir.to_string();
+        let thread_in_flight = in_flight.clone();
+        let thread_region_lock = region_lock.clone();
+        std::thread::Builder::new()
+            .name("world-saver".to_string())
+            .spawn(move || {
+                let mut loader = MCRegionLoader::new(&dir);
+                for job in job_recv.iter() {
+                    let result = match loader.as_mut() {
+                        Ok(loader) => {
+                            Self::write_job(loader, job, &thread_in_flight, &thread_region_lock)
+                        }
+                        Err(e) => SaveResult {
+                            requester: job.requester,
+                            summary: SaveSummary {
+                                chunks_saved: 0,
+                                chunks_loaded: job.chunks_loaded,
+                            },
+                            failed: job.chunks.into_iter().map(|(_, c)| (*c).clone()).collect(),
+                            error: Some(anyhow::anyhow!("Could not open the region folder: {:?}", e)),
+                        },
+                    };
+                    if result_send.send(result).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to start the world saver thread");
+        Self {
+            jobs,
+            results,
+            in_flight,
+            region_lock,
+            next_generation: 0,
+            pending_jobs: 0,
+        }
+    }
+    fn write_job(
+        loader: &mut MCRegionLoader,
+        job: SaveJob,
+        in_flight: &Mutex<HashMap<ChunkCoords, (u64, Arc<Chunk>)>>,
+        region_lock: &Mutex<()>,
+    ) -> SaveResult {
+        let mut result = SaveResult {
+            requester: job.requester,
+            summary: SaveSummary {
+                chunks_saved: 0,
+                chunks_loaded: job.chunks_loaded,
+            },
+            failed: Vec::new(),
+            error: None,
+        };
+        for (generation, chunk) in job.chunks {
+            let mut chunk = (*chunk).clone();
+            let written = {
+                let _guard = region_lock.lock().unwrap();
+                loader.save_chunk(&mut chunk)
+            };
+            let coords = ChunkCoords {
+                x: chunk.x,
+                z: chunk.z,
+            };
+            match written {
+                Ok(()) => result.summary.chunks_saved += 1,
+                Err(e) => {
+                    result.failed.push(chunk);
+                    result.error = Some(e);
+                }
+            }
+            // A newer snapshot of the same chunk may have been queued meanwhile, leave that one alone.
+            let mut in_flight = in_flight.lock().unwrap();
+            if in_flight.get(&coords).map_or(false, |(g, _)| *g == generation) {
+                in_flight.remove(&coords);
+            }
+        }
+        if let Some(level_dat) = job.level_dat {
+            if let Err(e) = write_level_dat(&loader.world_dir, &level_dat) {
+                result.error = Some(e);
+            }
+        }
+        result
+    }
+    /// Hand a snapshot to the background thread.
+    pub fn queue(
+        &mut self,
+        requester: Option<&str>,
+        chunks: Vec<Chunk>,
+        chunks_loaded: usize,
+        level_dat: Option<CompoundTag>,
+    ) -> anyhow::Result<()> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let mut snapshot = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            self.next_generation += 1;
+            let chunk = Arc::new(chunk);
+            in_flight.insert(
+                ChunkCoords {
+                    x: chunk.x,
+                    z: chunk.z,
+                },
+                (self.next_generation, chunk.clone()),
+            );
+            snapshot.push((self.next_generation, chunk));
+        }
+        drop(in_flight);
+        self.jobs
+            .send(SaveJob {
+                requester: requester.map(|r| r.to_string()),
+                chunks: snapshot,
+                chunks_loaded,
+                level_dat,
+            })
+            .or(Err(anyhow::anyhow!("World saver thread has stopped")))?;
+        self.pending_jobs += 1;
+        Ok(())
+    }
+    /// A copy of the chunk if it is still waiting to be written.
+    pub fn in_flight_chunk(&self, coords: &ChunkCoords) -> Option<Chunk> {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .get(coords)
+            .map(|(_, chunk)| (**chunk).clone())
+    }
+    pub fn lock_regions(&self) -> MutexGuard<'_, ()> {
+        self.region_lock.lock().unwrap()
+    }
+    /// The lock `lock_regions` takes, for other threads that read region files.
+    pub fn region_lock(&self) -> Arc<Mutex<()>> {
+        self.region_lock.clone()
+    }
+    /// Results of the jobs that have finished since the last poll.
+    pub fn poll(&mut self) -> Vec<SaveResult> {
+        let results: Vec<SaveResult> = self.results.try_iter().collect();
+        self.pending_jobs -= results.len();
+        results
+    }
+    /// Block until every queued job has been written.
+    pub fn wait(&mut self) -> Vec<SaveResult> {
+        let mut results = Vec::new();
+        while self.pending_jobs > 0 {
+            match self.results.recv() {
+                Ok(result) => {
+                    self.pending_jobs -= 1;
+                    results.push(result);
+                }
+                Err(_) => break,
+            }
+        }
+        results
+    }
+}
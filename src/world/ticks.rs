@@ -0,0 +1,130 @@
+use crate::game::{BlockPosition, ChunkCoords};
+use nbt::CompoundTag;
+use std::collections::{BTreeMap, HashSet};
+/// A block update waiting in a saved chunk, stored the way later vanilla versions write `TileTicks`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PendingTick {
+    pub position: BlockPosition,
+    pub block_type: u8,
+    /// Ticks left until it is due, counted from when the chunk was saved.
+    pub delay: i32,
+}
+impl PendingTick {
+    pub fn to_tag(&self) -> CompoundTag {
+        let mut tag = CompoundTag::new();
+        tag.insert_i32("i", self.block_type as i32);
+        tag.insert_i32("x", self.position.x);
+        tag.insert_i32("y", self.position.y);
+        tag.insert_i32("z", self.position.z);
+        tag.insert_i32("t", self.delay);
+        tag
+    }
+    pub fn from_tag(tag: &CompoundTag) -> Option<Self> {
+        Some(Self {
+            position: BlockPosition::new(tag.get_i32("x").ok()?, tag.get_i32("y").ok()?, tag.get_i32("z").ok()?),
+            block_type: tag.get_i32("i").ok()? as u8,
+            delay: tag.get_i32("t").ok()?,
+        })
+    }
+}
+/// Block updates scheduled for a later game tick.
+///
+/// A block only has one update queued at a time, scheduling it again before it runs does nothing.
+#[derive(Default)]
+pub struct TickQueue {
+    due: BTreeMap<u128, Vec<(BlockPosition, u8)>>,
+    queued: HashSet<(BlockPosition, u8)>,
+    /// The last tick updates were taken for, what saved delays are measured from.
+    now: u128,
+}
+impl TickQueue {
+    pub fn schedule(&mut self, position: BlockPosition, block_type: u8, tick: u128) {
+        if self.queued.insert((position, block_type)) {
+            self.due.entry(tick).or_default().push((position, block_type));
+        }
+    }
+    /// Removes and returns every update due on or before `tick`, oldest first.
+    pub fn take_due(&mut self, tick: u128) -> Vec<(BlockPosition, u8)> {
+        self.now = tick;
+        let later = self.due.split_off(&(tick + 1));
+        let due = std::mem::replace(&mut self.due, later);
+        let updates: Vec<(BlockPosition, u8)> = due.into_iter().flat_map(|(_, u)| u).collect();
+        for update in &updates {
+            self.queued.remove(update);
+        }
+        updates
+    }
+    /// The updates queued inside a chunk, optionally taking them out of the queue.
+    pub fn chunk_ticks(&mut self, coords: &ChunkCoords, remove: bool) -> Vec<PendingTick> {
+        let in_chunk = |position: &BlockPosition| {
+            position.x >> 4 == coords.x && position.z >> 4 == coords.z
+        };
+        let mut ticks = Vec::new();
+        for (tick, updates) in self.due.iter_mut() {
+            for (position, block_type) in updates.iter().filter(|(p, _)| in_chunk(p)) {
+                ticks.push(PendingTick {
+                    position: *position,
+                    block_type: *block_type,
+                    delay: tick.saturating_sub(self.now) as i32,
+                });
+            }
+            if remove {
+                updates.retain(|(p, _)| !in_chunk(p));
+            }
+        }
+        if remove {
+            self.due.retain(|_, updates| !updates.is_empty());
+            for tick in &ticks {
+                self.queued.remove(&(tick.position, tick.block_type));
+            }
+        }
+        ticks
+    }
+    /// Put back the updates of a chunk that was just loaded.
+    pub fn restore(&mut self, ticks: Vec<PendingTick>) {
+        for tick in ticks {
+            self.schedule(tick.position, tick.block_type, self.now + tick.delay.max(0) as u128);
+        }
+    }
+    /// The last tick updates were taken for.
+    pub fn now(&self) -> u128 {
+        self.now
+    }
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn ticks_are_deduplicated_and_run_in_order() {
+        let mut queue = TickQueue::default();
+        let a = BlockPosition::new(0, 64, 0);
+        let b = BlockPosition::new(1, 64, 0);
+        queue.schedule(b, 8, 10);
+        queue.schedule(a, 8, 5);
+        queue.schedule(a, 8, 7);
+        queue.schedule(a, 13, 7);
+        assert_eq!(queue.len(), 3);
+        assert!(queue.take_due(4).is_empty());
+        assert_eq!(queue.take_due(10), vec![(a, 8), (a, 13), (b, 8)]);
+        assert_eq!(queue.len(), 0);
+    }
+    #[test]
+    fn chunk_ticks_keep_their_delay() {
+        let mut queue = TickQueue::default();
+        queue.take_due(100);
+        let inside = BlockPosition::new(-1, 64, 5);
+        let outside = BlockPosition::new(1, 64, 5);
+        queue.schedule(inside, 8, 105);
+        queue.schedule(outside, 8, 105);
+        let ticks = queue.chunk_ticks(&ChunkCoords { x: -1, z: 0 }, true);
+        assert_eq!(ticks, vec![PendingTick { position: inside, block_type: 8, delay: 5 }]);
+        assert_eq!(queue.len(), 1);
+        queue.take_due(200);
+        queue.restore(ticks);
+        assert!(queue.take_due(204).is_empty());
+        assert_eq!(queue.take_due(205), vec![(inside, 8)]);
+    }
+}
@@ -0,0 +1,691 @@
+//! A port of the Beta 1.7.3 overworld generator, `ChunkProviderGenerate`.
+//!
+//! Terrain, surface and caves follow the vanilla algorithms step for step, so the same seed should
+//! give the same landscape. Decorations run in `populate` once a chunk's neighbours exist, like
+//! vanilla. The quick tests check terrain before decoration against a transcription of the vanilla
+//! classes. `populated_chunks_match_a_real_world` compares finished chunks with worlds saved by a
+//! real b1.7.3 server, kept in `tests/fixtures/b173`.
+pub mod biome;
+pub mod caves;
+pub mod features;
+pub mod nether;
+pub mod noise;
+pub mod ravines;
+
+use self::biome::{Biome, BiomeSource};
+use self::features::{Region, Tree};
+use self::noise::{JavaRandom, OctaveNoise};
+use super::chunks::{Chunk, World, WorldGenerator};
+use crate::configuration::CONFIGURATION;
+use crate::game::fluids::Fluid;
+use crate::game::{BlockPosition, ChunkCoords};
+use std::collections::HashMap;
+/// Block ids the generator places or looks for.
+mod ids {
+    pub const AIR: u8 = 0;
+    pub const STONE: u8 = 1;
+    pub const GRASS: u8 = 2;
+    pub const DIRT: u8 = 3;
+    pub const COBBLESTONE: u8 = 4;
+    pub const BEDROCK: u8 = 7;
+    pub const WATER: u8 = 8;
+    pub const STILL_WATER: u8 = 9;
+    pub const LAVA: u8 = 10;
+    pub const STILL_LAVA: u8 = 11;
+    pub const SAND: u8 = 12;
+    pub const GRAVEL: u8 = 13;
+    pub const GOLD_ORE: u8 = 14;
+    pub const IRON_ORE: u8 = 15;
+    pub const COAL_ORE: u8 = 16;
+    pub const LOG: u8 = 17;
+    pub const LEAVES: u8 = 18;
+    pub const LAPIS_ORE: u8 = 21;
+    pub const SANDSTONE: u8 = 24;
+    pub const TALL_GRASS: u8 = 31;
+    pub const DEAD_BUSH: u8 = 32;
+    pub const DANDELION: u8 = 37;
+    pub const ROSE: u8 = 38;
+    pub const BROWN_MUSHROOM: u8 = 39;
+    pub const RED_MUSHROOM: u8 = 40;
+    pub const MOSSY_COBBLESTONE: u8 = 48;
+    pub const FIRE: u8 = 51;
+    pub const MOB_SPAWNER: u8 = 52;
+    pub const CHEST: u8 = 54;
+    pub const DIAMOND_ORE: u8 = 56;
+    pub const FARMLAND: u8 = 60;
+    pub const REDSTONE_ORE: u8 = 73;
+    pub const SNOW: u8 = 78;
+    pub const ICE: u8 = 79;
+    pub const CACTUS: u8 = 81;
+    pub const CLAY: u8 = 82;
+    pub const REED: u8 = 83;
+    pub const PUMPKIN: u8 = 86;
+    pub const NETHERRACK: u8 = 87;
+    pub const SOUL_SAND: u8 = 88;
+    pub const GLOWSTONE: u8 = 89;
+}
+use self::ids::*;
+/// Height of the sea surface, and of the band where beaches and the biome's surface blocks appear.
+const SEA_LEVEL: i32 = 64;
+/// The b1.7.3 overworld generator.
+pub struct VanillaChunkGenerator {
+    seed: i64,
+    biomes: BiomeSource,
+    low_noise: OctaveNoise,
+    high_noise: OctaveNoise,
+    selector_noise: OctaveNoise,
+    beach_noise: OctaveNoise,
+    surface_depth_noise: OctaveNoise,
+    scale_noise: OctaveNoise,
+    depth_noise: OctaveNoise,
+    tree_noise: OctaveNoise,
+}
+impl VanillaChunkGenerator {
+    pub fn new(seed: u64) -> Self {
+        let seed = seed as i64;
+        let mut random = JavaRandom::new(seed);
+        Self {
+            seed,
+            biomes: BiomeSource::new(seed),
+            low_noise: OctaveNoise::new(&mut random, 16),
+            high_noise: OctaveNoise::new(&mut random, 16),
+            selector_noise: OctaveNoise::new(&mut random, 8),
+            beach_noise: OctaveNoise::new(&mut random, 4),
+            surface_depth_noise: OctaveNoise::new(&mut random, 4),
+            scale_noise: OctaveNoise::new(&mut random, 10),
+            depth_noise: OctaveNoise::new(&mut random, 16),
+            tree_noise: OctaveNoise::new(&mut random, 8),
+        }
+    }
+    /// The chunk's blocks before decoration, indexed `x << 11 | z << 7 | y`.
+    pub fn generate_blocks(&self, chunk_x: i32, chunk_z: i32) -> Vec<u8> {
+        let mut random = JavaRandom::new(
+            (chunk_x as i64)
+                .wrapping_mul(341873128712)
+                .wrapping_add((chunk_z as i64).wrapping_mul(132897987541)),
+        );
+        let mut blocks = vec![AIR; 32768];
+        let climate = self.biomes.climate(chunk_x * 16, chunk_z * 16, 16, 16);
+        self.generate_terrain(chunk_x, chunk_z, &mut blocks, &climate.temperature, &climate.humidity);
+        self.replace_surface(chunk_x, chunk_z, &mut blocks, &climate.biomes, &mut random);
+        caves::carve(self.seed, chunk_x, chunk_z, &mut blocks);
+        blocks
+    }
+    /// The terrain density on a grid of 4 by 8 by 4 block cells, `func_4061_a`.
+    fn density(&self, origin: (i32, i32, i32), size: (usize, usize, usize), temperature: &[f64], humidity: &[f64]) -> Vec<f64> {
+        let (x, y, z) = origin;
+        let (size_x, size_y, size_z) = size;
+        let horizontal = 684.412;
+        let vertical = 684.412;
+        let mut scale = Vec::new();
+        let mut depth = Vec::new();
+        let mut selector = Vec::new();
+        let mut low = Vec::new();
+        let mut high = Vec::new();
+        self.scale_noise.grid_2d(&mut scale, x, z, size_x, size_z, 1.121, 1.121);
+        self.depth_noise.grid_2d(&mut depth, x, z, size_x, size_z, 200.0, 200.0);
+        let origin = (x as f64, y as f64, z as f64);
+        self.selector_noise.grid(
+            &mut selector,
+            origin,
+            size,
+            (horizontal / 80.0, vertical / 160.0, horizontal / 80.0),
+        );
+        self.low_noise.grid(&mut low, origin, size, (horizontal, vertical, horizontal));
+        self.high_noise.grid(&mut high, origin, size, (horizontal, vertical, horizontal));
+        let mut density = Vec::with_capacity(size_x * size_y * size_z);
+        let cell_width = 16 / size_x;
+        for cx in 0..size_x {
+            let sample_x = cx * cell_width + cell_width / 2;
+            for cz in 0..size_z {
+                let sample_z = cz * cell_width + cell_width / 2;
+                let temperature = temperature[sample_x * 16 + sample_z];
+                let humidity = humidity[sample_x * 16 + sample_z] * temperature;
+                let dryness = 1.0 - humidity;
+                let dryness = dryness * dryness;
+                let dryness = dryness * dryness;
+                let wetness = 1.0 - dryness;
+                let index = cx * size_z + cz;
+                let mut scale = ((scale[index] + 256.0) / 512.0) * wetness;
+                if scale > 1.0 {
+                    scale = 1.0;
+                }
+                let mut depth = depth[index] / 8000.0;
+                if depth < 0.0 {
+                    depth = -depth * 0.3;
+                }
+                depth = depth * 3.0 - 2.0;
+                if depth < 0.0 {
+                    depth /= 2.0;
+                    if depth < -1.0 {
+                        depth = -1.0;
+                    }
+                    depth /= 1.4;
+                    depth /= 2.0;
+                    scale = 0.0;
+                } else {
+                    if depth > 1.0 {
+                        depth = 1.0;
+                    }
+                    depth /= 8.0;
+                }
+                if scale < 0.0 {
+                    scale = 0.0;
+                }
+                scale += 0.5;
+                depth = (depth * size_y as f64) / 16.0;
+                let base_height = size_y as f64 / 2.0 + depth * 4.0;
+                for cy in 0..size_y {
+                    let index = density.len();
+                    let mut falloff = ((cy as f64 - base_height) * 12.0) / scale;
+                    if falloff < 0.0 {
+                        falloff *= 4.0;
+                    }
+                    let low = low[index] / 512.0;
+                    let high = high[index] / 512.0;
+                    let blend = (selector[index] / 10.0 + 1.0) / 2.0;
+                    let mut value = if blend < 0.0 {
+                        low
+                    } else if blend > 1.0 {
+                        high
+                    } else {
+                        low + (high - low) * blend
+                    };
+                    value -= falloff;
+                    if cy > size_y - 4 {
+                        let top = ((cy - (size_y - 4)) as f32 / 3.0) as f64;
+                        value = value * (1.0 - top) + -10.0 * top;
+                    }
+                    density.push(value);
+                }
+            }
+        }
+        density
+    }
+    /// Stone where the interpolated density is positive, water up to sea level, `generateTerrain`.
+    fn generate_terrain(&self, chunk_x: i32, chunk_z: i32, blocks: &mut [u8], temperature: &[f64], humidity: &[f64]) {
+        const CELLS: usize = 4;
+        const SIZE_X: usize = CELLS + 1;
+        const SIZE_Y: usize = 17;
+        const SIZE_Z: usize = CELLS + 1;
+        let density = self.density(
+            (chunk_x * CELLS as i32, 0, chunk_z * CELLS as i32),
+            (SIZE_X, SIZE_Y, SIZE_Z),
+            temperature,
+            humidity,
+        );
+        let at = |x: usize, z: usize, y: usize| density[(x * SIZE_Z + z) * SIZE_Y + y];
+        for cx in 0..CELLS {
+            for cz in 0..CELLS {
+                for cy in 0..SIZE_Y - 1 {
+                    let mut corner_a = at(cx, cz, cy);
+                    let mut corner_b = at(cx, cz + 1, cy);
+                    let mut corner_c = at(cx + 1, cz, cy);
+                    let mut corner_d = at(cx + 1, cz + 1, cy);
+                    let step_a = (at(cx, cz, cy + 1) - corner_a) * 0.125;
+                    let step_b = (at(cx, cz + 1, cy + 1) - corner_b) * 0.125;
+                    let step_c = (at(cx + 1, cz, cy + 1) - corner_c) * 0.125;
+                    let step_d = (at(cx + 1, cz + 1, cy + 1) - corner_d) * 0.125;
+                    for ly in 0..8 {
+                        let mut edge_a = corner_a;
+                        let mut edge_b = corner_b;
+                        let edge_step_a = (corner_c - corner_a) * 0.25;
+                        let edge_step_b = (corner_d - corner_b) * 0.25;
+                        for lx in 0..4 {
+                            let x = lx + cx * 4;
+                            let y = cy * 8 + ly;
+                            let mut index = x << 11 | (cz * 4) << 7 | y;
+                            let mut value = edge_a;
+                            let value_step = (edge_b - edge_a) * 0.25;
+                            for lz in 0..4 {
+                                let temperature = temperature[x * 16 + cz * 4 + lz];
+                                let mut block_type = AIR;
+                                if (y as i32) < SEA_LEVEL {
+                                    block_type = if temperature < 0.5 && y as i32 >= SEA_LEVEL - 1 {
+                                        ICE
+                                    } else {
+                                        STILL_WATER
+                                    };
+                                }
+                                if value > 0.0 {
+                                    block_type = STONE;
+                                }
+                                blocks[index] = block_type;
+                                index += 128;
+                                value += value_step;
+                            }
+                            edge_a += edge_step_a;
+                            edge_b += edge_step_b;
+                        }
+                        corner_a += step_a;
+                        corner_b += step_b;
+                        corner_c += step_c;
+                        corner_d += step_d;
+                    }
+                }
+            }
+        }
+    }
+    /// Grass, sand, gravel and bedrock over the bare stone, `replaceBlocksForBiome`.
+    fn replace_surface(&self, chunk_x: i32, chunk_z: i32, blocks: &mut [u8], biomes: &[Biome], random: &mut JavaRandom) {
+        let scale = 1.0 / 32.0;
+        let (x, z) = ((chunk_x * 16) as f64, (chunk_z * 16) as f64);
+        let mut sand = Vec::new();
+        let mut gravel = Vec::new();
+        let mut depth = Vec::new();
+        self.beach_noise.grid(&mut sand, (x, z, 0.0), (16, 16, 1), (scale, scale, 1.0));
+        self.beach_noise.grid(&mut gravel, (x, 109.0134, z), (16, 1, 16), (scale, 1.0, scale));
+        self.surface_depth_noise.grid(&mut depth, (x, z, 0.0), (16, 16, 1), (scale * 2.0, scale * 2.0, scale * 2.0));
+        for z in 0..16 {
+            for x in 0..16 {
+                let column = z + x * 16;
+                let biome = biomes[column];
+                let is_sand = sand[column] + random.next_double() * 0.2 > 0.0;
+                let is_gravel = gravel[column] + random.next_double() * 0.2 > 3.0;
+                let surface_depth = (depth[column] / 3.0 + 3.0 + random.next_double() * 0.25) as i32;
+                let mut remaining = -1;
+                let mut top = biome.top_block();
+                let mut filler = biome.filler_block();
+                for y in (0..128).rev() {
+                    let index = (x * 16 + z) * 128 + y as usize;
+                    if y <= random.next_int_bounded(5) {
+                        blocks[index] = BEDROCK;
+                        continue;
+                    }
+                    let block_type = blocks[index];
+                    if block_type == AIR {
+                        remaining = -1;
+                        continue;
+                    }
+                    if block_type != STONE {
+                        continue;
+                    }
+                    if remaining == -1 {
+                        if surface_depth <= 0 {
+                            top = AIR;
+                            filler = STONE;
+                        } else if (SEA_LEVEL - 4..=SEA_LEVEL + 1).contains(&y) {
+                            top = biome.top_block();
+                            filler = biome.filler_block();
+                            if is_gravel {
+                                top = AIR;
+                                filler = GRAVEL;
+                            }
+                            if is_sand {
+                                top = SAND;
+                                filler = SAND;
+                            }
+                        }
+                        if y < SEA_LEVEL && top == AIR {
+                            top = STILL_WATER;
+                        }
+                        remaining = surface_depth;
+                        blocks[index] = if y >= SEA_LEVEL - 1 { top } else { filler };
+                        continue;
+                    }
+                    if remaining > 0 {
+                        remaining -= 1;
+                        blocks[index] = filler;
+                        if remaining == 0 && filler == SAND {
+                            remaining = random.next_int_bounded(4);
+                            filler = SANDSTONE;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Places the decorations of the chunk at `coords` across it and its +x, +z neighbours,
+    /// `ChunkProviderGenerate.populate`. Returns the fluid springs, which still need their first update.
+    fn decorate(&self, chunks: &mut HashMap<ChunkCoords, Chunk>, coords: ChunkCoords) -> Vec<(BlockPosition, u8)> {
+        let region = &mut Region::new(chunks);
+        let (x, z) = (coords.x * 16, coords.z * 16);
+        let biome = self.biomes.biome_at(x + 16, z + 16);
+        let mut random = JavaRandom::new(self.seed);
+        let x_seed = random.next_long() / 2 * 2 + 1;
+        let z_seed = random.next_long() / 2 * 2 + 1;
+        random.set_seed(
+            (coords.x as i64)
+                .wrapping_mul(x_seed)
+                .wrapping_add((coords.z as i64).wrapping_mul(z_seed))
+                ^ self.seed,
+        );
+        let random = &mut random;
+        // Everything but the ores is offset by 8 so it lands in the middle of the four chunks.
+        let spot = |random: &mut JavaRandom, y: i32| {
+            let bx = x + random.next_int_bounded(16) + 8;
+            let by = random.next_int_bounded(y);
+            let bz = z + random.next_int_bounded(16) + 8;
+            (bx, by, bz)
+        };
+        if random.next_int_bounded(4) == 0 {
+            let (bx, by, bz) = spot(random, 128);
+            features::lake(region, random, bx, by, bz, STILL_WATER);
+        }
+        if random.next_int_bounded(8) == 0 {
+            let bx = x + random.next_int_bounded(16) + 8;
+            let bound = random.next_int_bounded(120) + 8;
+            let by = random.next_int_bounded(bound);
+            let bz = z + random.next_int_bounded(16) + 8;
+            if by < SEA_LEVEL || random.next_int_bounded(10) == 0 {
+                features::lake(region, random, bx, by, bz, STILL_LAVA);
+            }
+        }
+        for _ in 0..8 {
+            let (bx, by, bz) = spot(random, 128);
+            features::dungeon(region, random, bx, by, bz);
+        }
+        for _ in 0..10 {
+            let bx = x + random.next_int_bounded(16);
+            let by = random.next_int_bounded(128);
+            let bz = z + random.next_int_bounded(16);
+            features::clay(region, random, (bx, by, bz));
+        }
+        features::ores(region, random, x, z);
+        let forest_density = ((self.tree_noise.sample_2d(x as f64 * 0.5, z as f64 * 0.5) / 8.0
+            + random.next_double() * 4.0
+            + 4.0)
+            / 3.0) as i32;
+        let mut trees = 0;
+        if random.next_int_bounded(10) == 0 {
+            trees += 1;
+        }
+        trees += match biome {
+            Biome::Forest | Biome::Rainforest | Biome::Taiga => forest_density + 5,
+            Biome::SeasonalForest => forest_density + 2,
+            Biome::Desert | Biome::Tundra | Biome::Plains => -20,
+            _ => 0,
+        };
+        for _ in 0..trees {
+            let bx = x + random.next_int_bounded(16) + 8;
+            let bz = z + random.next_int_bounded(16) + 8;
+            let tree = biome.tree(random);
+            let by = region.height(bx, bz);
+            tree.grow(region, random, bx, by, bz);
+        }
+        let dandelions = match biome {
+            Biome::Forest | Biome::Taiga => 2,
+            Biome::SeasonalForest => 4,
+            Biome::Plains => 3,
+            _ => 0,
+        };
+        for _ in 0..dandelions {
+            let origin = spot(random, 128);
+            features::flowers(region, random, origin, DANDELION);
+        }
+        let grass = match biome {
+            Biome::Forest | Biome::SeasonalForest => 2,
+            Biome::Rainforest | Biome::Plains => 10,
+            Biome::Taiga => 1,
+            _ => 0,
+        };
+        for _ in 0..grass {
+            let meta = if biome == Biome::Rainforest && random.next_int_bounded(3) != 0 {
+                2
+            } else {
+                1
+            };
+            let origin = spot(random, 128);
+            features::tall_grass(region, random, origin, meta);
+        }
+        if biome == Biome::Desert {
+            for _ in 0..2 {
+                let origin = spot(random, 128);
+                features::dead_bush(region, random, origin);
+            }
+        }
+        for (block_type, chance) in [(ROSE, 2), (BROWN_MUSHROOM, 4), (RED_MUSHROOM, 8)].iter() {
+            if random.next_int_bounded(*chance) == 0 {
+                let origin = spot(random, 128);
+                features::flowers(region, random, origin, *block_type);
+            }
+        }
+        for _ in 0..10 {
+            let origin = spot(random, 128);
+            features::reeds(region, random, origin);
+        }
+        if random.next_int_bounded(32) == 0 {
+            let origin = spot(random, 128);
+            features::pumpkins(region, random, origin);
+        }
+        if biome == Biome::Desert {
+            for _ in 0..10 {
+                let origin = spot(random, 128);
+                features::cacti(region, random, origin);
+            }
+        }
+        let mut springs = Vec::new();
+        for _ in 0..50 {
+            let bx = x + random.next_int_bounded(16) + 8;
+            let bound = random.next_int_bounded(120) + 8;
+            let by = random.next_int_bounded(bound);
+            let bz = z + random.next_int_bounded(16) + 8;
+            springs.extend(features::spring(region, (bx, by, bz), WATER).map(|pos| (pos, WATER)));
+        }
+        for _ in 0..20 {
+            let bx = x + random.next_int_bounded(16) + 8;
+            let bound = random.next_int_bounded(112) + 8;
+            let bound = random.next_int_bounded(bound) + 8;
+            let by = random.next_int_bounded(bound);
+            let bz = z + random.next_int_bounded(16) + 8;
+            springs.extend(features::spring(region, (bx, by, bz), LAVA).map(|pos| (pos, LAVA)));
+        }
+        let temperatures = self.biomes.temperatures(x + 8, z + 8, 16, 16);
+        for dx in 0..16 {
+            for dz in 0..16 {
+                features::snow(region, x + 8 + dx, z + 8 + dz, temperatures[(dx * 16 + dz) as usize]);
+            }
+        }
+        springs
+    }
+}
+/// Builds an unpopulated chunk from blocks indexed `x << 11 | z << 7 | y`.
+fn chunk_from_blocks(coords: ChunkCoords, blocks: &[u8]) -> Chunk {
+    let mut chunk = Chunk {
+        x: coords.x,
+        z: coords.z,
+        data: Chunk::sections_from_region(coords.x, coords.z, blocks, &[0; 16384], &[0; 16384], &[0; 16384])
+            .expect("Generated a chunk of the wrong size"),
+        heightmap: [[0; 16]; 16],
+        entities: Vec::new(),
+        tile_entities: Vec::new(),
+        populated: false,
+        last_update: 0,
+        dirty: false,
+        tile_ticks: Vec::new(),
+        packet_cache: None,
+    };
+    chunk.calculate_heightmap().ok();
+    chunk
+}
+impl WorldGenerator for VanillaChunkGenerator {
+    fn gen_chunk(&self, coords: ChunkCoords) -> Chunk {
+        if CONFIGURATION.logging.chunk_gen {
+            log::info!("Generating chunk at ({}, {})", coords.x, coords.z);
+        }
+        chunk_from_blocks(coords, &self.generate_blocks(coords.x, coords.z))
+    }
+    fn gen_structures(&self, _world: &mut World, _coords: ChunkCoords) {}
+    fn populates_lazily(&self) -> bool {
+        true
+    }
+    fn populate(&self, world: &mut World, coords: ChunkCoords) {
+        let springs = self.decorate(&mut world.chunks, coords);
+        let now = world.scheduled_ticks.now();
+        for (position, block_type) in springs {
+            let delay = Fluid::from_id(block_type).map_or(0, |fluid| fluid.tick_rate());
+            world.schedule_tick(position, block_type, now + delay);
+        }
+    }
+    fn get_seed(&self) -> u64 {
+        self.seed as u64
+    }
+    fn get_name(&self) -> String {
+        "VanillaChunkGenerator".to_string()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    /// A column of blocks run-length encoded from bedrock up, as `(block id, count)`.
+    fn column(blocks: &[u8], x: usize, z: usize) -> Vec<(u8, usize)> {
+        let mut runs: Vec<(u8, usize)> = Vec::new();
+        for &block_type in &blocks[(x * 16 + z) * 128..(x * 16 + z + 1) * 128] {
+            match runs.last_mut() {
+                Some((last, count)) if *last == block_type => *count += 1,
+                _ => runs.push((block_type, 1)),
+            }
+        }
+        runs
+    }
+    fn checksum(blocks: &[u8]) -> i64 {
+        blocks
+            .iter()
+            .fold(0i64, |hash, &block_type| hash.wrapping_mul(31).wrapping_add(block_type as i64))
+    }
+    // Expected values come from a transcription of the b1.7.3 `ChunkProviderGenerate`, `MapGenCaves`
+    // and noise classes run on the JVM with java.util.Random, not from a real server's world, and
+    // only cover the blocks before population.
+    #[test]
+    fn terrain_matches_reference() {
+        let generator = VanillaChunkGenerator::new(0);
+        let blocks = generator.generate_blocks(0, 0);
+        assert_eq!(column(&blocks, 0, 0), [(7, 4), (1, 78), (3, 3), (2, 1), (0, 42)]);
+        assert_eq!(checksum(&blocks), 242479875908421927);
+        let generator = VanillaChunkGenerator::new(-4172144997902289642i64 as u64);
+        let blocks = generator.generate_blocks(-10, 7);
+        assert_eq!(column(&blocks, 0, 0), [(7, 2), (1, 53), (24, 3), (12, 3), (9, 3), (0, 64)]);
+        assert_eq!(column(&blocks, 7, 9), [(7, 1), (1, 1), (7, 1), (1, 59), (12, 4), (0, 62)]);
+        assert_eq!(checksum(&blocks), -1486904857288000018);
+    }
+    #[test]
+    fn caves_match_reference() {
+        let generator = VanillaChunkGenerator::new(404);
+        let blocks = generator.generate_blocks(3, -2);
+        assert_eq!(
+            column(&blocks, 7, 9),
+            [(7, 4), (1, 6), (10, 1), (0, 3), (1, 52), (3, 4), (2, 1), (0, 57)]
+        );
+        assert_eq!(column(&blocks, 15, 3), [(7, 3), (1, 19), (0, 2), (1, 42), (3, 3), (2, 1), (0, 58)]);
+        assert_eq!(checksum(&blocks), -6447046296464893684);
+        let generator = VanillaChunkGenerator::new(123456789);
+        let blocks = generator.generate_blocks(50, 50);
+        assert_eq!(
+            column(&blocks, 7, 9),
+            [(7, 2), (1, 1), (7, 1), (1, 30), (0, 2), (1, 27), (24, 1), (12, 2), (0, 62)]
+        );
+        assert_eq!(checksum(&blocks), 5935356606155938834);
+    }
+    /// Every populated chunk near the origin of each fixture world, whose neighbours towards -x and
+    /// -z are populated too, has to match block for block. The worlds live in
+    /// `tests/fixtures/b173/seed_<seed>`, see the README there for how to make them.
+    #[test]
+    #[ignore = "needs worlds saved by a b1.7.3 server in tests/fixtures/b173"]
+    fn populated_chunks_match_a_real_world() {
+        use crate::world::mcregion::MCRegionLoader;
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/b173");
+        let mut compared = 0;
+        for entry in std::fs::read_dir(&fixtures).expect("no fixture worlds") {
+            let dir = entry.unwrap().path();
+            let seed = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("seed_"))
+                .and_then(|seed| seed.parse::<i64>().ok());
+            let seed = match seed {
+                Some(seed) => seed,
+                None => continue,
+            };
+            let mut saved = MCRegionLoader::new(dir.to_str().unwrap()).unwrap();
+            let mut populated = |coords: ChunkCoords| saved.get_chunk(coords).filter(|chunk| chunk.populated);
+            let generator = VanillaChunkGenerator::new(seed as u64);
+            let mut chunks = HashMap::new();
+            for x in -3..=3 {
+                for z in -3..=3 {
+                    let coords = ChunkCoords { x, z };
+                    chunks.insert(coords, generator.gen_chunk(coords));
+                }
+            }
+            for x in -3..3 {
+                for z in -3..3 {
+                    generator.decorate(&mut chunks, ChunkCoords { x, z });
+                }
+            }
+            for x in -2..=2 {
+                for z in -2..=2 {
+                    let expected = match populated(ChunkCoords { x, z }) {
+                        Some(chunk) => chunk.block_types(),
+                        None => continue,
+                    };
+                    let finished = [(-1, 0), (0, -1), (-1, -1)]
+                        .iter()
+                        .all(|(dx, dz)| populated(ChunkCoords { x: x + dx, z: z + dz }).is_some());
+                    if !finished {
+                        continue;
+                    }
+                    let actual = chunks[&ChunkCoords { x, z }].block_types();
+                    let differing: Vec<usize> = (0..expected.len()).filter(|&i| expected[i] != actual[i]).collect();
+                    assert!(
+                        differing.is_empty(),
+                        "seed {} chunk {}, {}: {} blocks differ, the first at x {} z {} y {} is {} instead of {}",
+                        seed,
+                        x,
+                        z,
+                        differing.len(),
+                        differing[0] >> 11,
+                        differing[0] >> 7 & 15,
+                        differing[0] & 127,
+                        actual[differing[0]],
+                        expected[differing[0]]
+                    );
+                    compared += 1;
+                }
+            }
+        }
+        assert!(compared > 0, "no finished chunks near the origin in {}", fixtures.display());
+    }
+    #[test]
+    fn ravines_reach_across_chunks() {
+        // A seed 0 ravine starts a few chunks away and runs through these two.
+        let generator = VanillaChunkGenerator::new(0);
+        for chunk_z in 5..7 {
+            let blocks = generator.generate_blocks(-9, chunk_z);
+            let mut carved = blocks.clone();
+            ravines::carve(0, -9, chunk_z, &mut carved);
+            let mut again = blocks.clone();
+            ravines::carve(0, -9, chunk_z, &mut again);
+            assert_eq!(carved, again);
+            assert!(blocks.iter().zip(&carved).filter(|(before, after)| before != after).count() > 1000);
+        }
+    }
+    #[test]
+    fn population_decorates_the_chunk() {
+        let generator = VanillaChunkGenerator::new(0);
+        let mut chunks = HashMap::new();
+        for x in 0..2 {
+            for z in 0..2 {
+                let coords = ChunkCoords { x, z };
+                chunks.insert(coords, generator.gen_chunk(coords));
+            }
+        }
+        let count = |chunks: &mut HashMap<ChunkCoords, Chunk>, block_type: u8| {
+            let mut count = 0;
+            for chunk in chunks.values_mut() {
+                for x in 0..16 {
+                    for z in 0..16 {
+                        for y in 0..128 {
+                            if chunk.get_block(x, y, z).map(|block| block.b_type) == Some(block_type) {
+                                count += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            count
+        };
+        assert_eq!(count(&mut chunks, COAL_ORE), 0);
+        generator.decorate(&mut chunks, ChunkCoords { x: 0, z: 0 });
+        assert!(count(&mut chunks, COAL_ORE) > 0);
+        assert!(count(&mut chunks, IRON_ORE) > 0);
+    }
+}
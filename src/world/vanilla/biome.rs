@@ -0,0 +1,213 @@
+use super::features::Tree;
+use super::noise::{JavaRandom, OctaveSimplexNoise};
+use crate::game::BlockPosition;
+/// The b1.7.3 biomes, picked from a temperature and humidity pair.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Rainforest,
+    Swampland,
+    SeasonalForest,
+    Forest,
+    Savanna,
+    Shrubland,
+    Taiga,
+    Desert,
+    Plains,
+    Tundra,
+}
+impl Biome {
+    /// `MobSpawnerBase.getBiome`.
+    fn from_climate(temperature: f32, humidity: f32) -> Self {
+        let humidity = humidity * temperature;
+        if temperature < 0.1 {
+            Self::Tundra
+        } else if humidity < 0.2 {
+            if temperature < 0.5 {
+                Self::Tundra
+            } else if temperature < 0.95 {
+                Self::Savanna
+            } else {
+                Self::Desert
+            }
+        } else if humidity > 0.5 && temperature < 0.7 {
+            Self::Swampland
+        } else if temperature < 0.5 {
+            Self::Taiga
+        } else if temperature < 0.97 {
+            if humidity < 0.35 {
+                Self::Shrubland
+            } else {
+                Self::Forest
+            }
+        } else if humidity < 0.45 {
+            Self::Plains
+        } else if humidity < 0.9 {
+            Self::SeasonalForest
+        } else {
+            Self::Rainforest
+        }
+    }
+    /// Vanilla quantizes the climate to a 64x64 lookup table before picking the biome.
+    fn lookup(temperature: f64, humidity: f64) -> Self {
+        let t = (temperature * 63.0) as i32;
+        let h = (humidity * 63.0) as i32;
+        Self::from_climate(t as f32 / 63.0, h as f32 / 63.0)
+    }
+    /// The block id covering the surface.
+    pub fn top_block(&self) -> u8 {
+        match self {
+            Self::Desert => 12,
+            _ => 2,
+        }
+    }
+    /// The block id under the surface block.
+    pub fn filler_block(&self) -> u8 {
+        match self {
+            Self::Desert => 12,
+            _ => 3,
+        }
+    }
+    /// Whether precipitation here falls as snow, `getEnableSnow`.
+    pub fn has_snow(&self) -> bool {
+        matches!(self, Self::Taiga | Self::Tundra)
+    }
+    /// Picks the kind of tree to grow, `getRandomWorldGenForTrees`.
+    pub fn tree(&self, random: &mut JavaRandom) -> Tree {
+        match self {
+            Self::Forest => {
+                if random.next_int_bounded(5) == 0 {
+                    Tree::Birch
+                } else if random.next_int_bounded(3) == 0 {
+                    Tree::Big
+                } else {
+                    Tree::Oak
+                }
+            }
+            Self::Rainforest => {
+                if random.next_int_bounded(3) == 0 {
+                    Tree::Big
+                } else {
+                    Tree::Oak
+                }
+            }
+            Self::Taiga => {
+                if random.next_int_bounded(3) == 0 {
+                    Tree::Pine
+                } else {
+                    Tree::Spruce
+                }
+            }
+            _ => {
+                if random.next_int_bounded(10) == 0 {
+                    Tree::Big
+                } else {
+                    Tree::Oak
+                }
+            }
+        }
+    }
+}
+/// Blends a little of the variation noise into a climate value, spelled the way vanilla does so it rounds the same.
+fn mix(value: f64, variation: f64, weight: f64) -> f64 {
+    value * (1.0 - weight) + variation * weight
+}
+/// The climate noise, `WorldChunkManager`.
+pub struct BiomeSource {
+    temperature_noise: OctaveSimplexNoise,
+    humidity_noise: OctaveSimplexNoise,
+    variation_noise: OctaveSimplexNoise,
+}
+/// Temperature, humidity and biome for an area, x major like every other vanilla grid.
+#[derive(Default)]
+pub struct Climate {
+    pub temperature: Vec<f64>,
+    pub humidity: Vec<f64>,
+    pub biomes: Vec<Biome>,
+}
+impl BiomeSource {
+    pub fn new(seed: i64) -> Self {
+        Self {
+            temperature_noise: OctaveSimplexNoise::new(&mut JavaRandom::new(seed.wrapping_mul(9871)), 4),
+            humidity_noise: OctaveSimplexNoise::new(&mut JavaRandom::new(seed.wrapping_mul(39811)), 4),
+            variation_noise: OctaveSimplexNoise::new(&mut JavaRandom::new(seed.wrapping_mul(543321)), 2),
+        }
+    }
+    /// `loadBlockGeneratorData`: the climate of a `size_x` by `size_z` block area.
+    pub fn climate(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Climate {
+        let mut climate = Climate::default();
+        let mut variation = Vec::new();
+        self.temperature_noise.grid(
+            &mut climate.temperature,
+            x as f64,
+            z as f64,
+            size_x,
+            size_z,
+            0.02500000037252903,
+            0.02500000037252903,
+            0.25,
+        );
+        self.humidity_noise.grid(
+            &mut climate.humidity,
+            x as f64,
+            z as f64,
+            size_x,
+            size_z,
+            0.05000000074505806,
+            0.05000000074505806,
+            0.3333333333333333,
+        );
+        self.variation(&mut variation, x, z, size_x, size_z);
+        for i in 0..size_x * size_z {
+            let variation = variation[i] * 1.1 + 0.5;
+            let temperature = mix(climate.temperature[i] * 0.15 + 0.7, variation, 0.01);
+            let humidity = mix(climate.humidity[i] * 0.15 + 0.5, variation, 0.002);
+            let temperature = (1.0 - (1.0 - temperature) * (1.0 - temperature)).clamp(0.0, 1.0);
+            let humidity = humidity.clamp(0.0, 1.0);
+            climate.temperature[i] = temperature;
+            climate.humidity[i] = humidity;
+            climate.biomes.push(Biome::lookup(temperature, humidity));
+        }
+        climate
+    }
+    /// `getTemperatures`, the temperature part of `climate` on its own.
+    pub fn temperatures(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<f64> {
+        let mut temperatures = Vec::new();
+        let mut variation = Vec::new();
+        self.temperature_noise.grid(
+            &mut temperatures,
+            x as f64,
+            z as f64,
+            size_x,
+            size_z,
+            0.02500000037252903,
+            0.02500000037252903,
+            0.25,
+        );
+        self.variation(&mut variation, x, z, size_x, size_z);
+        for (temperature, variation) in temperatures.iter_mut().zip(variation) {
+            let variation = variation * 1.1 + 0.5;
+            let t = mix(*temperature * 0.15 + 0.7, variation, 0.01);
+            *temperature = (1.0 - (1.0 - t) * (1.0 - t)).clamp(0.0, 1.0);
+        }
+        temperatures
+    }
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        self.climate(x, z, 1, 1).biomes[0]
+    }
+    /// The biome of the column `position` is in.
+    pub fn biome(&self, position: &BlockPosition) -> Biome {
+        self.biome_at(position.x, position.z)
+    }
+    fn variation(&self, out: &mut Vec<f64>, x: i32, z: i32, size_x: usize, size_z: usize) {
+        self.variation_noise.grid(
+            out,
+            x as f64,
+            z as f64,
+            size_x,
+            size_z,
+            0.25,
+            0.25,
+            0.5882352941176471,
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! `MapGenCaves`: tunnels and rooms carved out of a chunk's terrain before it is decorated.
+//!
+//! Every chunk within `RANGE` may start a cave system, and each one is replayed from its own seed
+//! so the part crossing into this chunk comes out the same whichever chunk is generated first.
+use super::ids::*;
+use super::noise::{math, JavaRandom};
+/// How many chunks away a cave system can start and still reach this chunk.
+const RANGE: i32 = 8;
+/// Carves the caves crossing chunk `chunk_x`, `chunk_z` out of its blocks, indexed `x << 11 | z << 7 | y`.
+pub fn carve(seed: i64, chunk_x: i32, chunk_z: i32, blocks: &mut [u8]) {
+    let mut random = JavaRandom::new(seed);
+    let x_seed = random.next_long() / 2 * 2 + 1;
+    let z_seed = random.next_long() / 2 * 2 + 1;
+    let mut carver = Carver {
+        random,
+        chunk_x,
+        chunk_z,
+        blocks,
+    };
+    for x in chunk_x - RANGE..=chunk_x + RANGE {
+        for z in chunk_z - RANGE..=chunk_z + RANGE {
+            let start_seed = (x as i64).wrapping_mul(x_seed).wrapping_add((z as i64).wrapping_mul(z_seed)) ^ seed;
+            carver.random.set_seed(start_seed);
+            carver.start_caves(x, z);
+        }
+    }
+}
+/// One leg of a cave, walked a step at a time until `length`.
+#[derive(Clone, Copy)]
+struct Tunnel {
+    x: f64,
+    y: f64,
+    z: f64,
+    width: f32,
+    yaw: f32,
+    pitch: f32,
+    start: i32,
+    /// Zero to pick a random length.
+    length: i32,
+    vertical_scale: f64,
+}
+struct Carver<'a> {
+    random: JavaRandom,
+    chunk_x: i32,
+    chunk_z: i32,
+    blocks: &'a mut [u8],
+}
+impl<'a> Carver<'a> {
+    fn start_caves(&mut self, origin_x: i32, origin_z: i32) {
+        let bound = self.random.next_int_bounded(40) + 1;
+        let bound = self.random.next_int_bounded(bound) + 1;
+        let mut count = self.random.next_int_bounded(bound);
+        if self.random.next_int_bounded(15) != 0 {
+            count = 0;
+        }
+        for _ in 0..count {
+            let x = (origin_x * 16 + self.random.next_int_bounded(16)) as f64;
+            let bound = self.random.next_int_bounded(120) + 8;
+            let y = self.random.next_int_bounded(bound) as f64;
+            let z = (origin_z * 16 + self.random.next_int_bounded(16)) as f64;
+            let mut tunnels = 1;
+            if self.random.next_int_bounded(4) == 0 {
+                let room = Tunnel {
+                    x,
+                    y,
+                    z,
+                    width: 1.0 + self.random.next_float() * 6.0,
+                    yaw: 0.0,
+                    pitch: 0.0,
+                    start: -1,
+                    length: -1,
+                    vertical_scale: 0.5,
+                };
+                self.tunnel(room);
+                tunnels += self.random.next_int_bounded(4);
+            }
+            for _ in 0..tunnels {
+                let yaw = self.random.next_float() * math::PI * 2.0;
+                let pitch = ((self.random.next_float() - 0.5) * 2.0) / 8.0;
+                let width = self.random.next_float() * 2.0 + self.random.next_float();
+                self.tunnel(Tunnel {
+                    x,
+                    y,
+                    z,
+                    width,
+                    yaw,
+                    pitch,
+                    start: 0,
+                    length: 0,
+                    vertical_scale: 1.0,
+                });
+            }
+        }
+    }
+    fn tunnel(&mut self, mut tunnel: Tunnel) {
+        let center_x = (self.chunk_x * 16 + 8) as f64;
+        let center_z = (self.chunk_z * 16 + 8) as f64;
+        let mut yaw_change = 0.0f32;
+        let mut pitch_change = 0.0f32;
+        let mut random = JavaRandom::new(self.random.next_long());
+        if tunnel.length <= 0 {
+            let max = RANGE * 16 - 16;
+            tunnel.length = max - random.next_int_bounded(max / 4);
+        }
+        let length = tunnel.length;
+        let mut is_room = false;
+        if tunnel.start == -1 {
+            tunnel.start = length / 2;
+            is_room = true;
+        }
+        let branch_at = random.next_int_bounded(length / 2) + length / 4;
+        let steep = random.next_int_bounded(6) == 0;
+        for step in tunnel.start..length {
+            let radius = 1.5 + (math::sin((step as f32 * math::PI) / length as f32) * tunnel.width * 1.0) as f64;
+            let radius_y = radius * tunnel.vertical_scale;
+            let horizontal = math::cos(tunnel.pitch);
+            let vertical = math::sin(tunnel.pitch);
+            tunnel.x += (math::cos(tunnel.yaw) * horizontal) as f64;
+            tunnel.y += vertical as f64;
+            tunnel.z += (math::sin(tunnel.yaw) * horizontal) as f64;
+            tunnel.pitch *= if steep { 0.92 } else { 0.7 };
+            tunnel.pitch += pitch_change * 0.1;
+            tunnel.yaw += yaw_change * 0.1;
+            pitch_change *= 0.9;
+            yaw_change *= 0.75;
+            pitch_change += (random.next_float() - random.next_float()) * random.next_float() * 2.0;
+            yaw_change += (random.next_float() - random.next_float()) * random.next_float() * 4.0;
+            if !is_room && step == branch_at && tunnel.width > 1.0 {
+                for turn in &[-math::HALF_PI, math::HALF_PI] {
+                    let width = random.next_float() * 0.5 + 0.5;
+                    self.tunnel(Tunnel {
+                        width,
+                        yaw: tunnel.yaw + turn,
+                        pitch: tunnel.pitch / 3.0,
+                        start: step,
+                        length,
+                        vertical_scale: 1.0,
+                        ..tunnel
+                    });
+                }
+                return;
+            }
+            if !is_room && random.next_int_bounded(4) == 0 {
+                continue;
+            }
+            let dx = tunnel.x - center_x;
+            let dz = tunnel.z - center_z;
+            let remaining = (length - step) as f64;
+            let reach = (tunnel.width + 2.0 + 16.0) as f64;
+            if dx * dx + dz * dz - remaining * remaining > reach * reach {
+                return;
+            }
+            if tunnel.x < center_x - 16.0 - radius * 2.0
+                || tunnel.z < center_z - 16.0 - radius * 2.0
+                || tunnel.x > center_x + 16.0 + radius * 2.0
+                || tunnel.z > center_z + 16.0 + radius * 2.0
+            {
+                continue;
+            }
+            if self.carve_step(&tunnel, radius, radius_y) && is_room {
+                break;
+            }
+        }
+    }
+    /// Clears the ellipsoid around the tunnel's current position, unless it would open into water.
+    fn carve_step(&mut self, tunnel: &Tunnel, radius: f64, radius_y: f64) -> bool {
+        let min_x = (math::floor(tunnel.x - radius) - self.chunk_x * 16 - 1).max(0);
+        let max_x = (math::floor(tunnel.x + radius) - self.chunk_x * 16 + 1).min(16);
+        let min_y = (math::floor(tunnel.y - radius_y) - 1).max(1);
+        let max_y = (math::floor(tunnel.y + radius_y) + 1).min(120);
+        let min_z = (math::floor(tunnel.z - radius) - self.chunk_z * 16 - 1).max(0);
+        let max_z = (math::floor(tunnel.z + radius) - self.chunk_z * 16 + 1).min(16);
+        if touches_water(self.blocks, (min_x, max_x), (min_y, max_y), (min_z, max_z)) {
+            return false;
+        }
+        for x in min_x..max_x {
+            let px = ((x + self.chunk_x * 16) as f64 + 0.5 - tunnel.x) / radius;
+            for z in min_z..max_z {
+                let pz = ((z + self.chunk_z * 16) as f64 + 0.5 - tunnel.z) / radius;
+                if px * px + pz * pz >= 1.0 {
+                    continue;
+                }
+                // Vanilla tests each block against the ellipsoid one layer below it, keep that.
+                let mut index = ((x * 16 + z) * 128 + max_y) as usize;
+                let mut hit_grass = false;
+                for y in (min_y..max_y).rev() {
+                    let py = (y as f64 + 0.5 - tunnel.y) / radius_y;
+                    if py > -0.7 && px * px + py * py + pz * pz < 1.0 {
+                        let block_type = self.blocks[index];
+                        if block_type == GRASS {
+                            hit_grass = true;
+                        }
+                        if block_type == STONE || block_type == DIRT || block_type == GRASS {
+                            if y < 10 {
+                                self.blocks[index] = LAVA;
+                            } else {
+                                self.blocks[index] = AIR;
+                                if hit_grass && self.blocks[index - 1] == DIRT {
+                                    self.blocks[index - 1] = GRASS;
+                                }
+                            }
+                        }
+                    }
+                    index -= 1;
+                }
+            }
+        }
+        true
+    }
+}
+/// Whether the shell of a carving box holds water, which caves and ravines stop short of.
+pub(super) fn touches_water(blocks: &[u8], (min_x, max_x): (i32, i32), (min_y, max_y): (i32, i32), (min_z, max_z): (i32, i32)) -> bool {
+    for x in min_x..max_x {
+        for z in min_z..max_z {
+            let mut y = max_y + 1;
+            while y >= min_y - 1 {
+                if (0..128).contains(&y) {
+                    let block_type = blocks[((x * 16 + z) * 128 + y) as usize];
+                    if block_type == WATER || block_type == STILL_WATER {
+                        return true;
+                    }
+                    if y != min_y - 1 && x != min_x && x != max_x - 1 && z != min_z && z != max_z - 1 {
+                        y = min_y;
+                    }
+                }
+                y -= 1;
+            }
+        }
+    }
+    false
+}
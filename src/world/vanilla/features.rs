@@ -0,0 +1,935 @@
+//! The `WorldGen*` decorations vanilla places while populating a chunk.
+//!
+//! Each one draws from the population random in exactly the order vanilla does, including the
+//! draws it makes before deciding a spot doesn't fit, so that later features land in the same place.
+use super::ids::*;
+use super::noise::{math, JavaRandom};
+use crate::game::{BlockPosition, ChunkCoords};
+use crate::world::chunks::{Block, Chunk};
+use std::collections::HashMap;
+/// The resident chunks as population sees them.
+///
+/// A chunk is only populated once the chunks its decorations reach into are resident, so reads
+/// that miss find air and writes that miss are dropped only at the edge of what has been generated.
+pub struct Region<'a> {
+    chunks: &'a mut HashMap<ChunkCoords, Chunk>,
+    /// Every block set, when the caller needs to tell players about them.
+    changes: Option<Vec<BlockPosition>>,
+}
+impl<'a> Region<'a> {
+    pub fn new(chunks: &'a mut HashMap<ChunkCoords, Chunk>) -> Self {
+        Self { chunks, changes: None }
+    }
+    /// A region that remembers which blocks were set, for features placed in a live world.
+    pub fn tracked(chunks: &'a mut HashMap<ChunkCoords, Chunk>) -> Self {
+        Self {
+            chunks,
+            changes: Some(Vec::new()),
+        }
+    }
+    /// The blocks set so far, in order and without duplicates removed.
+    pub fn take_changes(&mut self) -> Vec<BlockPosition> {
+        self.changes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+    fn block(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        if !(0..128).contains(&y) {
+            return None;
+        }
+        self.chunks
+            .get(&ChunkCoords { x: x >> 4, z: z >> 4 })?
+            .get_block(x & 15, y, z & 15)
+    }
+    pub fn get(&mut self, x: i32, y: i32, z: i32) -> u8 {
+        self.block(x, y, z).map_or(0, |block| block.b_type)
+    }
+    pub fn is_air(&mut self, x: i32, y: i32, z: i32) -> bool {
+        self.get(x, y, z) == AIR
+    }
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block_type: u8) {
+        self.set_with_meta(x, y, z, block_type, 0);
+    }
+    pub fn set_with_meta(&mut self, x: i32, y: i32, z: i32, block_type: u8, meta: u8) {
+        if !(0..128).contains(&y) {
+            return;
+        }
+        if let Some(chunk) = self.chunks.get_mut(&ChunkCoords { x: x >> 4, z: z >> 4 }) {
+            let changed = chunk.update_block(x & 15, y, z & 15, |block| {
+                block.b_type = block_type;
+                block.b_metadata = meta;
+            });
+            if changed.is_some() {
+                chunk.dirty = true;
+                if let Some(changes) = &mut self.changes {
+                    changes.push(BlockPosition { x, y, z });
+                }
+            }
+        }
+    }
+    /// The lowest y with nothing that blocks light above it, `getHeightValue`.
+    pub fn height(&mut self, x: i32, z: i32) -> i32 {
+        let mut y = 127;
+        while y > 0 && light_opacity(self.get(x, y - 1, z)) == 0 {
+            y -= 1;
+        }
+        y
+    }
+    /// Sky light worked out down the column only, what a freshly generated vanilla chunk holds
+    /// before its light spreads sideways.
+    pub fn skylight(&mut self, x: i32, y: i32, z: i32) -> i32 {
+        if y <= 0 {
+            return 0;
+        }
+        let mut light = 15;
+        for above in (y.min(127)..128).rev() {
+            light -= light_opacity(self.get(x, above, z)) as i32;
+            if light <= 0 {
+                return 0;
+            }
+        }
+        light
+    }
+    fn can_see_sky(&mut self, x: i32, y: i32, z: i32) -> bool {
+        y >= self.height(x, z)
+    }
+    /// Material checks by block id, vanilla's `getBlockMaterial(..).isSolid()` and friends.
+    fn is_solid(&mut self, x: i32, y: i32, z: i32) -> bool {
+        is_solid(self.get(x, y, z))
+    }
+    fn is_liquid(&mut self, x: i32, y: i32, z: i32) -> bool {
+        is_liquid(self.get(x, y, z))
+    }
+    fn is_water(&mut self, x: i32, y: i32, z: i32) -> bool {
+        matches!(self.get(x, y, z), WATER | STILL_WATER)
+    }
+    /// `findTopSolidBlock`: one above the highest solid or liquid block.
+    pub fn top_solid_block(&mut self, x: i32, z: i32) -> i32 {
+        let mut y = 127;
+        while y > 0 {
+            let block_type = self.get(x, y, z);
+            if is_solid(block_type) || is_liquid(block_type) {
+                return y + 1;
+            }
+            y -= 1;
+        }
+        -1
+    }
+}
+/// Blocks vanilla treats as full opaque cubes, `Block.opaqueCubeLookup`.
+///
+/// Leaves count: the table is filled in while the leaves block is still being constructed, before
+/// its fancy graphics flag is set.
+pub fn is_opaque_cube(block_type: u8) -> bool {
+    matches!(
+        block_type,
+        1..=5 | 7 | 12..=19 | 21..=25 | 35 | 41..=43 | 45..=49 | 54 | 56..=58 | 61 | 62 | 73 | 74 | 80 | 82 | 84 | 86..=89 | 91
+    )
+}
+pub fn light_opacity(block_type: u8) -> u8 {
+    match block_type {
+        LEAVES | 30 => 1,
+        WATER | STILL_WATER | ICE => 3,
+        LAVA | STILL_LAVA | 44 | 60 => 255,
+        block_type if is_opaque_cube(block_type) => 255,
+        _ => 0,
+    }
+}
+/// Whether the block's material is solid. Plants, fluids, snow, fire and redstone parts are not.
+pub fn is_solid(block_type: u8) -> bool {
+    !matches!(
+        block_type,
+        AIR | 6 | WATER..=STILL_LAVA | 27 | 28 | 30..=32 | 37..=40 | 50 | 51 | 55 | 59 | 65 | 66 | 69 | 75..=78 | 83 | 90 | 93 | 94
+    )
+}
+pub fn is_liquid(block_type: u8) -> bool {
+    matches!(block_type, WATER..=STILL_LAVA)
+}
+/// `WorldGenLakes`: a blob of water or lava sunk into the ground, with a stone rim around lava.
+pub fn lake(region: &mut Region, random: &mut JavaRandom, x: i32, y: i32, z: i32, fluid: u8) -> bool {
+    let x = x - 8;
+    let z = z - 8;
+    let mut y = y;
+    while y > 0 && region.is_air(x, y, z) {
+        y -= 1;
+    }
+    y -= 4;
+    let mut shape = [false; 2048];
+    let index = |dx: i32, dz: i32, dy: i32| ((dx * 16 + dz) * 8 + dy) as usize;
+    let blobs = random.next_int_bounded(4) + 4;
+    for _ in 0..blobs {
+        let size_x = random.next_double() * 6.0 + 3.0;
+        let size_y = random.next_double() * 4.0 + 2.0;
+        let size_z = random.next_double() * 6.0 + 3.0;
+        let center_x = random.next_double() * (16.0 - size_x - 2.0) + 1.0 + size_x / 2.0;
+        let center_y = random.next_double() * (8.0 - size_y - 4.0) + 2.0 + size_y / 2.0;
+        let center_z = random.next_double() * (16.0 - size_z - 2.0) + 1.0 + size_z / 2.0;
+        for dx in 1..15 {
+            for dz in 1..15 {
+                for dy in 1..7 {
+                    let px = (dx as f64 - center_x) / (size_x / 2.0);
+                    let py = (dy as f64 - center_y) / (size_y / 2.0);
+                    let pz = (dz as f64 - center_z) / (size_z / 2.0);
+                    if px * px + py * py + pz * pz < 1.0 {
+                        shape[index(dx, dz, dy)] = true;
+                    }
+                }
+            }
+        }
+    }
+    let is_edge = |dx: i32, dz: i32, dy: i32| {
+        !shape[index(dx, dz, dy)]
+            && (dx < 15 && shape[index(dx + 1, dz, dy)]
+                || dx > 0 && shape[index(dx - 1, dz, dy)]
+                || dz < 15 && shape[index(dx, dz + 1, dy)]
+                || dz > 0 && shape[index(dx, dz - 1, dy)]
+                || dy < 7 && shape[index(dx, dz, dy + 1)]
+                || dy > 0 && shape[index(dx, dz, dy - 1)])
+    };
+    for dx in 0..16 {
+        for dz in 0..16 {
+            for dy in 0..8 {
+                if !is_edge(dx, dz, dy) {
+                    continue;
+                }
+                let (bx, by, bz) = (x + dx, y + dy, z + dz);
+                if dy >= 4 && region.is_liquid(bx, by, bz) {
+                    return false;
+                }
+                if dy < 4 && !region.is_solid(bx, by, bz) && region.get(bx, by, bz) != fluid {
+                    return false;
+                }
+            }
+        }
+    }
+    for dx in 0..16 {
+        for dz in 0..16 {
+            for dy in 0..8 {
+                if shape[index(dx, dz, dy)] {
+                    region.set(x + dx, y + dy, z + dz, if dy < 4 { fluid } else { AIR });
+                }
+            }
+        }
+    }
+    for dx in 0..16 {
+        for dz in 0..16 {
+            for dy in 4..8 {
+                if shape[index(dx, dz, dy)]
+                    && region.get(x + dx, y + dy - 1, z + dz) == DIRT
+                    && region.skylight(x + dx, y + dy, z + dz) > 0
+                {
+                    region.set(x + dx, y + dy - 1, z + dz, GRASS);
+                }
+            }
+        }
+    }
+    if fluid == STILL_LAVA {
+        for dx in 0..16 {
+            for dz in 0..16 {
+                for dy in 0..8 {
+                    if is_edge(dx, dz, dy)
+                        && (dy < 4 || random.next_int_bounded(2) != 0)
+                        && region.is_solid(x + dx, y + dy, z + dz)
+                    {
+                        region.set(x + dx, y + dy, z + dz, STONE);
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+/// `WorldGenDungeons`: a cobblestone room with a spawner and up to two chests.
+///
+/// The server has no chest or spawner tile entities yet, so the loot is rolled only to keep the
+/// random sequence in step with vanilla and the chests are left empty.
+pub fn dungeon(region: &mut Region, random: &mut JavaRandom, x: i32, y: i32, z: i32) -> bool {
+    let height = 3;
+    let radius_x = random.next_int_bounded(2) + 2;
+    let radius_z = random.next_int_bounded(2) + 2;
+    let mut openings = 0;
+    for bx in x - radius_x - 1..=x + radius_x + 1 {
+        for by in y - 1..=y + height + 1 {
+            for bz in z - radius_z - 1..=z + radius_z + 1 {
+                let solid = region.is_solid(bx, by, bz);
+                if by == y - 1 && !solid {
+                    return false;
+                }
+                if by == y + height + 1 && !solid {
+                    return false;
+                }
+                if (bx == x - radius_x - 1
+                    || bx == x + radius_x + 1
+                    || bz == z - radius_z - 1
+                    || bz == z + radius_z + 1)
+                    && by == y
+                    && region.is_air(bx, by, bz)
+                    && region.is_air(bx, by + 1, bz)
+                {
+                    openings += 1;
+                }
+            }
+        }
+    }
+    if !(1..=5).contains(&openings) {
+        return false;
+    }
+    for bx in x - radius_x - 1..=x + radius_x + 1 {
+        for by in (y - 1..=y + height).rev() {
+            for bz in z - radius_z - 1..=z + radius_z + 1 {
+                let wall = bx == x - radius_x - 1
+                    || by == y - 1
+                    || bz == z - radius_z - 1
+                    || bx == x + radius_x + 1
+                    || by == y + height + 1
+                    || bz == z + radius_z + 1;
+                if !wall || by >= 0 && !region.is_solid(bx, by - 1, bz) {
+                    region.set(bx, by, bz, AIR);
+                } else if region.is_solid(bx, by, bz) {
+                    if by == y - 1 && random.next_int_bounded(4) != 0 {
+                        region.set(bx, by, bz, MOSSY_COBBLESTONE);
+                    } else {
+                        region.set(bx, by, bz, COBBLESTONE);
+                    }
+                }
+            }
+        }
+    }
+    for _ in 0..2 {
+        for _ in 0..3 {
+            let cx = x + random.next_int_bounded(radius_x * 2 + 1) - radius_x;
+            let cz = z + random.next_int_bounded(radius_z * 2 + 1) - radius_z;
+            if !region.is_air(cx, y, cz) {
+                continue;
+            }
+            let walls = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .filter(|(dx, dz)| region.is_solid(cx + dx, y, cz + dz))
+                .count();
+            if walls != 1 {
+                continue;
+            }
+            region.set(cx, y, cz, CHEST);
+            for _ in 0..8 {
+                if roll_dungeon_loot(random) {
+                    // The slot the item would go in.
+                    random.next_int_bounded(27);
+                }
+            }
+            break;
+        }
+    }
+    region.set(x, y, z, MOB_SPAWNER);
+    // Skeleton, zombie, zombie or spider.
+    random.next_int_bounded(4);
+    true
+}
+/// `pickCheckLootItem`, returning whether an item came up.
+fn roll_dungeon_loot(random: &mut JavaRandom) -> bool {
+    match random.next_int_bounded(11) {
+        0 | 2 | 6 | 10 => true,
+        1 | 3 | 4 | 5 => {
+            random.next_int_bounded(4);
+            true
+        }
+        7 => random.next_int_bounded(100) == 0,
+        8 if random.next_int_bounded(2) == 0 => {
+            random.next_int_bounded(4);
+            true
+        }
+        9 if random.next_int_bounded(10) == 0 => {
+            random.next_int_bounded(2);
+            true
+        }
+        _ => false,
+    }
+}
+/// Vein block, attempts per chunk, vein size and the height veins start below.
+const ORES: [(u8, i32, i32, i32); 7] = [
+    (DIRT, 20, 32, 128),
+    (GRAVEL, 10, 32, 128),
+    (COAL_ORE, 20, 16, 128),
+    (IRON_ORE, 20, 8, 64),
+    (GOLD_ORE, 2, 8, 32),
+    (REDSTONE_ORE, 8, 7, 16),
+    (DIAMOND_ORE, 1, 7, 16),
+];
+/// The vanilla ore distribution for the chunk with its corner at `x`, `z`: dirt and gravel
+/// pockets, then coal, iron, gold, redstone, diamond and lapis, which clusters around y 16.
+pub fn ores(region: &mut Region, random: &mut JavaRandom, x: i32, z: i32) {
+    for (ore_type, attempts, size, max_y) in ORES.iter() {
+        for _ in 0..*attempts {
+            let bx = x + random.next_int_bounded(16);
+            let by = random.next_int_bounded(*max_y);
+            let bz = z + random.next_int_bounded(16);
+            ore(region, random, (bx, by, bz), *ore_type, *size);
+        }
+    }
+    let bx = x + random.next_int_bounded(16);
+    let by = random.next_int_bounded(16) + random.next_int_bounded(16);
+    let bz = z + random.next_int_bounded(16);
+    ore(region, random, (bx, by, bz), LAPIS_ORE, 6);
+}
+/// `WorldGenMinable`: a vein of `size` ore blocks stretched along a random direction through stone.
+pub fn ore(region: &mut Region, random: &mut JavaRandom, origin: (i32, i32, i32), ore: u8, size: i32) {
+    vein(region, random, origin, (ore, STONE), size, -2);
+}
+/// `WorldGenClay`: a vein of clay in the sand under water.
+pub fn clay(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) {
+    if !region.is_water(x, y, z) {
+        return;
+    }
+    vein(region, random, (x, y, z), (CLAY, SAND), 32, 2);
+}
+/// The blob shared by ores and clay. Clay starts a little above its origin where ores start below.
+fn vein(
+    region: &mut Region,
+    random: &mut JavaRandom,
+    (x, y, z): (i32, i32, i32),
+    (block_type, replaces): (u8, u8),
+    size: i32,
+    y_shift: i32,
+) {
+    let angle = random.next_float() * math::PI;
+    let spread_x = math::sin(angle) * size as f32 / 8.0;
+    let spread_z = math::cos(angle) * size as f32 / 8.0;
+    let start_x = ((x + 8) as f32 + spread_x) as f64;
+    let end_x = ((x + 8) as f32 - spread_x) as f64;
+    let start_z = ((z + 8) as f32 + spread_z) as f64;
+    let end_z = ((z + 8) as f32 - spread_z) as f64;
+    let start_y = (y + random.next_int_bounded(3) + y_shift) as f64;
+    let end_y = (y + random.next_int_bounded(3) + y_shift) as f64;
+    for step in 0..=size {
+        let cx = start_x + ((end_x - start_x) * step as f64) / size as f64;
+        let cy = start_y + ((end_y - start_y) * step as f64) / size as f64;
+        let cz = start_z + ((end_z - start_z) * step as f64) / size as f64;
+        let scale = (random.next_double() * size as f64) / 16.0;
+        let bulge = (math::sin((step as f32 * math::PI) / size as f32) + 1.0) as f64;
+        let width = bulge * scale + 1.0;
+        let height = bulge * scale + 1.0;
+        let (min_x, max_x) = (math::floor(cx - width / 2.0), math::floor(cx + width / 2.0));
+        let (min_y, max_y) = (math::floor(cy - height / 2.0), math::floor(cy + height / 2.0));
+        let (min_z, max_z) = (math::floor(cz - width / 2.0), math::floor(cz + width / 2.0));
+        for bx in min_x..=max_x {
+            let dx = ((bx as f64 + 0.5) - cx) / (width / 2.0);
+            if dx * dx >= 1.0 {
+                continue;
+            }
+            for by in min_y..=max_y {
+                let dy = ((by as f64 + 0.5) - cy) / (height / 2.0);
+                if dx * dx + dy * dy >= 1.0 {
+                    continue;
+                }
+                for bz in min_z..=max_z {
+                    let dz = ((bz as f64 + 0.5) - cz) / (width / 2.0);
+                    if dx * dx + dy * dy + dz * dz < 1.0 && region.get(bx, by, bz) == replaces {
+                        region.set(bx, by, bz, block_type);
+                    }
+                }
+            }
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tree {
+    Oak,
+    Big,
+    Birch,
+    /// The tall, thin taiga tree, `WorldGenTaiga1`.
+    Pine,
+    /// The bushier taiga tree, `WorldGenTaiga2`.
+    Spruce,
+}
+impl Tree {
+    pub fn grow(&self, region: &mut Region, random: &mut JavaRandom, x: i32, y: i32, z: i32) -> bool {
+        match self {
+            Self::Oak => small_tree(region, random, (x, y, z), 4, 0),
+            Self::Birch => small_tree(region, random, (x, y, z), 5, 2),
+            Self::Pine => pine(region, random, (x, y, z)),
+            Self::Spruce => spruce(region, random, (x, y, z)),
+            Self::Big => BigTree::new(random.next_long(), (x, y, z)).grow(region),
+        }
+    }
+}
+/// Checks every block in the rings `radius(y)` returns is air or leaves.
+fn has_room(region: &mut Region, (x, y, z): (i32, i32, i32), height: i32, radius: impl Fn(i32) -> i32) -> bool {
+    if y < 1 || y + height + 1 > 128 {
+        return false;
+    }
+    for by in y..=y + 1 + height {
+        let r = radius(by);
+        for bx in x - r..=x + r {
+            for bz in z - r..=z + r {
+                if !(0..128).contains(&by) {
+                    return false;
+                }
+                let block_type = region.get(bx, by, bz);
+                if block_type != AIR && block_type != LEAVES {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+/// Swaps the grass under a sapling spot for dirt, failing if it isn't grass or dirt.
+fn plant_on_soil(region: &mut Region, (x, y, z): (i32, i32, i32), height: i32) -> bool {
+    let soil = region.get(x, y - 1, z);
+    if soil != GRASS && soil != DIRT || y >= 128 - height - 1 {
+        return false;
+    }
+    region.set(x, y - 1, z, DIRT);
+    true
+}
+fn place_trunk(region: &mut Region, (x, y, z): (i32, i32, i32), height: i32, meta: u8) {
+    for dy in 0..height {
+        let block_type = region.get(x, y + dy, z);
+        if block_type == AIR || block_type == LEAVES {
+            region.set_with_meta(x, y + dy, z, LOG, meta);
+        }
+    }
+}
+/// `WorldGenTrees` and `WorldGenForest`, which only differ in height and wood type.
+fn small_tree(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32), min_height: i32, meta: u8) -> bool {
+    let height = random.next_int_bounded(3) + min_height;
+    let top = y + 1 + height;
+    let room = has_room(region, (x, y, z), height, |by| {
+        if by >= top - 2 {
+            2
+        } else if by == y {
+            0
+        } else {
+            1
+        }
+    });
+    if !room || !plant_on_soil(region, (x, y, z), height) {
+        return false;
+    }
+    for by in y - 3 + height..=y + height {
+        let dy = by - (y + height);
+        let radius = 1 - dy / 2;
+        for bx in x - radius..=x + radius {
+            for bz in z - radius..=z + radius {
+                let (dx, dz) = (bx - x, bz - z);
+                if (dx.abs() != radius || dz.abs() != radius || random.next_int_bounded(2) != 0 && dy != 0)
+                    && !is_opaque_cube(region.get(bx, by, bz))
+                {
+                    region.set_with_meta(bx, by, bz, LEAVES, meta);
+                }
+            }
+        }
+    }
+    place_trunk(region, (x, y, z), height, meta);
+    true
+}
+fn pine(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) -> bool {
+    let height = random.next_int_bounded(5) + 7;
+    let bare = height - random.next_int_bounded(2) - 3;
+    let leafy = height - bare;
+    let max_radius = 1 + random.next_int_bounded(leafy + 1);
+    let room = has_room(region, (x, y, z), height, |by| if by - y < bare { 0 } else { max_radius });
+    if !room || !plant_on_soil(region, (x, y, z), height) {
+        return false;
+    }
+    let mut radius = 0;
+    for by in (y + bare..=y + height).rev() {
+        place_leaf_ring(region, (x, by, z), radius);
+        if radius >= 1 && by == y + bare + 1 {
+            radius -= 1;
+        } else if radius < max_radius {
+            radius += 1;
+        }
+    }
+    place_trunk(region, (x, y, z), height - 1, 1);
+    true
+}
+fn spruce(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) -> bool {
+    let height = random.next_int_bounded(4) + 6;
+    let bare = 1 + random.next_int_bounded(2);
+    let leafy = height - bare;
+    let max_radius = 2 + random.next_int_bounded(2);
+    let room = has_room(region, (x, y, z), height, |by| if by - y < bare { 0 } else { max_radius });
+    if !room || !plant_on_soil(region, (x, y, z), height) {
+        return false;
+    }
+    let mut radius = random.next_int_bounded(2);
+    let mut ring_limit = 1;
+    let mut past_top = false;
+    for dy in 0..=leafy {
+        place_leaf_ring(region, (x, y + height - dy, z), radius);
+        if radius >= ring_limit {
+            radius = past_top as i32;
+            past_top = true;
+            ring_limit = (ring_limit + 1).min(max_radius);
+        } else {
+            radius += 1;
+        }
+    }
+    let trunk_gap = random.next_int_bounded(3);
+    place_trunk(region, (x, y, z), height - trunk_gap, 1);
+    true
+}
+/// A square of spruce leaves with the corners cut off, used by both taiga trees.
+fn place_leaf_ring(region: &mut Region, (x, y, z): (i32, i32, i32), radius: i32) {
+    for bx in x - radius..=x + radius {
+        for bz in z - radius..=z + radius {
+            let (dx, dz) = (bx - x, bz - z);
+            if (dx.abs() != radius || dz.abs() != radius || radius <= 0) && !is_opaque_cube(region.get(bx, y, bz)) {
+                region.set_with_meta(bx, y, bz, LEAVES, 1);
+            }
+        }
+    }
+}
+/// `WorldGenBigTree`, which draws from its own random seeded off the population one.
+struct BigTree {
+    random: JavaRandom,
+    base: [i32; 3],
+    height_limit: i32,
+    height: i32,
+    leaf_distance_limit: i32,
+    /// Branch ends as x, y, z and the trunk height the branch starts from.
+    leaf_nodes: Vec<[i32; 4]>,
+}
+/// For each axis, the two others.
+const OTHER_AXES: [usize; 6] = [2, 0, 0, 1, 2, 1];
+impl BigTree {
+    const HEIGHT_ATTENUATION: f64 = 0.618;
+    const BRANCH_SLOPE: f64 = 0.381;
+    /// Branch angles use an even shorter pi than the rest of vanilla.
+    #[allow(clippy::approx_constant)]
+    const PI: f64 = 3.14159;
+    fn new(seed: i64, (x, y, z): (i32, i32, i32)) -> Self {
+        Self {
+            random: JavaRandom::new(seed),
+            base: [x, y, z],
+            height_limit: 0,
+            height: 0,
+            leaf_distance_limit: 5,
+            leaf_nodes: Vec::new(),
+        }
+    }
+    fn grow(mut self, region: &mut Region) -> bool {
+        self.height_limit = 5 + self.random.next_int_bounded(12);
+        if !self.valid_location(region) {
+            return false;
+        }
+        self.generate_leaf_nodes(region);
+        for node in self.leaf_nodes.clone() {
+            for y in node[1]..node[1] + self.leaf_distance_limit {
+                let size = self.leaf_size(y - node[1]);
+                Self::cross_section(region, [node[0], y, node[2]], size);
+            }
+        }
+        let [x, y, z] = self.base;
+        Self::place_line(region, [x, y, z], [x, y + self.height, z]);
+        for node in self.leaf_nodes.clone() {
+            let start = [x, node[3], z];
+            if (node[3] - y) as f64 >= self.height_limit as f64 * 0.2 {
+                Self::place_line(region, start, [node[0], node[1], node[2]]);
+            }
+        }
+        true
+    }
+    fn valid_location(&mut self, region: &mut Region) -> bool {
+        let [x, y, z] = self.base;
+        let soil = region.get(x, y - 1, z);
+        if soil != GRASS && soil != DIRT {
+            return false;
+        }
+        match Self::check_line(region, self.base, [x, y + self.height_limit - 1, z]) {
+            -1 => true,
+            free if free < 6 => false,
+            free => {
+                self.height_limit = free;
+                true
+            }
+        }
+    }
+    fn generate_leaf_nodes(&mut self, region: &mut Region) {
+        self.height = (self.height_limit as f64 * Self::HEIGHT_ATTENUATION) as i32;
+        if self.height >= self.height_limit {
+            self.height = self.height_limit - 1;
+        }
+        let per_layer = ((1.382 + (self.height_limit as f64 / 13.0).powi(2)) as i32).max(1);
+        let [base_x, base_y, base_z] = self.base;
+        let mut y = base_y + self.height_limit - self.leaf_distance_limit;
+        let trunk_top = base_y + self.height;
+        let mut layer = y - base_y;
+        self.leaf_nodes.push([base_x, y, base_z, trunk_top]);
+        y -= 1;
+        while layer >= 0 {
+            let size = self.layer_size(layer);
+            if size >= 0.0 {
+                for _ in 0..per_layer {
+                    let distance = size as f64 * (self.random.next_float() as f64 + 0.328);
+                    let angle = self.random.next_float() as f64 * 2.0 * Self::PI;
+                    let nx = math::floor(distance * angle.sin() + base_x as f64 + 0.5);
+                    let nz = math::floor(distance * angle.cos() + base_z as f64 + 0.5);
+                    let node = [nx, y, nz];
+                    if Self::check_line(region, node, [nx, y + self.leaf_distance_limit, nz]) != -1 {
+                        continue;
+                    }
+                    let dx = (base_x - nx).abs() as f64;
+                    let dz = (base_z - nz).abs() as f64;
+                    let slope = (dx * dx + dz * dz).sqrt() * Self::BRANCH_SLOPE;
+                    let branch_y = if y as f64 - slope > trunk_top as f64 {
+                        trunk_top
+                    } else {
+                        (y as f64 - slope) as i32
+                    };
+                    if Self::check_line(region, [base_x, branch_y, base_z], node) == -1 {
+                        self.leaf_nodes.push([nx, y, nz, branch_y]);
+                    }
+                }
+            }
+            y -= 1;
+            layer -= 1;
+        }
+    }
+    fn layer_size(&self, layer: i32) -> f32 {
+        if (layer as f64) < self.height_limit as f32 as f64 * 0.3 {
+            return -1.618;
+        }
+        let half = self.height_limit as f32 / 2.0;
+        let offset = self.height_limit as f32 / 2.0 - layer as f32;
+        let size = if offset == 0.0 {
+            half
+        } else if offset.abs() >= half {
+            0.0
+        } else {
+            let (half, offset) = (half.abs() as f64, offset.abs() as f64);
+            (half * half - offset * offset).sqrt() as f32
+        };
+        size * 0.5
+    }
+    fn leaf_size(&self, y: i32) -> f32 {
+        if y < 0 || y >= self.leaf_distance_limit {
+            -1.0
+        } else if y != 0 && y != self.leaf_distance_limit - 1 {
+            3.0
+        } else {
+            2.0
+        }
+    }
+    /// A disc of leaves in the horizontal plane.
+    fn cross_section(region: &mut Region, center: [i32; 3], radius: f32) {
+        let extent = (radius as f64 + 0.618) as i32;
+        for a in -extent..=extent {
+            for b in -extent..=extent {
+                let da = a.abs() as f64 + 0.5;
+                let db = b.abs() as f64 + 0.5;
+                if (da * da + db * db).sqrt() > radius as f64 {
+                    continue;
+                }
+                let (x, y, z) = (center[0] + a, center[1], center[2] + b);
+                let block_type = region.get(x, y, z);
+                if block_type == AIR || block_type == LEAVES {
+                    region.set(x, y, z, LEAVES);
+                }
+            }
+        }
+    }
+    /// Steps from `from` towards `to` along their longest axis, `(major, minor a, minor b, step)`.
+    fn line(from: [i32; 3], to: [i32; 3]) -> Option<(usize, usize, usize, [i32; 3], i32)> {
+        let delta = [to[0] - from[0], to[1] - from[1], to[2] - from[2]];
+        let mut major = 0;
+        for axis in 0..3 {
+            if delta[axis].abs() > delta[major].abs() {
+                major = axis;
+            }
+        }
+        if delta[major] == 0 {
+            return None;
+        }
+        let step = if delta[major] > 0 { 1 } else { -1 };
+        Some((major, OTHER_AXES[major], OTHER_AXES[major + 3], delta, step))
+    }
+    fn place_line(region: &mut Region, from: [i32; 3], to: [i32; 3]) {
+        let (major, a, b, delta, step) = match Self::line(from, to) {
+            Some(line) => line,
+            None => return,
+        };
+        let slope_a = delta[a] as f64 / delta[major] as f64;
+        let slope_b = delta[b] as f64 / delta[major] as f64;
+        let end = delta[major] + step;
+        let mut i = 0;
+        while i != end {
+            let mut position = [0; 3];
+            position[major] = math::floor((from[major] + i) as f64 + 0.5);
+            position[a] = math::floor(from[a] as f64 + i as f64 * slope_a + 0.5);
+            position[b] = math::floor(from[b] as f64 + i as f64 * slope_b + 0.5);
+            region.set(position[0], position[1], position[2], LOG);
+            i += step;
+        }
+    }
+    /// How far the line gets before hitting something other than air or leaves, -1 if it gets all the way.
+    fn check_line(region: &mut Region, from: [i32; 3], to: [i32; 3]) -> i32 {
+        let (major, a, b, delta, step) = match Self::line(from, to) {
+            Some(line) => line,
+            None => return -1,
+        };
+        let slope_a = delta[a] as f64 / delta[major] as f64;
+        let slope_b = delta[b] as f64 / delta[major] as f64;
+        let end = delta[major] + step;
+        let mut i = 0;
+        while i != end {
+            let mut position = [0; 3];
+            position[major] = from[major] + i;
+            position[a] = math::floor(from[a] as f64 + i as f64 * slope_a);
+            position[b] = math::floor(from[b] as f64 + i as f64 * slope_b);
+            let block_type = region.get(position[0], position[1], position[2]);
+            if block_type != AIR && block_type != LEAVES {
+                break;
+            }
+            i += step;
+        }
+        if i == end {
+            -1
+        } else {
+            i.abs()
+        }
+    }
+}
+/// `BlockFlower.canBlockStay`: enough light and a block it grows on underneath.
+fn plant_can_stay(region: &mut Region, (x, y, z): (i32, i32, i32), grows_on: impl Fn(u8) -> bool) -> bool {
+    (region.skylight(x, y.min(127), z) >= 8 || region.can_see_sky(x, y, z)) && grows_on(region.get(x, y - 1, z))
+}
+fn grows_on_soil(block_type: u8) -> bool {
+    matches!(block_type, GRASS | DIRT | FARMLAND)
+}
+fn scatter(random: &mut JavaRandom, (x, y, z): (i32, i32, i32), spread: i32, spread_y: i32) -> (i32, i32, i32) {
+    let x = x + random.next_int_bounded(spread) - random.next_int_bounded(spread);
+    let y = y + random.next_int_bounded(spread_y) - random.next_int_bounded(spread_y);
+    let z = z + random.next_int_bounded(spread) - random.next_int_bounded(spread);
+    (x, y, z)
+}
+/// `WorldGenFlowers`, also used for mushrooms.
+pub fn flowers(region: &mut Region, random: &mut JavaRandom, origin: (i32, i32, i32), block_type: u8) {
+    for _ in 0..64 {
+        let (x, y, z) = scatter(random, origin, 8, 4);
+        if !region.is_air(x, y, z) {
+            continue;
+        }
+        let stays = if block_type == BROWN_MUSHROOM || block_type == RED_MUSHROOM {
+            (0..128).contains(&y) && region.skylight(x, y, z) < 13 && is_opaque_cube(region.get(x, y - 1, z))
+        } else {
+            plant_can_stay(region, (x, y, z), grows_on_soil)
+        };
+        if stays {
+            region.set(x, y, z, block_type);
+        }
+    }
+}
+/// Moves down from `y` past air and leaves, where tall grass and dead bushes start scattering from.
+fn ground_below(region: &mut Region, x: i32, mut y: i32, z: i32) -> i32 {
+    loop {
+        let block_type = region.get(x, y, z);
+        if !(block_type == AIR || block_type == LEAVES) || y <= 0 {
+            return y;
+        }
+        y -= 1;
+    }
+}
+/// `WorldGenTallGrass`: tall grass (meta 1) or ferns (meta 2).
+pub fn tall_grass(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32), meta: u8) {
+    let y = ground_below(region, x, y, z);
+    for _ in 0..128 {
+        let (bx, by, bz) = scatter(random, (x, y, z), 8, 4);
+        if region.is_air(bx, by, bz) && plant_can_stay(region, (bx, by, bz), grows_on_soil) {
+            region.set_with_meta(bx, by, bz, TALL_GRASS, meta);
+        }
+    }
+}
+/// `WorldGenDeadBush`.
+pub fn dead_bush(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) {
+    let y = ground_below(region, x, y, z);
+    for _ in 0..4 {
+        let (bx, by, bz) = scatter(random, (x, y, z), 8, 4);
+        if region.is_air(bx, by, bz) && plant_can_stay(region, (bx, by, bz), |below| below == SAND) {
+            region.set(bx, by, bz, DEAD_BUSH);
+        }
+    }
+}
+fn next_to_water(region: &mut Region, x: i32, y: i32, z: i32) -> bool {
+    region.is_water(x - 1, y, z) || region.is_water(x + 1, y, z) || region.is_water(x, y, z - 1) || region.is_water(x, y, z + 1)
+}
+/// `WorldGenReed`: sugar cane on grass or dirt beside water.
+pub fn reeds(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) {
+    for _ in 0..20 {
+        let bx = x + random.next_int_bounded(4) - random.next_int_bounded(4);
+        let bz = z + random.next_int_bounded(4) - random.next_int_bounded(4);
+        if !region.is_air(bx, y, bz) || !next_to_water(region, bx, y - 1, bz) {
+            continue;
+        }
+        let bound = random.next_int_bounded(3) + 1;
+        let height = 2 + random.next_int_bounded(bound);
+        for dy in 0..height {
+            let below = region.get(bx, y + dy - 1, bz);
+            let stays = below == REED || (below == GRASS || below == DIRT) && next_to_water(region, bx, y + dy - 1, bz);
+            if stays {
+                region.set(bx, y + dy, bz, REED);
+            }
+        }
+    }
+}
+/// `WorldGenPumpkin`.
+pub fn pumpkins(region: &mut Region, random: &mut JavaRandom, origin: (i32, i32, i32)) {
+    for _ in 0..64 {
+        let (x, y, z) = scatter(random, origin, 8, 4);
+        if region.is_air(x, y, z) && region.get(x, y - 1, z) == GRASS {
+            let facing = random.next_int_bounded(4) as u8;
+            region.set_with_meta(x, y, z, PUMPKIN, facing);
+        }
+    }
+}
+/// `WorldGenCactus`.
+pub fn cacti(region: &mut Region, random: &mut JavaRandom, origin: (i32, i32, i32)) {
+    for _ in 0..10 {
+        let (x, y, z) = scatter(random, origin, 8, 4);
+        if !region.is_air(x, y, z) {
+            continue;
+        }
+        let bound = random.next_int_bounded(3) + 1;
+        let height = 1 + random.next_int_bounded(bound);
+        for by in y..y + height {
+            let crowded = region.is_solid(x - 1, by, z)
+                || region.is_solid(x + 1, by, z)
+                || region.is_solid(x, by, z - 1)
+                || region.is_solid(x, by, z + 1);
+            let below = region.get(x, by - 1, z);
+            if !crowded && (below == CACTUS || below == SAND) {
+                region.set(x, by, z, CACTUS);
+            }
+        }
+    }
+}
+/// `WorldGenLiquids`: a spring in a stone wall with exactly one open side.
+///
+/// Vanilla lets the spring flow out on the spot. Here the source is only placed, the caller
+/// schedules its first flow update.
+pub fn spring(region: &mut Region, (x, y, z): (i32, i32, i32), fluid: u8) -> Option<BlockPosition> {
+    if region.get(x, y + 1, z) != STONE || region.get(x, y - 1, z) != STONE {
+        return None;
+    }
+    let here = region.get(x, y, z);
+    if here != AIR && here != STONE {
+        return None;
+    }
+    let sides = [(x - 1, z), (x + 1, z), (x, z - 1), (x, z + 1)];
+    let stone = sides.iter().filter(|(sx, sz)| region.get(*sx, y, *sz) == STONE).count();
+    let open = sides.iter().filter(|(sx, sz)| region.is_air(*sx, y, *sz)).count();
+    if stone == 3 && open == 1 {
+        region.set(x, y, z, fluid);
+        return Some(BlockPosition { x, y, z });
+    }
+    None
+}
+/// Snow on the top solid block of each column cold enough for it.
+pub fn snow(region: &mut Region, x: i32, z: i32, temperature: f64) {
+    let y = region.top_solid_block(x, z);
+    let temperature = temperature - ((y - 64) as f64 / 64.0) * 0.3;
+    if temperature < 0.5 && y > 0 && y < 128 && region.is_air(x, y, z) {
+        let below = region.get(x, y - 1, z);
+        if is_solid(below) && below != ICE {
+            region.set(x, y, z, SNOW);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! The nether, after the b1.7.3 `ChunkProviderHell`.
+//!
+//! Terrain and surface follow the vanilla density and soul sand passes. The cave carver vanilla
+//! runs afterwards is left out, and population places the same features in the same order but
+//! isn't checked against the vanilla server.
+use super::chunk_from_blocks;
+use super::features::Region;
+use super::ids::*;
+use super::noise::{JavaRandom, OctaveNoise};
+use crate::configuration::CONFIGURATION;
+use crate::game::fluids::Fluid;
+use crate::game::{BlockPosition, ChunkCoords};
+use crate::world::chunks::{Chunk, World, WorldGenerator};
+use std::collections::HashMap;
+/// Everything below this is a lava sea where the terrain leaves room.
+const LAVA_LEVEL: usize = 32;
+/// Height of the band where soul sand and gravel replace the netherrack surface.
+const SURFACE_LEVEL: i32 = 64;
+pub struct NetherChunkGenerator {
+    seed: i64,
+    low_noise: OctaveNoise,
+    high_noise: OctaveNoise,
+    selector_noise: OctaveNoise,
+    surface_noise: OctaveNoise,
+    surface_depth_noise: OctaveNoise,
+}
+impl NetherChunkGenerator {
+    pub fn new(seed: u64) -> Self {
+        let seed = seed as i64;
+        let mut random = JavaRandom::new(seed);
+        Self {
+            seed,
+            low_noise: OctaveNoise::new(&mut random, 16),
+            high_noise: OctaveNoise::new(&mut random, 16),
+            selector_noise: OctaveNoise::new(&mut random, 8),
+            surface_noise: OctaveNoise::new(&mut random, 4),
+            surface_depth_noise: OctaveNoise::new(&mut random, 4),
+        }
+    }
+    /// The chunk's blocks before decoration, indexed `x << 11 | z << 7 | y`.
+    pub fn generate_blocks(&self, chunk_x: i32, chunk_z: i32) -> Vec<u8> {
+        let mut random = JavaRandom::new(
+            (chunk_x as i64)
+                .wrapping_mul(341873128712)
+                .wrapping_add((chunk_z as i64).wrapping_mul(132897987541)),
+        );
+        let mut blocks = vec![AIR; 32768];
+        self.generate_terrain(chunk_x, chunk_z, &mut blocks);
+        self.replace_surface(chunk_x, chunk_z, &mut blocks, &mut random);
+        blocks
+    }
+    /// The terrain density on a grid of 4 by 8 by 4 block cells, `func_4057_a`. Unlike the
+    /// overworld it closes off at both the top and the bottom, leaving a cavern in between.
+    ///
+    /// Vanilla also samples a scale and a depth noise here and then never uses them. Those come
+    /// last off the seed, so they are skipped without moving any of the other noise.
+    fn density(&self, origin: (i32, i32, i32), size: (usize, usize, usize)) -> Vec<f64> {
+        let (x, y, z) = origin;
+        let (size_x, size_y, size_z) = size;
+        let horizontal = 684.412;
+        let vertical = 2053.236;
+        let origin = (x as f64, y as f64, z as f64);
+        let mut selector = Vec::new();
+        let mut low = Vec::new();
+        let mut high = Vec::new();
+        self.selector_noise.grid(
+            &mut selector,
+            origin,
+            size,
+            (horizontal / 80.0, vertical / 60.0, horizontal / 80.0),
+        );
+        self.low_noise.grid(&mut low, origin, size, (horizontal, vertical, horizontal));
+        self.high_noise.grid(&mut high, origin, size, (horizontal, vertical, horizontal));
+        // A wave through the height that pinches the terrain together near the floor and ceiling.
+        let mut falloff = Vec::with_capacity(size_y);
+        for cy in 0..size_y {
+            let mut value = ((cy as f64 * std::f64::consts::PI * 6.0) / size_y as f64).cos() * 2.0;
+            let edge = if cy > size_y / 2 { size_y - 1 - cy } else { cy } as f64;
+            if edge < 4.0 {
+                let edge = 4.0 - edge;
+                value -= edge * edge * edge * 10.0;
+            }
+            falloff.push(value);
+        }
+        let mut density = Vec::with_capacity(size_x * size_y * size_z);
+        for _ in 0..size_x * size_z {
+            for (cy, falloff) in falloff.iter().enumerate() {
+                let index = density.len();
+                let low = low[index] / 512.0;
+                let high = high[index] / 512.0;
+                let blend = (selector[index] / 10.0 + 1.0) / 2.0;
+                let mut value = if blend < 0.0 {
+                    low
+                } else if blend > 1.0 {
+                    high
+                } else {
+                    low + (high - low) * blend
+                };
+                value -= falloff;
+                if cy > size_y - 4 {
+                    let top = ((cy - (size_y - 4)) as f32 / 3.0) as f64;
+                    value = value * (1.0 - top) + -10.0 * top;
+                }
+                density.push(value);
+            }
+        }
+        density
+    }
+    /// Netherrack where the interpolated density is positive, lava below `LAVA_LEVEL`, `func_4062_a`.
+    fn generate_terrain(&self, chunk_x: i32, chunk_z: i32, blocks: &mut [u8]) {
+        const CELLS: usize = 4;
+        const SIZE_X: usize = CELLS + 1;
+        const SIZE_Y: usize = 17;
+        const SIZE_Z: usize = CELLS + 1;
+        let density = self.density((chunk_x * CELLS as i32, 0, chunk_z * CELLS as i32), (SIZE_X, SIZE_Y, SIZE_Z));
+        let at = |x: usize, z: usize, y: usize| density[(x * SIZE_Z + z) * SIZE_Y + y];
+        for cx in 0..CELLS {
+            for cz in 0..CELLS {
+                for cy in 0..SIZE_Y - 1 {
+                    let mut corner_a = at(cx, cz, cy);
+                    let mut corner_b = at(cx, cz + 1, cy);
+                    let mut corner_c = at(cx + 1, cz, cy);
+                    let mut corner_d = at(cx + 1, cz + 1, cy);
+                    let step_a = (at(cx, cz, cy + 1) - corner_a) * 0.125;
+                    let step_b = (at(cx, cz + 1, cy + 1) - corner_b) * 0.125;
+                    let step_c = (at(cx + 1, cz, cy + 1) - corner_c) * 0.125;
+                    let step_d = (at(cx + 1, cz + 1, cy + 1) - corner_d) * 0.125;
+                    for ly in 0..8 {
+                        let mut edge_a = corner_a;
+                        let mut edge_b = corner_b;
+                        let edge_step_a = (corner_c - corner_a) * 0.25;
+                        let edge_step_b = (corner_d - corner_b) * 0.25;
+                        for lx in 0..4 {
+                            let x = lx + cx * 4;
+                            let y = cy * 8 + ly;
+                            let mut index = x << 11 | (cz * 4) << 7 | y;
+                            let mut value = edge_a;
+                            let value_step = (edge_b - edge_a) * 0.25;
+                            for _ in 0..4 {
+                                let mut block_type = AIR;
+                                if y < LAVA_LEVEL {
+                                    block_type = STILL_LAVA;
+                                }
+                                if value > 0.0 {
+                                    block_type = NETHERRACK;
+                                }
+                                blocks[index] = block_type;
+                                index += 128;
+                                value += value_step;
+                            }
+                            edge_a += edge_step_a;
+                            edge_b += edge_step_b;
+                        }
+                        corner_a += step_a;
+                        corner_b += step_b;
+                        corner_c += step_c;
+                        corner_d += step_d;
+                    }
+                }
+            }
+        }
+    }
+    /// Soul sand and gravel around y 64 and bedrock at the floor and ceiling, `func_4058_b`.
+    fn replace_surface(&self, chunk_x: i32, chunk_z: i32, blocks: &mut [u8], random: &mut JavaRandom) {
+        let scale = 1.0 / 32.0;
+        let (x, z) = ((chunk_x * 16) as f64, (chunk_z * 16) as f64);
+        let mut soul_sand = Vec::new();
+        let mut gravel = Vec::new();
+        let mut depth = Vec::new();
+        self.surface_noise.grid(&mut soul_sand, (x, z, 0.0), (16, 16, 1), (scale, scale, 1.0));
+        self.surface_noise.grid(&mut gravel, (x, 109.0134, z), (16, 1, 16), (scale, 1.0, scale));
+        self.surface_depth_noise.grid(&mut depth, (x, z, 0.0), (16, 16, 1), (scale * 2.0, scale * 2.0, scale * 2.0));
+        for z in 0..16 {
+            for x in 0..16 {
+                let column = z + x * 16;
+                let is_soul_sand = soul_sand[column] + random.next_double() * 0.2 > 0.0;
+                let is_gravel = gravel[column] + random.next_double() * 0.2 > 0.0;
+                let surface_depth = (depth[column] / 3.0 + 3.0 + random.next_double() * 0.25) as i32;
+                let mut remaining = -1;
+                let mut top = NETHERRACK;
+                let mut filler = NETHERRACK;
+                for y in (0..128).rev() {
+                    let index = (x * 16 + z) * 128 + y as usize;
+                    if y >= 127 - random.next_int_bounded(5) || y <= random.next_int_bounded(5) {
+                        blocks[index] = BEDROCK;
+                        continue;
+                    }
+                    let block_type = blocks[index];
+                    if block_type == AIR {
+                        remaining = -1;
+                        continue;
+                    }
+                    if block_type != NETHERRACK {
+                        continue;
+                    }
+                    if remaining == -1 {
+                        if surface_depth <= 0 {
+                            top = AIR;
+                            filler = NETHERRACK;
+                        } else if (SURFACE_LEVEL - 4..=SURFACE_LEVEL + 1).contains(&y) {
+                            top = NETHERRACK;
+                            filler = NETHERRACK;
+                            if is_gravel {
+                                top = GRAVEL;
+                            }
+                            if is_soul_sand {
+                                top = SOUL_SAND;
+                                filler = SOUL_SAND;
+                            }
+                        }
+                        if y < SURFACE_LEVEL && top == AIR {
+                            top = STILL_LAVA;
+                        }
+                        remaining = surface_depth;
+                        blocks[index] = if y >= SURFACE_LEVEL - 1 { top } else { filler };
+                        continue;
+                    }
+                    if remaining > 0 {
+                        remaining -= 1;
+                        blocks[index] = filler;
+                    }
+                }
+            }
+        }
+    }
+    /// Lava springs, fire and glowstone across the chunk at `coords` and its +x, +z neighbours.
+    /// Returns the springs, which still need their first update.
+    fn decorate(&self, chunks: &mut HashMap<ChunkCoords, Chunk>, coords: ChunkCoords) -> Vec<BlockPosition> {
+        let region = &mut Region::new(chunks);
+        let (x, z) = (coords.x * 16, coords.z * 16);
+        let mut random = JavaRandom::new(self.seed);
+        let x_seed = random.next_long() / 2 * 2 + 1;
+        let z_seed = random.next_long() / 2 * 2 + 1;
+        random.set_seed(
+            (coords.x as i64)
+                .wrapping_mul(x_seed)
+                .wrapping_add((coords.z as i64).wrapping_mul(z_seed))
+                ^ self.seed,
+        );
+        let random = &mut random;
+        let spot = |random: &mut JavaRandom| {
+            let bx = x + random.next_int_bounded(16) + 8;
+            let by = random.next_int_bounded(120) + 4;
+            let bz = z + random.next_int_bounded(16) + 8;
+            (bx, by, bz)
+        };
+        let mut springs = Vec::new();
+        for _ in 0..8 {
+            let origin = spot(random);
+            springs.extend(lava_spring(region, origin));
+        }
+        let bound = random.next_int_bounded(10) + 1;
+        let fires = random.next_int_bounded(bound) + 1;
+        for _ in 0..fires {
+            let origin = spot(random);
+            fire(region, random, origin);
+        }
+        let bound = random.next_int_bounded(10) + 1;
+        let clusters = random.next_int_bounded(bound);
+        for _ in 0..clusters + 10 {
+            let origin = spot(random);
+            glowstone(region, random, origin);
+        }
+        springs
+    }
+}
+/// `WorldGenHellLava`: lava pouring out of a netherrack wall with exactly one open side.
+fn lava_spring(region: &mut Region, (x, y, z): (i32, i32, i32)) -> Option<BlockPosition> {
+    if region.get(x, y + 1, z) != NETHERRACK {
+        return None;
+    }
+    let here = region.get(x, y, z);
+    if here != AIR && here != NETHERRACK {
+        return None;
+    }
+    let sides = [(x - 1, y, z), (x + 1, y, z), (x, y, z - 1), (x, y, z + 1), (x, y - 1, z)];
+    let netherrack = sides.iter().filter(|(sx, sy, sz)| region.get(*sx, *sy, *sz) == NETHERRACK).count();
+    let open = sides.iter().filter(|(sx, sy, sz)| region.is_air(*sx, *sy, *sz)).count();
+    if netherrack == 4 && open == 1 {
+        region.set(x, y, z, LAVA);
+        return Some(BlockPosition { x, y, z });
+    }
+    None
+}
+/// `WorldGenFire`: a scatter of fires that burn forever on the netherrack below them.
+fn fire(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) {
+    for _ in 0..64 {
+        let fx = x + random.next_int_bounded(8) - random.next_int_bounded(8);
+        let fy = y + random.next_int_bounded(4) - random.next_int_bounded(4);
+        let fz = z + random.next_int_bounded(8) - random.next_int_bounded(8);
+        if region.is_air(fx, fy, fz) && region.get(fx, fy - 1, fz) == NETHERRACK {
+            region.set(fx, fy, fz, FIRE);
+        }
+    }
+}
+/// `WorldGenLightStone1`: glowstone hanging from a netherrack ceiling, grown one block at a time
+/// onto spots touching exactly one glowstone block.
+fn glowstone(region: &mut Region, random: &mut JavaRandom, (x, y, z): (i32, i32, i32)) {
+    if !region.is_air(x, y, z) || region.get(x, y + 1, z) != NETHERRACK {
+        return;
+    }
+    region.set(x, y, z, GLOWSTONE);
+    for _ in 0..1500 {
+        let gx = x + random.next_int_bounded(8) - random.next_int_bounded(8);
+        let gy = y - random.next_int_bounded(12);
+        let gz = z + random.next_int_bounded(8) - random.next_int_bounded(8);
+        if !region.is_air(gx, gy, gz) {
+            continue;
+        }
+        let neighbours = [
+            (gx - 1, gy, gz),
+            (gx + 1, gy, gz),
+            (gx, gy - 1, gz),
+            (gx, gy + 1, gz),
+            (gx, gy, gz - 1),
+            (gx, gy, gz + 1),
+        ];
+        let touching = neighbours
+            .iter()
+            .filter(|(nx, ny, nz)| region.get(*nx, *ny, *nz) == GLOWSTONE)
+            .count();
+        if touching == 1 {
+            region.set(gx, gy, gz, GLOWSTONE);
+        }
+    }
+}
+impl WorldGenerator for NetherChunkGenerator {
+    fn gen_chunk(&self, coords: ChunkCoords) -> Chunk {
+        if CONFIGURATION.logging.chunk_gen {
+            log::info!("Generating nether chunk at ({}, {})", coords.x, coords.z);
+        }
+        chunk_from_blocks(coords, &self.generate_blocks(coords.x, coords.z))
+    }
+    fn gen_structures(&self, _world: &mut World, _coords: ChunkCoords) {}
+    fn populates_lazily(&self) -> bool {
+        true
+    }
+    fn populate(&self, world: &mut World, coords: ChunkCoords) {
+        let springs = self.decorate(&mut world.chunks, coords);
+        let now = world.scheduled_ticks.now();
+        for position in springs {
+            world.schedule_tick(position, LAVA, now + Fluid::Lava.tick_rate());
+        }
+    }
+    fn get_seed(&self) -> u64 {
+        self.seed as u64
+    }
+    fn get_name(&self) -> String {
+        "NetherChunkGenerator".to_string()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn nether_has_a_floor_ceiling_and_lava_sea() {
+        let generator = NetherChunkGenerator::new(0);
+        let blocks = generator.generate_blocks(0, 0);
+        let count = |block_type: u8| blocks.iter().filter(|&&b| b == block_type).count();
+        assert!(count(NETHERRACK) > 4096);
+        for x in 0..16 {
+            for z in 0..16 {
+                let column = &blocks[(x * 16 + z) * 128..(x * 16 + z + 1) * 128];
+                assert_eq!(column[0], BEDROCK);
+                assert_eq!(column[127], BEDROCK);
+                // Nothing but lava and netherrack fills the open space below the lava level.
+                assert!(column[1..LAVA_LEVEL].iter().all(|&b| b != AIR));
+            }
+        }
+        assert_eq!(blocks, generator.generate_blocks(0, 0));
+    }
+    #[test]
+    fn population_adds_glowstone() {
+        // Most of the area around the origin is open for this seed, with plenty of ceiling.
+        let generator = NetherChunkGenerator::new(1);
+        let mut chunks = HashMap::new();
+        let mut glowstone = 0;
+        for cx in 0..4 {
+            for cz in 0..4 {
+                let coords = ChunkCoords { x: cx, z: cz };
+                chunks.insert(coords, generator.gen_chunk(coords));
+            }
+        }
+        for cx in 0..3 {
+            for cz in 0..3 {
+                generator.decorate(&mut chunks, ChunkCoords { x: cx, z: cz });
+            }
+        }
+        for chunk in chunks.values_mut() {
+            for x in 0..16 {
+                for z in 0..16 {
+                    for y in 0..128 {
+                        if chunk.get_block(x, y, z).map(|block| block.b_type) == Some(GLOWSTONE) {
+                            glowstone += 1;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(glowstone > 0);
+    }
+}
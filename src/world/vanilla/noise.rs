@@ -0,0 +1,497 @@
+//! The random number generator and noise functions of the b1.7.3 generator, ported bit for bit.
+//!
+//! Everything here has to round exactly like the Java original, so the float and double
+//! arithmetic deliberately mirrors the casts and evaluation order of the vanilla classes.
+
+/// `java.util.Random`, the 48 bit LCG every vanilla generator draws from.
+#[derive(Clone, Debug)]
+pub struct JavaRandom {
+    seed: i64,
+}
+impl JavaRandom {
+    const MULTIPLIER: i64 = 0x5DEECE66D;
+    const MASK: i64 = (1 << 48) - 1;
+    pub fn new(seed: i64) -> Self {
+        let mut random = Self { seed: 0 };
+        random.set_seed(seed);
+        random
+    }
+    pub fn set_seed(&mut self, seed: i64) {
+        self.seed = (seed ^ Self::MULTIPLIER) & Self::MASK;
+    }
+    fn next(&mut self, bits: u32) -> i32 {
+        self.seed = self
+            .seed
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(0xB)
+            & Self::MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+    pub fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+    /// A value in `0..bound`. Like Java, `bound` must be positive.
+    pub fn next_int_bounded(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "bound must be positive");
+        if bound & -bound == bound {
+            return ((bound as i64 * self.next(31) as i64) >> 31) as i32;
+        }
+        loop {
+            let bits = self.next(31);
+            let value = bits % bound;
+            if bits.wrapping_sub(value).wrapping_add(bound - 1) >= 0 {
+                return value;
+            }
+        }
+    }
+    pub fn next_long(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+    pub fn next_bool(&mut self) -> bool {
+        self.next(1) != 0
+    }
+    pub fn next_float(&mut self) -> f32 {
+        self.next(24) as f32 / (1 << 24) as f32
+    }
+    pub fn next_double(&mut self) -> f64 {
+        (((self.next(26) as i64) << 27) + self.next(27) as i64) as f64 * (1.0 / (1i64 << 53) as f64)
+    }
+}
+/// Vanilla's `MathHelper`: a 65536 entry sine table and a floor that works on the int cast.
+pub mod math {
+    use once_cell::sync::Lazy;
+    static SIN_TABLE: Lazy<Vec<f32>> = Lazy::new(|| {
+        (0..65536)
+            .map(|i| ((i as f64 * std::f64::consts::PI * 2.0) / 65536.0).sin() as f32)
+            .collect()
+    });
+    pub fn sin(value: f32) -> f32 {
+        SIN_TABLE[((value * 10430.38) as i32 & 0xffff) as usize]
+    }
+    pub fn cos(value: f32) -> f32 {
+        SIN_TABLE[((value * 10430.38 + 16384.0) as i32 & 0xffff) as usize]
+    }
+    /// Vanilla's pi, rounded to a float that differs from `std::f32::consts::PI` in the last bit.
+    #[allow(clippy::approx_constant)]
+    pub const PI: f32 = 3.141593;
+    #[allow(clippy::approx_constant)]
+    pub const HALF_PI: f32 = 1.570796;
+    pub fn floor(value: f64) -> i32 {
+        let truncated = value as i32;
+        if value < truncated as f64 {
+            truncated - 1
+        } else {
+            truncated
+        }
+    }
+}
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+fn grad(hash: i32, x: f64, y: f64, z: f64) -> f64 {
+    let hash = hash & 15;
+    let u = if hash < 8 { x } else { y };
+    let v = if hash < 4 {
+        y
+    } else if hash == 12 || hash == 14 {
+        x
+    } else {
+        z
+    };
+    (if hash & 1 != 0 { -u } else { u }) + (if hash & 2 != 0 { -v } else { v })
+}
+/// The gradient the 2D path of `NoiseGeneratorPerlin` uses for its first corner, which differs from `grad`.
+fn grad_2d(hash: i32, x: f64, z: f64) -> f64 {
+    let hash = hash & 15;
+    let u = (1 - ((hash & 8) >> 3)) as f64 * x;
+    let v = if hash < 4 {
+        0.0
+    } else if hash == 12 || hash == 14 {
+        x
+    } else {
+        z
+    };
+    (if hash & 1 != 0 { -u } else { u }) + (if hash & 2 != 0 { -v } else { v })
+}
+/// One octave of improved Perlin noise, `NoiseGeneratorPerlin`.
+pub struct PerlinNoise {
+    permutations: [i32; 512],
+    x_offset: f64,
+    y_offset: f64,
+    z_offset: f64,
+}
+impl PerlinNoise {
+    pub fn new(random: &mut JavaRandom) -> Self {
+        let x_offset = random.next_double() * 256.0;
+        let y_offset = random.next_double() * 256.0;
+        let z_offset = random.next_double() * 256.0;
+        Self {
+            permutations: shuffled_permutations(random),
+            x_offset,
+            y_offset,
+            z_offset,
+        }
+    }
+    fn p(&self, index: i32) -> i32 {
+        self.permutations[index as usize]
+    }
+    pub fn noise(&self, x: f64, y: f64, z: f64) -> f64 {
+        let x = x + self.x_offset;
+        let y = y + self.y_offset;
+        let z = z + self.z_offset;
+        let (xi, yi, zi) = (math::floor(x), math::floor(y), math::floor(z));
+        let (px, py, pz) = (xi & 255, yi & 255, zi & 255);
+        let (x, y, z) = (x - xi as f64, y - yi as f64, z - zi as f64);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let a = self.p(px) + py;
+        let aa = self.p(a) + pz;
+        let ab = self.p(a + 1) + pz;
+        let b = self.p(px + 1) + py;
+        let ba = self.p(b) + pz;
+        let bb = self.p(b + 1) + pz;
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(self.p(aa), x, y, z), grad(self.p(ba), x - 1.0, y, z)),
+                lerp(u, grad(self.p(ab), x, y - 1.0, z), grad(self.p(bb), x - 1.0, y - 1.0, z)),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(self.p(aa + 1), x, y, z - 1.0),
+                    grad(self.p(ba + 1), x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(self.p(ab + 1), x, y - 1.0, z - 1.0),
+                    grad(self.p(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+    /// Adds a grid of samples to `out`, x outermost and y innermost.
+    ///
+    /// Like vanilla, a grid one sample tall takes a separate 2D path that ignores `y`, and the 3D
+    /// path only recomputes its y corners when the integer y lattice cell changes.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_grid(
+        &self,
+        out: &mut [f64],
+        (x, y, z): (f64, f64, f64),
+        (size_x, size_y, size_z): (usize, usize, usize),
+        (scale_x, scale_y, scale_z): (f64, f64, f64),
+        amplitude: f64,
+    ) {
+        let inverse = 1.0 / amplitude;
+        let mut index = 0;
+        if size_y == 1 {
+            for ix in 0..size_x {
+                let sx = (x + ix as f64) * scale_x + self.x_offset;
+                let xi = math::floor(sx);
+                let px = xi & 255;
+                let sx = sx - xi as f64;
+                let u = fade(sx);
+                for iz in 0..size_z {
+                    let sz = (z + iz as f64) * scale_z + self.z_offset;
+                    let zi = math::floor(sz);
+                    let pz = zi & 255;
+                    let sz = sz - zi as f64;
+                    let w = fade(sz);
+                    let a = self.p(px);
+                    let aa = self.p(a) + pz;
+                    let b = self.p(px + 1);
+                    let ba = self.p(b) + pz;
+                    let near = lerp(
+                        u,
+                        grad_2d(self.p(aa), sx, sz),
+                        grad(self.p(ba), sx - 1.0, 0.0, sz),
+                    );
+                    let far = lerp(
+                        u,
+                        grad(self.p(aa + 1), sx, 0.0, sz - 1.0),
+                        grad(self.p(ba + 1), sx - 1.0, 0.0, sz - 1.0),
+                    );
+                    out[index] += lerp(w, near, far) * inverse;
+                    index += 1;
+                }
+            }
+            return;
+        }
+        let mut last_py = -1;
+        let (mut l1, mut l2, mut l3, mut l4) = (0.0, 0.0, 0.0, 0.0);
+        for ix in 0..size_x {
+            let sx = (x + ix as f64) * scale_x + self.x_offset;
+            let xi = math::floor(sx);
+            let px = xi & 255;
+            let sx = sx - xi as f64;
+            let u = fade(sx);
+            for iz in 0..size_z {
+                let sz = (z + iz as f64) * scale_z + self.z_offset;
+                let zi = math::floor(sz);
+                let pz = zi & 255;
+                let sz = sz - zi as f64;
+                let w = fade(sz);
+                for iy in 0..size_y {
+                    let sy = (y + iy as f64) * scale_y + self.y_offset;
+                    let yi = math::floor(sy);
+                    let py = yi & 255;
+                    let sy = sy - yi as f64;
+                    let v = fade(sy);
+                    if iy == 0 || py != last_py {
+                        last_py = py;
+                        let a = self.p(px) + py;
+                        let aa = self.p(a) + pz;
+                        let ab = self.p(a + 1) + pz;
+                        let b = self.p(px + 1) + py;
+                        let ba = self.p(b) + pz;
+                        let bb = self.p(b + 1) + pz;
+                        l1 = lerp(u, grad(self.p(aa), sx, sy, sz), grad(self.p(ba), sx - 1.0, sy, sz));
+                        l2 = lerp(
+                            u,
+                            grad(self.p(ab), sx, sy - 1.0, sz),
+                            grad(self.p(bb), sx - 1.0, sy - 1.0, sz),
+                        );
+                        l3 = lerp(
+                            u,
+                            grad(self.p(aa + 1), sx, sy, sz - 1.0),
+                            grad(self.p(ba + 1), sx - 1.0, sy, sz - 1.0),
+                        );
+                        l4 = lerp(
+                            u,
+                            grad(self.p(ab + 1), sx, sy - 1.0, sz - 1.0),
+                            grad(self.p(bb + 1), sx - 1.0, sy - 1.0, sz - 1.0),
+                        );
+                    }
+                    let near = lerp(v, l1, l2);
+                    let far = lerp(v, l3, l4);
+                    out[index] += lerp(w, near, far) * inverse;
+                    index += 1;
+                }
+            }
+        }
+    }
+}
+fn shuffled_permutations(random: &mut JavaRandom) -> [i32; 512] {
+    let mut permutations = [0; 512];
+    for (i, p) in permutations.iter_mut().take(256).enumerate() {
+        *p = i as i32;
+    }
+    for i in 0..256 {
+        let j = random.next_int_bounded(256 - i as i32) as usize + i;
+        permutations.swap(i, j);
+        permutations[i + 256] = permutations[i];
+    }
+    permutations
+}
+/// Several Perlin octaves, each half the frequency and twice the amplitude of the last,
+/// `NoiseGeneratorOctaves`.
+pub struct OctaveNoise {
+    octaves: Vec<PerlinNoise>,
+}
+impl OctaveNoise {
+    pub fn new(random: &mut JavaRandom, count: usize) -> Self {
+        Self {
+            octaves: (0..count).map(|_| PerlinNoise::new(random)).collect(),
+        }
+    }
+    /// A single 2D sample.
+    pub fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut frequency = 1.0;
+        for octave in &self.octaves {
+            total += octave.noise(x * frequency, z * frequency, 0.0) / frequency;
+            frequency /= 2.0;
+        }
+        total
+    }
+    /// Fills `out` with a grid of samples, see `PerlinNoise::add_grid` for the layout.
+    pub fn grid(
+        &self,
+        out: &mut Vec<f64>,
+        origin: (f64, f64, f64),
+        size: (usize, usize, usize),
+        scale: (f64, f64, f64),
+    ) {
+        out.clear();
+        out.resize(size.0 * size.1 * size.2, 0.0);
+        let mut frequency = 1.0;
+        for octave in &self.octaves {
+            octave.add_grid(
+                out,
+                origin,
+                size,
+                (scale.0 * frequency, scale.1 * frequency, scale.2 * frequency),
+                frequency,
+            );
+            frequency /= 2.0;
+        }
+    }
+    /// A 2D grid, the generator's `func_4109_a`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn grid_2d(&self, out: &mut Vec<f64>, x: i32, z: i32, size_x: usize, size_z: usize, scale_x: f64, scale_z: f64) {
+        self.grid(
+            out,
+            (x as f64, 10.0, z as f64),
+            (size_x, 1, size_z),
+            (scale_x, 1.0, scale_z),
+        );
+    }
+}
+const SIMPLEX_GRADIENTS: [[i32; 3]; 12] = [
+    [1, 1, 0],
+    [-1, 1, 0],
+    [1, -1, 0],
+    [-1, -1, 0],
+    [1, 0, 1],
+    [-1, 0, 1],
+    [1, 0, -1],
+    [-1, 0, -1],
+    [0, 1, 1],
+    [0, -1, 1],
+    [0, 1, -1],
+    [0, -1, -1],
+];
+/// 2D simplex noise, `NoiseGenerator2`, which drives the biome climate.
+pub struct SimplexNoise {
+    permutations: [i32; 512],
+    x_offset: f64,
+    y_offset: f64,
+}
+impl SimplexNoise {
+    pub fn new(random: &mut JavaRandom) -> Self {
+        let x_offset = random.next_double() * 256.0;
+        let y_offset = random.next_double() * 256.0;
+        // Vanilla draws a z offset too, it just never uses it.
+        let _z_offset = random.next_double() * 256.0;
+        Self {
+            permutations: shuffled_permutations(random),
+            x_offset,
+            y_offset,
+        }
+    }
+    fn p(&self, index: i32) -> i32 {
+        self.permutations[index as usize]
+    }
+    fn wrap(value: f64) -> i32 {
+        if value > 0.0 {
+            value as i32
+        } else {
+            value as i32 - 1
+        }
+    }
+    fn corner(gradient: usize, x: f64, y: f64) -> f64 {
+        let t = 0.5 - x * x - y * y;
+        if t < 0.0 {
+            0.0
+        } else {
+            let t = t * t;
+            let g = SIMPLEX_GRADIENTS[gradient];
+            t * t * (g[0] as f64 * x + g[1] as f64 * y)
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_grid(
+        &self,
+        out: &mut [f64],
+        x: f64,
+        y: f64,
+        size_x: usize,
+        size_y: usize,
+        scale_x: f64,
+        scale_y: f64,
+        amplitude: f64,
+    ) {
+        let skew = 0.5 * (3f64.sqrt() - 1.0);
+        let unskew = (3.0 - 3f64.sqrt()) / 6.0;
+        let mut index = 0;
+        for ix in 0..size_x {
+            let sx = (x + ix as f64) * scale_x + self.x_offset;
+            for iy in 0..size_y {
+                let sy = (y + iy as f64) * scale_y + self.y_offset;
+                let s = (sx + sy) * skew;
+                let i = Self::wrap(sx + s);
+                let j = Self::wrap(sy + s);
+                let t = (i + j) as f64 * unskew;
+                let x0 = sx - (i as f64 - t);
+                let y0 = sy - (j as f64 - t);
+                let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+                let x1 = (x0 - i1 as f64) + unskew;
+                let y1 = (y0 - j1 as f64) + unskew;
+                let x2 = (x0 - 1.0) + 2.0 * unskew;
+                let y2 = (y0 - 1.0) + 2.0 * unskew;
+                let ii = i & 255;
+                let jj = j & 255;
+                let g0 = self.p(ii + self.p(jj)) % 12;
+                let g1 = self.p(ii + i1 + self.p(jj + j1)) % 12;
+                let g2 = self.p(ii + 1 + self.p(jj + 1)) % 12;
+                let n0 = Self::corner(g0 as usize, x0, y0);
+                let n1 = Self::corner(g1 as usize, x1, y1);
+                let n2 = Self::corner(g2 as usize, x2, y2);
+                out[index] += 70.0 * (n0 + n1 + n2) * amplitude;
+                index += 1;
+            }
+        }
+    }
+}
+/// Simplex octaves, `NoiseGeneratorOctaves2`.
+pub struct OctaveSimplexNoise {
+    octaves: Vec<SimplexNoise>,
+}
+impl OctaveSimplexNoise {
+    pub fn new(random: &mut JavaRandom, count: usize) -> Self {
+        Self {
+            octaves: (0..count).map(|_| SimplexNoise::new(random)).collect(),
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn grid(
+        &self,
+        out: &mut Vec<f64>,
+        x: f64,
+        z: f64,
+        size_x: usize,
+        size_z: usize,
+        scale_x: f64,
+        scale_z: f64,
+        frequency_step: f64,
+    ) {
+        let scale_x = scale_x / 1.5;
+        let scale_z = scale_z / 1.5;
+        out.clear();
+        out.resize(size_x * size_z, 0.0);
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for octave in &self.octaves {
+            octave.add_grid(
+                out,
+                x,
+                z,
+                size_x,
+                size_z,
+                scale_x * frequency,
+                scale_z * frequency,
+                0.55 / amplitude,
+            );
+            frequency *= frequency_step;
+            amplitude *= 0.5;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn java_random_matches_the_jdk() {
+        // Values printed by java.util.Random.
+        assert_eq!(JavaRandom::new(0).next_int(), -1155484576);
+        assert_eq!(JavaRandom::new(42).next_int(), -1170105035);
+        let mut random = JavaRandom::new(12345);
+        assert_eq!(random.next_int_bounded(100), 51);
+        assert_eq!(random.next_long(), -8979699159253079774);
+    }
+}
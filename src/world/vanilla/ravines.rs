@@ -0,0 +1,150 @@
+//! Ravines: tall, narrow cuts through the terrain.
+//!
+//! Beta 1.7.3 has none of these. The shape follows the `MapGenRavine` later vanilla releases
+//! added, squeezed into 128 blocks of height, and each ravine is replayed from the seed of the
+//! chunk it starts in the same way caves are.
+use super::caves::touches_water;
+use super::ids::*;
+use super::noise::{math, JavaRandom};
+/// How many chunks away a ravine can start and still reach this chunk.
+const RANGE: i32 = 8;
+/// Carves the ravines crossing chunk `chunk_x`, `chunk_z` out of its blocks, indexed `x << 11 | z << 7 | y`.
+pub fn carve(seed: i64, chunk_x: i32, chunk_z: i32, blocks: &mut [u8]) {
+    let mut random = JavaRandom::new(seed);
+    let x_seed = random.next_long() / 2 * 2 + 1;
+    let z_seed = random.next_long() / 2 * 2 + 1;
+    for x in chunk_x - RANGE..=chunk_x + RANGE {
+        for z in chunk_z - RANGE..=chunk_z + RANGE {
+            let start_seed = (x as i64).wrapping_mul(x_seed).wrapping_add((z as i64).wrapping_mul(z_seed)) ^ seed;
+            random.set_seed(start_seed);
+            if random.next_int_bounded(50) != 0 {
+                continue;
+            }
+            let start_x = (x * 16 + random.next_int_bounded(16)) as f64;
+            let bound = random.next_int_bounded(40) + 8;
+            let start_y = (random.next_int_bounded(bound) + 20) as f64;
+            let start_z = (z * 16 + random.next_int_bounded(16)) as f64;
+            let yaw = random.next_float() * math::PI * 2.0;
+            let pitch = ((random.next_float() - 0.5) * 2.0) / 8.0;
+            let width = (random.next_float() * 2.0 + random.next_float()) * 2.0;
+            let mut ravine = Ravine {
+                random: JavaRandom::new(random.next_long()),
+                chunk_x,
+                chunk_z,
+                blocks,
+                x: start_x,
+                y: start_y,
+                z: start_z,
+            };
+            ravine.walk(width, yaw, pitch);
+        }
+    }
+}
+struct Ravine<'a> {
+    random: JavaRandom,
+    chunk_x: i32,
+    chunk_z: i32,
+    blocks: &'a mut [u8],
+    x: f64,
+    y: f64,
+    z: f64,
+}
+impl<'a> Ravine<'a> {
+    fn walk(&mut self, width: f32, mut yaw: f32, mut pitch: f32) {
+        let center_x = (self.chunk_x * 16 + 8) as f64;
+        let center_z = (self.chunk_z * 16 + 8) as f64;
+        let mut yaw_change = 0.0f32;
+        let mut pitch_change = 0.0f32;
+        let max = RANGE * 16 - 16;
+        let length = max - self.random.next_int_bounded(max / 4);
+        // How far each layer is pushed in, which gives the walls their ledges.
+        let mut ledges = [0.0f64; 128];
+        let mut ledge = 1.0f32;
+        for (y, scale) in ledges.iter_mut().enumerate() {
+            if y == 0 || self.random.next_int_bounded(3) == 0 {
+                ledge = 1.0 + self.random.next_float() * self.random.next_float();
+            }
+            *scale = (ledge * ledge) as f64;
+        }
+        for step in 0..length {
+            let radius = 1.5 + (math::sin((step as f32 * math::PI) / length as f32) * width) as f64;
+            let radius_y = radius * 3.0;
+            let radius = radius * (self.random.next_float() as f64 * 0.25 + 0.75);
+            let radius_y = radius_y * (self.random.next_float() as f64 * 0.25 + 0.75);
+            let horizontal = math::cos(pitch);
+            self.x += (math::cos(yaw) * horizontal) as f64;
+            self.y += math::sin(pitch) as f64;
+            self.z += (math::sin(yaw) * horizontal) as f64;
+            pitch *= 0.7;
+            pitch += pitch_change * 0.05;
+            yaw += yaw_change * 0.05;
+            pitch_change *= 0.8;
+            yaw_change *= 0.5;
+            pitch_change += (self.random.next_float() - self.random.next_float()) * self.random.next_float() * 2.0;
+            yaw_change += (self.random.next_float() - self.random.next_float()) * self.random.next_float() * 4.0;
+            if self.random.next_int_bounded(4) == 0 {
+                continue;
+            }
+            let dx = self.x - center_x;
+            let dz = self.z - center_z;
+            let remaining = (length - step) as f64;
+            let reach = (width + 2.0 + 16.0) as f64;
+            if dx * dx + dz * dz - remaining * remaining > reach * reach {
+                return;
+            }
+            if self.x < center_x - 16.0 - radius * 2.0
+                || self.z < center_z - 16.0 - radius * 2.0
+                || self.x > center_x + 16.0 + radius * 2.0
+                || self.z > center_z + 16.0 + radius * 2.0
+            {
+                continue;
+            }
+            self.carve_step(radius, radius_y, &ledges);
+        }
+    }
+    fn carve_step(&mut self, radius: f64, radius_y: f64, ledges: &[f64; 128]) {
+        let min_x = (math::floor(self.x - radius) - self.chunk_x * 16 - 1).max(0);
+        let max_x = (math::floor(self.x + radius) - self.chunk_x * 16 + 1).min(16);
+        let min_y = (math::floor(self.y - radius_y) - 1).max(1);
+        let max_y = (math::floor(self.y + radius_y) + 1).min(120);
+        let min_z = (math::floor(self.z - radius) - self.chunk_z * 16 - 1).max(0);
+        let max_z = (math::floor(self.z + radius) - self.chunk_z * 16 + 1).min(16);
+        if touches_water(self.blocks, (min_x, max_x), (min_y, max_y), (min_z, max_z)) {
+            return;
+        }
+        for x in min_x..max_x {
+            let px = ((x + self.chunk_x * 16) as f64 + 0.5 - self.x) / radius;
+            for z in min_z..max_z {
+                let pz = ((z + self.chunk_z * 16) as f64 + 0.5 - self.z) / radius;
+                let horizontal = px * px + pz * pz;
+                if horizontal >= 1.0 {
+                    continue;
+                }
+                let column = ((x * 16 + z) * 128) as usize;
+                let mut hit_grass = false;
+                // Like caves, each block is tested against the ellipse of the layer below it.
+                for y in (min_y..max_y).rev() {
+                    let py = (y as f64 + 0.5 - self.y) / radius_y;
+                    if horizontal * ledges[y as usize] + py * py / 6.0 >= 1.0 {
+                        continue;
+                    }
+                    let index = column + y as usize + 1;
+                    let block_type = self.blocks[index];
+                    if block_type == GRASS {
+                        hit_grass = true;
+                    }
+                    if block_type == STONE || block_type == DIRT || block_type == GRASS {
+                        if y < 10 {
+                            self.blocks[index] = LAVA;
+                        } else {
+                            self.blocks[index] = AIR;
+                            if hit_grass && self.blocks[index - 1] == DIRT {
+                                self.blocks[index - 1] = GRASS;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}